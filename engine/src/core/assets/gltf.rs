@@ -0,0 +1,804 @@
+//! glTF 2.0 import, from `.gltf` files with their buffers and images or from
+//! `.glb` files. Only local files are read: external URIs must be relative
+//! paths or `file:`, and anything else is rejected.
+
+use std::{path::Path, sync::Arc};
+
+use ::gltf::{animation::util::ReadOutputs, buffer, image::Format, mesh::Mode};
+use cgmath::{Matrix4, Quaternion, SquareMatrix, Vector3};
+use image::RgbaImage;
+use log::warn;
+
+use super::{
+    AssetError,
+    material::{AlphaMode, MaterialDesc, TextureSource},
+};
+use crate::core::{
+    ecs::{
+        animation::{AnimationChannel, AnimationClip, Animations, Interpolation, Keyframes},
+        components::{Children, JointWeights, MeshInstance, Name, Parent, Skin, Transform},
+        world::{EntityId, World},
+    },
+    render::{
+        ctx::WgpuCtx,
+        mesh::{MeshData, MeshId, Vertex},
+    },
+};
+
+/// A part of a mesh drawn with a single material.
+pub struct Primitive {
+    pub data: MeshData,
+    pub material: Option<usize>,
+    /// The joints that move each vertex of a skinned mesh, and how much,
+    /// spawned as [`JointWeights`]. Empty for meshes that aren't skinned.
+    pub joints: Vec<[u16; 4]>,
+    pub weights: Vec<[f32; 4]>,
+}
+
+pub struct Mesh {
+    pub name: String,
+    pub primitives: Vec<Primitive>,
+}
+
+pub struct Node {
+    pub name: String,
+    pub transform: Transform,
+    pub mesh: Option<usize>,
+    pub skin: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+pub struct SkinData {
+    pub name: String,
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Matrix4<f32>>,
+}
+
+/// Everything imported from a glTF file. Like in the file, nodes, meshes,
+/// materials and skins refer to each other by index.
+pub struct GltfAsset {
+    pub name: String,
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<MaterialDesc>,
+    pub nodes: Vec<Node>,
+    /// The top-level nodes of the default scene.
+    pub roots: Vec<usize>,
+    pub skins: Vec<SkinData>,
+    /// Clips animating nodes, which they refer to by index.
+    pub animations: Vec<AnimationClip<usize>>,
+}
+
+pub fn load(path: &Path) -> Result<GltfAsset, AssetError> {
+    let gltf_error = |source| AssetError::Gltf {
+        path: path.to_path_buf(),
+        source,
+    };
+    let ::gltf::Gltf { document, blob } = ::gltf::Gltf::open(path).map_err(gltf_error)?;
+    let base = path.parent();
+    let buffers = ::gltf::import_buffers(&document, base, blob).map_err(gltf_error)?;
+
+    let images = document
+        .images()
+        .map(|image| image_source(image, base, &buffers))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(gltf_error)?;
+    let textures: Vec<TextureSource> = document
+        .textures()
+        .map(|texture| images[texture.source().index()].clone())
+        .collect();
+    let materials = document
+        .materials()
+        .map(|material| material_desc(&material, &textures))
+        .collect();
+    let meshes = document
+        .meshes()
+        .map(|mesh| import_mesh(&mesh, &buffers, path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let nodes = document
+        .nodes()
+        .map(|node| {
+            let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+            Node {
+                name: node
+                    .name()
+                    .map_or_else(|| format!("node{}", node.index()), str::to_string),
+                transform: Transform {
+                    translation: translation.into(),
+                    rotation: Quaternion::new(w, x, y, z),
+                    scale: scale.into(),
+                },
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                skin: node.skin().map(|skin| skin.index()),
+                children: node.children().map(|child| child.index()).collect(),
+            }
+        })
+        .collect();
+    let roots = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .map(|scene| scene.nodes().map(|node| node.index()).collect())
+        .unwrap_or_default();
+
+    let skins = document
+        .skins()
+        .map(|skin| {
+            let joints: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+            let inverse_bind_matrices = skin
+                .reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]))
+                .read_inverse_bind_matrices()
+                .map(|matrices| matrices.map(Matrix4::from).collect())
+                .unwrap_or_else(|| vec![Matrix4::identity(); joints.len()]);
+            SkinData {
+                name: skin.name().unwrap_or_default().to_string(),
+                joints,
+                inverse_bind_matrices,
+            }
+        })
+        .collect();
+    let animations = document
+        .animations()
+        .map(|animation| import_animation(&animation, &buffers, path))
+        .collect();
+
+    Ok(GltfAsset {
+        name: path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        meshes,
+        materials,
+        nodes,
+        roots,
+        skins,
+        animations,
+    })
+}
+
+/// Images in their own files are left for the texture loader, the rest are
+/// decoded now since there's no path to load them from later.
+fn image_source(
+    image: ::gltf::Image<'_>,
+    base: Option<&Path>,
+    buffers: &[buffer::Data],
+) -> Result<TextureSource, ::gltf::Error> {
+    if let (::gltf::image::Source::Uri { uri, .. }, Some(base)) = (image.source(), base) {
+        // Schemes and percent-encoding are handled by the glTF crate
+        if !uri.contains([':', '%']) {
+            return Ok(TextureSource::Path(base.join(uri)));
+        }
+    }
+    let data = ::gltf::image::Data::from_source(image.source(), base, buffers)?;
+    Ok(TextureSource::Embedded(Arc::new(to_rgba(&data))))
+}
+
+fn to_rgba(data: &::gltf::image::Data) -> RgbaImage {
+    let (channels, channel_bytes) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    let sample = |bytes: &[u8]| match bytes {
+        [value] => *value,
+        [_, high] => *high,
+        [a, b, c, d] => {
+            (f32::from_le_bytes([*a, *b, *c, *d]).clamp(0.0, 1.0) * 255.0).round() as u8
+        }
+        _ => unreachable!(),
+    };
+
+    let mut pixels = Vec::with_capacity((data.width * data.height * 4) as usize);
+    for pixel in data.pixels.chunks_exact(channels * channel_bytes) {
+        let values: Vec<u8> = pixel.chunks_exact(channel_bytes).map(sample).collect();
+        pixels.extend_from_slice(&match values[..] {
+            // One and two channel images are greyscale, with alpha for two
+            [luma] => [luma, luma, luma, 255],
+            [luma, alpha] => [luma, luma, luma, alpha],
+            [r, g, b] => [r, g, b, 255],
+            [r, g, b, a] => [r, g, b, a],
+            _ => unreachable!(),
+        });
+    }
+    RgbaImage::from_raw(data.width, data.height, pixels).expect("Pixel data matches the image size")
+}
+
+fn material_desc(material: &::gltf::Material<'_>, textures: &[TextureSource]) -> MaterialDesc {
+    let pbr = material.pbr_metallic_roughness();
+    let texture = |index: usize| textures.get(index).cloned();
+    MaterialDesc {
+        name: material.name().unwrap_or_default().to_string(),
+        base_color: pbr.base_color_factor(),
+        base_color_texture: pbr
+            .base_color_texture()
+            .and_then(|info| texture(info.texture().index())),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        emissive: material.emissive_factor(),
+        normal_texture: material
+            .normal_texture()
+            .and_then(|info| texture(info.texture().index())),
+        alpha_mode: match material.alpha_mode() {
+            ::gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            ::gltf::material::AlphaMode::Mask => AlphaMode::Mask {
+                cutoff: material.alpha_cutoff().unwrap_or(0.5),
+            },
+            ::gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        double_sided: material.double_sided(),
+    }
+}
+
+fn import_mesh(
+    mesh: &::gltf::Mesh<'_>,
+    buffers: &[buffer::Data],
+    path: &Path,
+) -> Result<Mesh, AssetError> {
+    let name = mesh
+        .name()
+        .map_or_else(|| format!("mesh{}", mesh.index()), str::to_string);
+    let invalid = |message: String| AssetError::Invalid {
+        path: path.to_path_buf(),
+        message: format!("mesh '{name}': {message}"),
+    };
+
+    let mut primitives = Vec::new();
+    for primitive in mesh.primitives() {
+        if primitive.mode() != Mode::Triangles {
+            warn!(
+                "{}: skipping a primitive of mesh '{name}' drawn as {:?}, only triangles are supported",
+                path.display(),
+                primitive.mode()
+            );
+            continue;
+        }
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+        let positions: Vec<[f32; 3]> = reader
+            .read_positions()
+            .ok_or_else(|| invalid("primitive has no positions".into()))?
+            .collect();
+        let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(Iterator::collect);
+        let mut uvs: Vec<[f32; 2]> = reader
+            .read_tex_coords(0)
+            .map(|uvs| uvs.into_f32().collect())
+            .unwrap_or_default();
+        if uvs.len() != positions.len() {
+            uvs = vec![[0.0; 2]; positions.len()];
+        }
+        let mut joints: Vec<[u16; 4]> = reader
+            .read_joints(0)
+            .map(|joints| joints.into_u16().collect())
+            .unwrap_or_default();
+        let mut weights: Vec<[f32; 4]> = reader
+            .read_weights(0)
+            .map(|weights| weights.into_f32().collect())
+            .unwrap_or_default();
+        if joints.len() != positions.len() || weights.len() != positions.len() {
+            joints.clear();
+            weights.clear();
+        }
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        if let Some(index) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+            return Err(invalid(format!("vertex index {index} is out of range")));
+        }
+
+        let data = match normals {
+            Some(normals) if normals.len() == positions.len() => MeshData {
+                vertices: (positions.iter().zip(&normals).zip(&uvs))
+                    .map(|((&position, &normal), &uv)| Vertex {
+                        position,
+                        normal,
+                        uv,
+                    })
+                    .collect(),
+                indices,
+            },
+            Some(_) => return Err(invalid("normal and position counts differ".into())),
+            // Meshes without normals are meant to be flat shaded, so every
+            // triangle gets vertices of its own
+            None => {
+                joints = unweld(&joints, &indices);
+                weights = unweld(&weights, &indices);
+                let mut data = MeshData {
+                    vertices: indices
+                        .iter()
+                        .map(|&i| Vertex {
+                            position: positions[i as usize],
+                            normal: [0.0; 3],
+                            uv: uvs[i as usize],
+                        })
+                        .collect(),
+                    indices: (0..indices.len() as u32).collect(),
+                };
+                data.compute_normals();
+                data
+            }
+        };
+        primitives.push(Primitive {
+            data,
+            material: primitive.material().index(),
+            joints,
+            weights,
+        });
+    }
+    Ok(Mesh { name, primitives })
+}
+
+/// Gives each index its own copy of the value it refers to.
+fn unweld<T: Copy>(values: &[T], indices: &[u32]) -> Vec<T> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    indices.iter().map(|&i| values[i as usize]).collect()
+}
+
+fn import_animation(
+    animation: &::gltf::Animation<'_>,
+    buffers: &[buffer::Data],
+    path: &Path,
+) -> AnimationClip<usize> {
+    let name = animation
+        .name()
+        .map_or_else(|| format!("animation{}", animation.index()), str::to_string);
+    let mut channels = Vec::new();
+    for channel in animation.channels() {
+        let reader = channel.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+        let (Some(times), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+            warn!(
+                "{}: skipping a channel of animation '{name}' without keyframes",
+                path.display()
+            );
+            continue;
+        };
+        let keyframes = match outputs {
+            ReadOutputs::Translations(values) => {
+                Keyframes::Translation(values.map(Vector3::from).collect())
+            }
+            ReadOutputs::Rotations(values) => Keyframes::Rotation(
+                values
+                    .into_f32()
+                    .map(|[x, y, z, w]| Quaternion::new(w, x, y, z))
+                    .collect(),
+            ),
+            ReadOutputs::Scales(values) => Keyframes::Scale(values.map(Vector3::from).collect()),
+            ReadOutputs::MorphTargetWeights(values) => {
+                Keyframes::Weights(values.into_f32().collect())
+            }
+        };
+        channels.push(AnimationChannel {
+            target: channel.target().node().index(),
+            interpolation: match channel.sampler().interpolation() {
+                ::gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                ::gltf::animation::Interpolation::Step => Interpolation::Step,
+                ::gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+            },
+            times: times.collect(),
+            keyframes,
+        });
+    }
+    AnimationClip { name, channels }
+}
+
+impl GltfAsset {
+    /// Creates entities for the default scene under a new root entity named
+    /// after the asset, and uploads the meshes they draw. Returns the root.
+    pub fn spawn(&self, world: &mut World, ctx: &mut WgpuCtx<'_>) -> EntityId {
+        let meshes: Vec<Vec<MeshId>> = self
+            .meshes
+            .iter()
+            .map(|mesh| {
+                (mesh.primitives.iter().enumerate())
+                    .map(|(i, primitive)| {
+                        ctx.add_mesh(&format!("{}/{}/{i}", self.name, mesh.name), &primitive.data)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let root = world.spawn();
+        world.insert(root, Name(self.name.clone()));
+        world.insert(root, Transform::default());
+        let mut entities = vec![None; self.nodes.len()];
+        let children = (self.roots.iter())
+            .filter_map(|&node| self.spawn_node(node, root, world, &meshes, &mut entities))
+            .collect();
+        world.insert(root, Children(children));
+
+        // Skins and animations can refer to nodes anywhere in the scene, so
+        // they're added once every node has an entity
+        for (node, entity) in self.nodes.iter().zip(&entities) {
+            let (Some(skin), Some(entity)) = (node.skin.and_then(|i| self.skins.get(i)), entity)
+            else {
+                continue;
+            };
+            world.insert(
+                *entity,
+                Skin {
+                    joints: (skin.joints.iter())
+                        .map(|&joint| entities.get(joint).copied().flatten())
+                        .collect(),
+                    inverse_bind_matrices: skin.inverse_bind_matrices.clone(),
+                },
+            );
+        }
+        let clips: Vec<AnimationClip> = (self.animations.iter())
+            .map(|clip| clip.filter_map_targets(|&node| entities.get(node).copied().flatten()))
+            .collect();
+        if !clips.is_empty() {
+            world.insert(root, Animations(clips));
+        }
+        root
+    }
+
+    fn spawn_node(
+        &self,
+        node: usize,
+        parent: EntityId,
+        world: &mut World,
+        meshes: &[Vec<MeshId>],
+        entities: &mut [Option<EntityId>],
+    ) -> Option<EntityId> {
+        // Guards against files where nodes are their own ancestors
+        if entities.get(node)?.is_some() {
+            return None;
+        }
+        let data = &self.nodes[node];
+        let entity = world.spawn();
+        entities[node] = Some(entity);
+        world.insert(entity, Name(data.name.clone()));
+        world.insert(entity, data.transform);
+        world.insert(entity, Parent(parent));
+
+        let mut children = Vec::new();
+        if let Some(mesh) = data.mesh.and_then(|i| self.meshes.get(i)) {
+            let instances = mesh.primitives.iter().zip(&meshes[data.mesh?]);
+            for (primitive, &mesh_id) in instances {
+                let instance = MeshInstance {
+                    mesh: mesh_id,
+                    material: primitive
+                        .material
+                        .and_then(|i| self.materials.get(i))
+                        .cloned(),
+                };
+                // An entity draws a single mesh, so primitives after the
+                // first get entities of their own
+                let target = if world.get::<MeshInstance>(entity).is_none() {
+                    entity
+                } else {
+                    let part = world.spawn();
+                    world.insert(part, Name(format!("{}/{}", data.name, children.len())));
+                    world.insert(part, Transform::default());
+                    world.insert(part, Parent(entity));
+                    children.push(part);
+                    part
+                };
+                world.insert(target, instance);
+                if !primitive.joints.is_empty() {
+                    world.insert(
+                        target,
+                        JointWeights {
+                            joints: primitive.joints.clone(),
+                            weights: primitive.weights.clone(),
+                        },
+                    );
+                }
+            }
+        }
+        children.extend(
+            (data.children.iter())
+                .filter_map(|&child| self.spawn_node(child, entity, world, meshes, entities)),
+        );
+        if !children.is_empty() {
+            world.insert(entity, Children(children));
+        }
+        Some(entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+    use crate::core::{assets::material::AlphaMode, ecs::animation::Animations};
+
+    /// A skinned triangle without normals under a root node, a node drawing
+    /// a mesh of two primitives, a node outside the scene, and a clip moving
+    /// the second node and the one outside the scene.
+    const FIXTURE: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [
+            { "name": "root", "translation": [1, 0, 0], "children": [1] },
+            { "name": "body", "mesh": 0, "skin": 0, "children": [2] },
+            { "name": "bone", "mesh": 1 },
+            { "name": "orphan" }
+        ],
+        "meshes": [
+            {
+                "name": "tri",
+                "primitives": [{
+                    "attributes": { "POSITION": 0, "JOINTS_0": 3, "WEIGHTS_0": 4 },
+                    "indices": 2,
+                    "material": 0
+                }]
+            },
+            {
+                "name": "parts",
+                "primitives": [
+                    { "attributes": { "POSITION": 0, "NORMAL": 1 }, "indices": 2, "material": 0 },
+                    { "attributes": { "POSITION": 0, "NORMAL": 1 }, "material": 1 }
+                ]
+            }
+        ],
+        "materials": [
+            {
+                "name": "red",
+                "pbrMetallicRoughness": {
+                    "baseColorFactor": [1, 0, 0, 1],
+                    "metallicFactor": 0.25,
+                    "roughnessFactor": 0.5
+                },
+                "alphaMode": "MASK",
+                "alphaCutoff": 0.25,
+                "doubleSided": true
+            },
+            { "name": "glass", "alphaMode": "BLEND", "emissiveFactor": [0, 0, 1] }
+        ],
+        "skins": [{ "name": "rig", "joints": [3, 2] }],
+        "animations": [{
+            "name": "bob",
+            "samplers": [{ "input": 5, "output": 6, "interpolation": "STEP" }],
+            "channels": [
+                { "sampler": 0, "target": { "node": 2, "path": "translation" } },
+                { "sampler": 0, "target": { "node": 3, "path": "translation" } }
+            ]
+        }],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+              "min": [0, 0, 0], "max": [1, 1, 0] },
+            { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3" },
+            { "bufferView": 2, "componentType": 5123, "count": 6, "type": "SCALAR" },
+            { "bufferView": 3, "componentType": 5123, "count": 3, "type": "VEC4" },
+            { "bufferView": 4, "componentType": 5126, "count": 3, "type": "VEC4" },
+            { "bufferView": 5, "componentType": 5126, "count": 2, "type": "SCALAR",
+              "min": [0], "max": [1] },
+            { "bufferView": 6, "componentType": 5126, "count": 2, "type": "VEC3" }
+        ],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 72, "byteLength": 12 },
+            { "buffer": 0, "byteOffset": 84, "byteLength": 24 },
+            { "buffer": 0, "byteOffset": 108, "byteLength": 48 },
+            { "buffer": 0, "byteOffset": 156, "byteLength": 8 },
+            { "buffer": 0, "byteOffset": 164, "byteLength": 24 }
+        ],
+        "buffers": [{ "uri": "fixture.bin", "byteLength": 188 }]
+    }"#;
+
+    const JOINTS: [[u16; 4]; 3] = [[0, 1, 0, 0], [1, 0, 0, 0], [0, 0, 0, 0]];
+    const WEIGHTS: [[f32; 4]; 3] = [[0.5, 0.5, 0.0, 0.0], [1.0; 4], [0.0; 4]];
+
+    /// The buffer the fixture's accessors read, in the order of its views.
+    fn fixture_buffer() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut floats = |values: &[f32]| {
+            for value in values {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        };
+        floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        floats(&[0.0, 0.0, 1.0].repeat(3));
+        // Both sides of the triangle
+        for index in [0u16, 1, 2, 2, 1, 0] {
+            bytes.extend_from_slice(&index.to_le_bytes());
+        }
+        for index in JOINTS.as_flattened() {
+            bytes.extend_from_slice(&index.to_le_bytes());
+        }
+        for weight in WEIGHTS.as_flattened() {
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+        for value in [0.0f32, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Writes the fixture into a directory of its own for the test `name`.
+    fn write_fixture(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("zenyx-gltf-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let buffer = fixture_buffer();
+        assert_eq!(buffer.len(), 188);
+        fs::write(dir.join("fixture.bin"), buffer).unwrap();
+        let path = dir.join("fixture.gltf");
+        fs::write(&path, FIXTURE).unwrap();
+        path
+    }
+
+    fn load_fixture(name: &str) -> GltfAsset {
+        let path = write_fixture(name);
+        let asset = load(&path).unwrap();
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        asset
+    }
+
+    #[test]
+    fn materials() {
+        let asset = load_fixture("materials");
+        let [red, glass] = asset.materials.as_slice() else {
+            panic!("expected two materials");
+        };
+        assert_eq!(red.name, "red");
+        assert_eq!(red.base_color, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!((red.metallic, red.roughness), (0.25, 0.5));
+        assert_eq!(red.alpha_mode, AlphaMode::Mask { cutoff: 0.25 });
+        assert!(red.double_sided);
+        assert_eq!(glass.alpha_mode, AlphaMode::Blend);
+        assert_eq!(glass.emissive, [0.0, 0.0, 1.0]);
+        assert!(!glass.double_sided);
+    }
+
+    #[test]
+    fn meshes_without_normals_get_a_vertex_per_corner() {
+        let asset = load_fixture("unweld");
+        let [primitive] = asset.meshes[0].primitives.as_slice() else {
+            panic!("expected one primitive");
+        };
+        assert_eq!(primitive.material, Some(0));
+        let data = &primitive.data;
+        assert_eq!(data.vertices.len(), 6);
+        assert_eq!(data.indices, [0, 1, 2, 3, 4, 5]);
+        // Flat normals facing each side of the triangle
+        for vertex in &data.vertices[..3] {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+        }
+        for vertex in &data.vertices[3..] {
+            assert_eq!(vertex.normal, [0.0, 0.0, -1.0]);
+        }
+        let corners = [0, 1, 2, 2, 1, 0];
+        assert_eq!(primitive.joints, corners.map(|i| JOINTS[i]));
+        assert_eq!(primitive.weights, corners.map(|i| WEIGHTS[i]));
+    }
+
+    #[test]
+    fn meshes_with_normals_share_vertices() {
+        let asset = load_fixture("welded");
+        let [indexed, unindexed] = asset.meshes[1].primitives.as_slice() else {
+            panic!("expected two primitives");
+        };
+        assert_eq!(indexed.data.vertices.len(), 3);
+        assert_eq!(indexed.data.indices, [0, 1, 2, 2, 1, 0]);
+        assert_eq!(unindexed.data.indices, [0, 1, 2]);
+        assert_eq!(unindexed.material, Some(1));
+        assert!(indexed.joints.is_empty() && indexed.weights.is_empty());
+    }
+
+    #[test]
+    fn nodes_skins_and_animations_refer_to_nodes_by_index() {
+        let asset = load_fixture("indices");
+        assert_eq!(asset.name, "fixture");
+        assert_eq!(asset.roots, [0]);
+        let names: Vec<_> = asset.nodes.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(names, ["root", "body", "bone", "orphan"]);
+        assert_eq!(
+            asset.nodes[0].transform.translation,
+            Vector3::new(1.0, 0.0, 0.0)
+        );
+        assert_eq!(asset.nodes[1].children, [2]);
+        assert_eq!(
+            (asset.nodes[1].mesh, asset.nodes[1].skin),
+            (Some(0), Some(0))
+        );
+
+        assert_eq!(asset.skins[0].joints, [3, 2]);
+        assert_eq!(
+            asset.skins[0].inverse_bind_matrices,
+            [Matrix4::identity(); 2]
+        );
+
+        let clip = &asset.animations[0];
+        assert_eq!(clip.name, "bob");
+        assert_eq!(clip.duration(), 1.0);
+        let targets: Vec<_> = clip.channels.iter().map(|channel| channel.target).collect();
+        assert_eq!(targets, [2, 3]);
+        assert_eq!(clip.channels[0].interpolation, Interpolation::Step);
+        assert_eq!(
+            clip.channels[0].keyframes,
+            Keyframes::Translation(vec![
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(0.0, 1.0, 0.0)
+            ])
+        );
+    }
+
+    #[test]
+    fn missing_buffers_are_reported() {
+        let path = write_fixture("missing");
+        fs::remove_file(path.with_file_name("fixture.bin")).unwrap();
+        let result = load(&path);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert!(matches!(result, Err(AssetError::Gltf { .. })));
+    }
+
+    fn named(world: &World, name: &str) -> EntityId {
+        (world.query::<Name>())
+            .find(|(_, Name(n))| n == name)
+            .map(|(entity, _)| entity)
+            .unwrap_or_else(|| panic!("no entity named {name}"))
+    }
+
+    #[test]
+    fn spawn_builds_the_scene_hierarchy() {
+        let mut ctx = match WgpuCtx::new_software_blocking(64, 64) {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                eprintln!("Skipping glTF spawn test: {e}");
+                return;
+            }
+        };
+        let asset = load_fixture("spawn");
+        let mut world = World::new();
+        let root = asset.spawn(&mut world, &mut ctx);
+
+        // The asset's root, the three nodes in the scene and the second
+        // primitive of "parts"
+        assert_eq!(world.len(), 5);
+        assert_eq!(world.get::<Name>(root), Some(&Name("fixture".into())));
+        let [node_root, body, bone] = ["root", "body", "bone"].map(|name| named(&world, name));
+        let part = named(&world, "bone/0");
+        assert_eq!(
+            world.get::<Children>(root),
+            Some(&Children(vec![node_root]))
+        );
+        assert_eq!(world.get::<Parent>(node_root), Some(&Parent(root)));
+        assert_eq!(world.get::<Children>(body), Some(&Children(vec![bone])));
+        assert_eq!(world.get::<Children>(bone), Some(&Children(vec![part])));
+        assert_eq!(world.get::<Parent>(part), Some(&Parent(bone)));
+        assert_eq!(
+            world.get::<Transform>(node_root).unwrap().translation,
+            Vector3::new(1.0, 0.0, 0.0)
+        );
+
+        let material = |entity| {
+            let instance: &MeshInstance = world.get(entity).unwrap();
+            instance.material.as_ref().unwrap().name.clone()
+        };
+        assert_eq!(material(body), "red");
+        assert_eq!(material(bone), "red");
+        assert_eq!(material(part), "glass");
+        assert!(world.get::<MeshInstance>(node_root).is_none());
+        assert!(ctx.meshes().find("fixture/parts/1").is_some());
+
+        let weights: &JointWeights = world.get(body).unwrap();
+        assert_eq!(weights.joints.len(), 6);
+        assert!(world.get::<JointWeights>(bone).is_none());
+
+        // The joint outside the scene keeps its place
+        let skin: &Skin = world.get(body).unwrap();
+        assert_eq!(skin.joints, [None, Some(bone)]);
+        assert_eq!(skin.inverse_bind_matrices.len(), 2);
+
+        // Channels move entities now, and ones for nodes that weren't
+        // spawned are dropped
+        let Animations(clips) = world.get(root).unwrap();
+        let targets: Vec<_> = clips[0]
+            .channels
+            .iter()
+            .map(|channel| channel.target)
+            .collect();
+        assert_eq!(targets, [bone]);
+    }
+}
@@ -0,0 +1,57 @@
+use std::{path::PathBuf, sync::Arc};
+
+use image::RgbaImage;
+
+/// Where a material's texture comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextureSource {
+    /// An image file on disk.
+    Path(PathBuf),
+    /// An image that was stored inside the asset itself.
+    Embedded(Arc<RgbaImage>),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    /// Fragments are either fully opaque or discarded, depending on whether
+    /// their alpha reaches the cutoff.
+    Mask {
+        cutoff: f32,
+    },
+    Blend,
+}
+
+/// A metallic-roughness material as described by an asset, before any of
+/// its textures are loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialDesc {
+    pub name: String,
+    /// Linear RGBA, multiplied with the base colour texture.
+    pub base_color: [f32; 4],
+    pub base_color_texture: Option<TextureSource>,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Linear RGB light given off by the surface.
+    pub emissive: [f32; 3],
+    pub normal_texture: Option<TextureSource>,
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+impl Default for MaterialDesc {
+    fn default() -> Self {
+        MaterialDesc {
+            name: String::new(),
+            base_color: [1.0; 4],
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            emissive: [0.0; 3],
+            normal_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
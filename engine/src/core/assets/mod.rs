@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+pub mod gltf;
+pub mod material;
+pub mod obj;
+
+#[derive(Debug, Error)]
+pub enum AssetError {
+    #[error("Failed to read {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to import glTF {}: {source}", path.display())]
+    Gltf {
+        path: PathBuf,
+        source: ::gltf::Error,
+    },
+    #[error("{}: {message}", path.display())]
+    Invalid { path: PathBuf, message: String },
+}
@@ -0,0 +1,675 @@
+//! Wavefront OBJ import, with materials from the MTL libraries it references.
+//! Polygons are split into triangle fans, so they're expected to be convex.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use log::warn;
+
+use super::{
+    AssetError,
+    material::{AlphaMode, MaterialDesc, TextureSource},
+};
+use crate::core::{
+    ecs::{
+        components::{Children, MeshInstance, Name, Parent, Transform},
+        world::{EntityId, World},
+    },
+    render::{
+        ctx::WgpuCtx,
+        mesh::{MeshData, Vertex},
+    },
+};
+
+/// The faces of an object or group that share a material.
+pub struct ObjMesh {
+    pub name: String,
+    pub data: MeshData,
+    pub material: Option<usize>,
+}
+
+pub struct ObjAsset {
+    pub name: String,
+    pub meshes: Vec<ObjMesh>,
+    pub materials: Vec<MaterialDesc>,
+}
+
+/// Vertices are shared between faces when they have the same key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct VertexKey {
+    position: usize,
+    uv: Option<usize>,
+    normal: NormalSource,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum NormalSource {
+    Given(usize),
+    /// Missing in a smoothing group, so it's averaged from the faces around
+    /// the vertex.
+    Smooth(u32),
+    /// Missing outside any smoothing group, so the vertex belongs to this
+    /// face alone and takes its normal.
+    Flat(usize),
+}
+
+#[derive(Default)]
+struct Attributes {
+    positions: Vec<[f32; 3]>,
+    /// Flipped vertically from the file, where the origin is at the bottom.
+    uvs: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+}
+
+#[derive(Default)]
+struct MeshBuilder {
+    name: String,
+    material: Option<usize>,
+    data: MeshData,
+    /// Which vertices came with a normal in the file.
+    given: Vec<bool>,
+    vertices: HashMap<VertexKey, u32>,
+}
+
+impl MeshBuilder {
+    fn new(name: &str, material: Option<usize>) -> MeshBuilder {
+        MeshBuilder {
+            name: name.to_string(),
+            material,
+            ..MeshBuilder::default()
+        }
+    }
+
+    fn vertex(&mut self, key: VertexKey, attributes: &Attributes) -> u32 {
+        *self.vertices.entry(key).or_insert_with(|| {
+            let normal = match key.normal {
+                NormalSource::Given(normal) => Some(attributes.normals[normal]),
+                NormalSource::Smooth(_) | NormalSource::Flat(_) => None,
+            };
+            self.data.vertices.push(Vertex {
+                position: attributes.positions[key.position],
+                normal: normal.unwrap_or([0.0; 3]),
+                uv: key.uv.map_or([0.0; 2], |uv| attributes.uvs[uv]),
+            });
+            self.given.push(normal.is_some());
+            self.data.vertices.len() as u32 - 1
+        })
+    }
+
+    fn finish(mut self) -> ObjMesh {
+        if self.given.contains(&false) {
+            let given: Vec<[f32; 3]> = self.data.vertices.iter().map(|v| v.normal).collect();
+            self.data.compute_normals();
+            for ((vertex, normal), given) in
+                self.data.vertices.iter_mut().zip(given).zip(self.given)
+            {
+                if given {
+                    vertex.normal = normal;
+                }
+            }
+        }
+        ObjMesh {
+            name: self.name,
+            data: self.data,
+            material: self.material,
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Result<ObjAsset, AssetError> {
+    let source = fs::read_to_string(path).map_err(|source| AssetError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let invalid = |line: usize, message: String| AssetError::Invalid {
+        path: path.to_path_buf(),
+        message: format!("line {line}: {message}"),
+    };
+
+    let mut attributes = Attributes::default();
+    let mut materials: Vec<MaterialDesc> = Vec::new();
+    let mut meshes = Vec::new();
+    let mut object = String::from("default");
+    let mut current = MeshBuilder::new(&object, None);
+    let mut smoothing = None;
+    let mut faces = 0;
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let rest: Vec<&str> = words.collect();
+        match keyword {
+            "v" => attributes
+                .positions
+                .push(floats(&rest).map_err(|e| invalid(number, e))?),
+            "vt" => {
+                // The second coordinate is optional for 1D textures
+                let [u] = floats(&rest).map_err(|e| invalid(number, e))?;
+                let v = floats(rest.get(1..).unwrap_or_default()).map_or(0.0, |[v]| v);
+                attributes.uvs.push([u, 1.0 - v]);
+            }
+            "vn" => attributes
+                .normals
+                .push(floats(&rest).map_err(|e| invalid(number, e))?),
+            "o" | "g" | "usemtl" => {
+                let material = if keyword == "usemtl" {
+                    let name = rest.join(" ");
+                    let material = materials.iter().position(|m| m.name == name);
+                    if material.is_none() {
+                        warn!("{}:{number}: no material named '{name}'", path.display());
+                    }
+                    material
+                } else {
+                    object = rest.join(" ");
+                    current.material
+                };
+                let next = MeshBuilder::new(&object, material);
+                let finished = std::mem::replace(&mut current, next);
+                if !finished.data.indices.is_empty() {
+                    meshes.push(finished.finish());
+                }
+            }
+            "s" => {
+                smoothing = match rest.first().copied() {
+                    None | Some("off") | Some("0") => None,
+                    Some(group) => {
+                        let group = group.parse().map_err(|_| {
+                            invalid(number, format!("bad smoothing group '{group}'"))
+                        })?;
+                        Some(group)
+                    }
+                }
+            }
+            "f" => {
+                if rest.len() < 3 {
+                    return Err(invalid(number, "face has fewer than 3 vertices".into()));
+                }
+                let mut corners = Vec::with_capacity(rest.len());
+                for corner in &rest {
+                    let (position, uv, normal) =
+                        corner_indices(corner, &attributes).map_err(|e| invalid(number, e))?;
+                    let normal = match (normal, smoothing) {
+                        (Some(normal), _) => NormalSource::Given(normal),
+                        (None, Some(group)) => NormalSource::Smooth(group),
+                        (None, None) => NormalSource::Flat(faces),
+                    };
+                    let key = VertexKey {
+                        position,
+                        uv,
+                        normal,
+                    };
+                    corners.push(current.vertex(key, &attributes));
+                }
+                faces += 1;
+                for i in 1..corners.len() - 1 {
+                    current
+                        .data
+                        .indices
+                        .extend([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            "mtllib" => {
+                for file in &rest {
+                    let library = base.join(file);
+                    match fs::read_to_string(&library) {
+                        Ok(source) => materials.extend(parse_mtl(&source, &library)?),
+                        Err(e) => warn!("{}: {e}", library.display()),
+                    }
+                }
+            }
+            // Lines, points, curves and the like
+            _ => {}
+        }
+    }
+    if !current.data.indices.is_empty() {
+        meshes.push(current.finish());
+    }
+
+    Ok(ObjAsset {
+        name: path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        meshes,
+        materials,
+    })
+}
+
+fn floats<const N: usize>(words: &[&str]) -> Result<[f32; N], String> {
+    let mut values = [0.0; N];
+    for (i, value) in values.iter_mut().enumerate() {
+        let word = words
+            .get(i)
+            .ok_or_else(|| format!("expected {N} numbers"))?;
+        *value = word
+            .parse()
+            .map_err(|_| format!("'{word}' isn't a number"))?;
+    }
+    Ok(values)
+}
+
+/// Resolves a face corner such as `3`, `3/1`, `3//2` or `3/1/2` to its
+/// position, texture coordinates and normal. Indices start at 1, and
+/// negative ones count back from the latest element.
+fn corner_indices(
+    corner: &str,
+    attributes: &Attributes,
+) -> Result<(usize, Option<usize>, Option<usize>), String> {
+    let resolve = |word: &str, len: usize, kind: &str| -> Result<usize, String> {
+        let index: i64 = word
+            .parse()
+            .map_err(|_| format!("bad {kind} index '{word}'"))?;
+        let resolved = if index < 0 {
+            len as i64 + index
+        } else {
+            index - 1
+        };
+        if (0..len as i64).contains(&resolved) {
+            Ok(resolved as usize)
+        } else {
+            Err(format!("{kind} index {index} is out of range"))
+        }
+    };
+    let optional = |word: Option<&str>, len: usize, kind: &str| match word {
+        Some(word) if !word.is_empty() => resolve(word, len, kind).map(Some),
+        _ => Ok(None),
+    };
+
+    let mut parts = corner.split('/');
+    let position = resolve(
+        parts.next().unwrap_or_default(),
+        attributes.positions.len(),
+        "vertex",
+    )?;
+    let uv = optional(parts.next(), attributes.uvs.len(), "texture coordinate")?;
+    let normal = optional(parts.next(), attributes.normals.len(), "normal")?;
+    Ok((position, uv, normal))
+}
+
+/// Converts the classic Phong-style MTL parameters to metallic-roughness,
+/// preferring the PBR extension's `Pr`/`Pm` when a material has them.
+fn parse_mtl(source: &str, path: &Path) -> Result<Vec<MaterialDesc>, AssetError> {
+    let base = path.parent().unwrap_or(Path::new(""));
+    let invalid = |line: usize, message: String| AssetError::Invalid {
+        path: path.to_path_buf(),
+        message: format!("line {line}: {message}"),
+    };
+
+    let mut materials: Vec<MaterialDesc> = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let rest: Vec<&str> = words.collect();
+        if keyword == "newmtl" {
+            materials.push(MaterialDesc {
+                name: rest.join(" "),
+                metallic: 0.0,
+                ..MaterialDesc::default()
+            });
+            continue;
+        }
+        let Some(material) = materials.last_mut() else {
+            continue;
+        };
+        let float = || floats::<1>(&rest).map(|[value]| value);
+        // Texture options come before the file name
+        let texture = || rest.last().map(|file| TextureSource::Path(base.join(file)));
+        match keyword {
+            "Kd" => {
+                let [r, g, b] = floats(&rest).map_err(|e| invalid(number, e))?;
+                material.base_color = [r, g, b, material.base_color[3]];
+            }
+            "Ke" => material.emissive = floats(&rest).map_err(|e| invalid(number, e))?,
+            "d" => material.base_color[3] = float().map_err(|e| invalid(number, e))?,
+            "Tr" => material.base_color[3] = 1.0 - float().map_err(|e| invalid(number, e))?,
+            "Ns" => {
+                let shininess = float().map_err(|e| invalid(number, e))?.max(0.0);
+                material.roughness = (2.0 / (shininess + 2.0)).sqrt();
+            }
+            "Pr" => material.roughness = float().map_err(|e| invalid(number, e))?,
+            "Pm" => material.metallic = float().map_err(|e| invalid(number, e))?,
+            "map_Kd" => material.base_color_texture = texture(),
+            "norm" | "map_Bump" | "map_bump" | "bump" => material.normal_texture = texture(),
+            _ => {}
+        }
+    }
+    for material in &mut materials {
+        if material.base_color[3] < 1.0 {
+            material.alpha_mode = AlphaMode::Blend;
+        }
+    }
+    Ok(materials)
+}
+
+impl ObjAsset {
+    /// Creates an entity for each mesh under a new root entity named after
+    /// the asset, and uploads the meshes. Returns the root.
+    pub fn spawn(&self, world: &mut World, ctx: &mut WgpuCtx<'_>) -> EntityId {
+        let root = world.spawn();
+        world.insert(root, Name(self.name.clone()));
+        world.insert(root, Transform::default());
+
+        let mut children = Vec::new();
+        for (i, mesh) in self.meshes.iter().enumerate() {
+            let entity = world.spawn();
+            world.insert(entity, Name(mesh.name.clone()));
+            world.insert(entity, Transform::default());
+            world.insert(entity, Parent(root));
+            world.insert(
+                entity,
+                MeshInstance {
+                    mesh: ctx.add_mesh(&format!("{}/{}/{i}", self.name, mesh.name), &mesh.data),
+                    material: mesh.material.and_then(|i| self.materials.get(i)).cloned(),
+                },
+            );
+            children.push(entity);
+        }
+        world.insert(root, Children(children));
+        root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Writes `files` into a directory of their own for the test `name` and
+    /// loads the first, which is the OBJ file.
+    fn load_files(name: &str, files: &[(&str, &str)]) -> Result<ObjAsset, AssetError> {
+        let dir = std::env::temp_dir().join(format!("zenyx-obj-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, contents) in files {
+            fs::write(dir.join(file), contents).unwrap();
+        }
+        let result = load(&dir.join(files[0].0));
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    fn load_obj(name: &str, source: &str) -> ObjAsset {
+        load_files(name, &[("model.obj", source)]).unwrap()
+    }
+
+    fn load_error(name: &str, source: &str) -> String {
+        match load_files(name, &[("model.obj", source)]) {
+            Err(AssetError::Invalid { message, .. }) => message,
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(_) => panic!("loaded invalid source:\n{source}"),
+        }
+    }
+
+    fn only_mesh(asset: &ObjAsset) -> &MeshData {
+        let [mesh] = asset.meshes.as_slice() else {
+            panic!("expected one mesh, got {}", asset.meshes.len());
+        };
+        &mesh.data
+    }
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        let close = actual
+            .iter()
+            .zip(expected)
+            .all(|(a, e)| (a - e).abs() < 1e-5);
+        assert!(close, "{actual:?} != {expected:?}");
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_latest_element() {
+        let asset = load_obj(
+            "negative",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf -3//-1 -2//-1 -1//-1\n\
+             v 0 0 1\nf -4//1 -3//1 -1//1\n",
+        );
+        let data = only_mesh(&asset);
+        let positions: Vec<_> = data.vertices.iter().map(|v| v.position).collect();
+        assert_eq!(
+            positions,
+            [
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [0.0, 0.0, 1.0]
+            ]
+        );
+        assert_eq!(data.indices, [0, 1, 2, 0, 1, 3]);
+    }
+
+    #[test]
+    fn corners_may_skip_texture_coordinates() {
+        let asset = load_obj(
+            "corners",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.25 0.75\nvt 0.5\nvn 0 0 -1\n\
+             f 1//1 2/1/1 3/2\n",
+        );
+        let data = only_mesh(&asset);
+        // Given normals are kept as they are, even when they disagree with
+        // the winding
+        for vertex in &data.vertices[..2] {
+            assert_eq!(vertex.normal, [0.0, 0.0, -1.0]);
+        }
+        assert_close(data.vertices[2].normal, [0.0, 0.0, 1.0]);
+        // Flipped vertically, with a missing second coordinate read as 0
+        let uvs: Vec<_> = data.vertices.iter().map(|v| v.uv).collect();
+        assert_eq!(uvs, [[0.0, 0.0], [0.25, 0.25], [0.5, 1.0]]);
+    }
+
+    #[test]
+    fn polygons_are_split_into_fans() {
+        let asset = load_obj(
+            "fan",
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0.5 2 0\nv 0 1 0\nf 1 2 3 4 5\n",
+        );
+        let data = only_mesh(&asset);
+        assert_eq!(data.vertices.len(), 5);
+        assert_eq!(data.indices, [0, 1, 2, 0, 2, 3, 0, 3, 4]);
+        for vertex in &data.vertices {
+            assert_close(vertex.normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    /// Two faces at a right angle, sharing the edge from 1 to 2.
+    const HINGE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\n";
+
+    #[test]
+    fn smoothing_groups_share_vertices_and_average_normals() {
+        let asset = load_obj("smooth", &format!("{HINGE}s 1\nf 1 2 3\nf 1 4 2\n"));
+        let data = only_mesh(&asset);
+        assert_eq!(data.vertices.len(), 4);
+        assert_eq!(data.indices, [0, 1, 2, 0, 3, 1]);
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert_close(data.vertices[0].normal, [0.0, half, half]);
+        assert_close(data.vertices[1].normal, [0.0, half, half]);
+        assert_close(data.vertices[2].normal, [0.0, 0.0, 1.0]);
+        assert_close(data.vertices[3].normal, [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn faces_outside_smoothing_groups_are_flat() {
+        for (name, smoothing) in [("flat", ""), ("off", "s off\n"), ("zero", "s 0\n")] {
+            let asset = load_obj(name, &format!("{HINGE}{smoothing}f 1 2 3\nf 1 4 2\n"));
+            let data = only_mesh(&asset);
+            assert_eq!(data.vertices.len(), 6, "{name}");
+            for vertex in &data.vertices[..3] {
+                assert_close(vertex.normal, [0.0, 0.0, 1.0]);
+            }
+            for vertex in &data.vertices[3..] {
+                assert_close(vertex.normal, [0.0, 1.0, 0.0]);
+            }
+        }
+
+        // Different groups don't share vertices either
+        let asset = load_obj("groups", &format!("{HINGE}s 1\nf 1 2 3\ns 2\nf 1 4 2\n"));
+        assert_eq!(only_mesh(&asset).vertices.len(), 6);
+    }
+
+    #[test]
+    fn vertices_are_shared_only_when_every_index_matches() {
+        let asset = load_obj(
+            "dedup",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvn 0 0 1\nvn 0 0 -1\n\
+             f 1/1/1 2/1/1 3/1/1\n\
+             f 1/1/1 3/1/1 2/1/1\n\
+             f 1/2/1 2/1/1 3/1/2\n",
+        );
+        let data = only_mesh(&asset);
+        // The second face reuses all three, the third adds a corner with
+        // other texture coordinates and one with another normal
+        assert_eq!(data.vertices.len(), 5);
+        assert_eq!(data.indices, [0, 1, 2, 0, 2, 1, 3, 1, 4]);
+    }
+
+    #[test]
+    fn objects_groups_and_materials_split_meshes() {
+        let mtl = "newmtl red\nKd 1 0 0\n\nnewmtl blue\nKd 0 0 1\n";
+        let obj = "mtllib scene.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                   o cube\nusemtl red\nf 1 2 3\nusemtl blue\nf 1 2 3\nf 3 2 1\n\
+                   g lid\nf 1 2 3\nusemtl missing\ng empty\no ball\nf 1 2 3\n";
+        let asset = load_files("split", &[("scene.obj", obj), ("scene.mtl", mtl)]).unwrap();
+        assert_eq!(asset.name, "scene");
+        let names: Vec<_> = asset.materials.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["red", "blue"]);
+        assert_eq!(asset.materials[0].base_color, [1.0, 0.0, 0.0, 1.0]);
+
+        let meshes: Vec<_> = (asset.meshes.iter())
+            .map(|mesh| {
+                (
+                    mesh.name.as_str(),
+                    mesh.material,
+                    mesh.data.indices.len() / 3,
+                )
+            })
+            .collect();
+        // Groups keep the material, and ones without faces are left out
+        assert_eq!(
+            meshes,
+            [
+                ("cube", Some(0), 1),
+                ("cube", Some(1), 2),
+                ("lid", Some(1), 1),
+                ("ball", None, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_material_libraries_are_skipped() {
+        let asset = load_obj(
+            "no-mtl",
+            "mtllib nowhere.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+        );
+        assert!(asset.materials.is_empty());
+        assert_eq!(asset.meshes.len(), 1);
+    }
+
+    fn material(source: &str) -> MaterialDesc {
+        let mut materials = parse_mtl(source, Path::new("textures/test.mtl")).unwrap();
+        assert_eq!(materials.len(), 1);
+        materials.pop().unwrap()
+    }
+
+    #[test]
+    fn mtl_transparency_makes_materials_blend() {
+        let opaque = material("newmtl a\nKd 0.5 0.5 0.5\n");
+        assert_eq!(opaque.base_color, [0.5, 0.5, 0.5, 1.0]);
+        assert_eq!(opaque.alpha_mode, AlphaMode::Opaque);
+        assert_eq!(opaque.metallic, 0.0);
+
+        // Dissolve is opacity, and comes through whatever order `Kd` is in
+        let dissolved = material("newmtl a\nd 0.5\nKd 1 1 1\n");
+        assert_eq!(dissolved.base_color, [1.0, 1.0, 1.0, 0.5]);
+        assert_eq!(dissolved.alpha_mode, AlphaMode::Blend);
+
+        let transparent = material("newmtl a\nTr 0.25\n");
+        assert_eq!(transparent.base_color[3], 0.75);
+        assert_eq!(transparent.alpha_mode, AlphaMode::Blend);
+
+        assert_eq!(material("newmtl a\nTr 0\n").alpha_mode, AlphaMode::Opaque);
+    }
+
+    #[test]
+    fn mtl_shininess_becomes_roughness() {
+        assert_eq!(material("newmtl a\nNs 0\n").roughness, 1.0);
+        assert_eq!(material("newmtl a\nNs -5\n").roughness, 1.0);
+        assert!((material("newmtl a\nNs 198\n").roughness - 0.1).abs() < 1e-6);
+
+        // The PBR extension's values win
+        let pbr = material("newmtl a\nNs 198\nPr 0.7\nPm 0.2\n");
+        assert_eq!((pbr.roughness, pbr.metallic), (0.7, 0.2));
+    }
+
+    #[test]
+    fn mtl_textures_are_relative_to_the_library() {
+        let textured = material("newmtl a\nmap_Kd -s 2 2 1 wood.png\nbump -bm 0.5 wood_n.png\n");
+        let texture = |file: &str| Some(TextureSource::Path(PathBuf::from("textures").join(file)));
+        assert_eq!(textured.base_color_texture, texture("wood.png"));
+        assert_eq!(textured.normal_texture, texture("wood_n.png"));
+    }
+
+    #[test]
+    fn mtl_errors_name_the_line() {
+        let e = parse_mtl("newmtl a\n\nNs shiny\n", Path::new("test.mtl")).unwrap_err();
+        assert_eq!(e.to_string(), "test.mtl: line 3: 'shiny' isn't a number");
+        // Parameters before the first material are ignored
+        assert!(
+            parse_mtl("Ns shiny\n", Path::new("test.mtl"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors() {
+        let attributes = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvn 0 0 1\n";
+        for (name, face, message) in [
+            (
+                "past-end",
+                "f 1 2 4",
+                "line 6: vertex index 4 is out of range",
+            ),
+            ("zero", "f 0 1 2", "line 6: vertex index 0 is out of range"),
+            (
+                "negative",
+                "f -4 1 2",
+                "line 6: vertex index -4 is out of range",
+            ),
+            (
+                "uv",
+                "f 1/2 2/1 3/1",
+                "line 6: texture coordinate index 2 is out of range",
+            ),
+            (
+                "normal",
+                "f 1//1 2//1 3//-2",
+                "line 6: normal index -2 is out of range",
+            ),
+            ("word", "f 1 2 x", "line 6: bad vertex index 'x'"),
+            ("short", "f 1 2", "line 6: face has fewer than 3 vertices"),
+        ] {
+            assert_eq!(load_error(name, &format!("{attributes}{face}\n")), message);
+        }
+    }
+
+    #[test]
+    fn malformed_attributes_are_errors() {
+        assert_eq!(
+            load_error("short-v", "v 0 0\n"),
+            "line 1: expected 3 numbers"
+        );
+        assert_eq!(
+            load_error("bad-vn", "\nvn 0 up 0\n"),
+            "line 2: 'up' isn't a number"
+        );
+        assert_eq!(
+            load_error("bad-s", "s smooth\n"),
+            "line 1: bad smoothing group 'smooth'"
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+use log::{LevelFilter, error};
+
+use super::{
+    repl::{handler::Exit, zensh::Interpreter},
+    workspace,
+};
+
+#[derive(Debug, Clone, Parser)]
+#[command(name = "zenyx", version, about = "The Zenyx game engine")]
+pub struct Cli {
+    /// A .zensh script to run
+    pub script: Option<PathBuf>,
+
+    /// Arguments passed to the script as $1, $2, ...
+    #[arg(
+        requires = "script",
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    pub args: Vec<String>,
+
+    /// Run zensh commands, e.g. -c "echo hi; help"
+    #[arg(short, long, value_name = "COMMANDS", conflicts_with = "script")]
+    pub command: Option<String>,
+
+    /// Start the REPL after the script or commands have run instead of exiting
+    #[arg(short, long)]
+    pub interactive: bool,
+
+    /// Run without opening a window. The simulation keeps running alongside
+    /// the REPL or script
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Ticks per second of the simulation when headless
+    #[arg(long, value_name = "HZ", default_value_t = 60, requires = "headless")]
+    pub tick_rate: u32,
+
+    /// Render every tick into an offscreen target when headless
+    #[arg(
+        long,
+        value_name = "WIDTHxHEIGHT",
+        num_args = 0..=1,
+        default_missing_value = "800x600",
+        value_parser = parse_size,
+        requires = "headless"
+    )]
+    pub offscreen: Option<(u32, u32)>,
+
+    /// Don't print the splash screen
+    #[arg(long)]
+    pub no_splash: bool,
+
+    /// Don't run ~/.zenyxrc or the workspace's .zenyxrc before the REPL starts
+    #[arg(long)]
+    pub no_rc: bool,
+
+    /// Most verbose level of log messages to show
+    #[arg(long, value_name = "LEVEL", default_value = "info")]
+    pub log_level: LogLevel,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let parsed = size
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+    match parsed {
+        Some((width, height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("expected WIDTHxHEIGHT, e.g. 800x600, got '{size}'")),
+    }
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> LevelFilter {
+        match level {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+impl Cli {
+    /// Whether a REPL session follows startup. Without one, the engine exits
+    /// once the script or commands have run.
+    pub fn is_interactive(&self) -> bool {
+        self.interactive || (self.script.is_none() && self.command.is_none())
+    }
+
+    /// Runs the script or commands given on the command line.
+    pub fn run_startup(&self, shell: &mut Interpreter) -> anyhow::Result<()> {
+        if let Some(commands) = &self.command {
+            shell.run(commands)?;
+        }
+        if let Some(script) = &self.script {
+            shell.set_args(&self.args);
+            shell.run_file(script)?;
+        }
+        Ok(())
+    }
+
+    /// Creates the interpreter for the REPL, with the rc files and then the
+    /// startup script or commands already run in it, so that whatever they
+    /// define can be used interactively. Errors are reported and the REPL
+    /// starts anyway, unless one of them runs `exit`.
+    pub fn start_shell(&self) -> anyhow::Result<Interpreter> {
+        let rc_files = if self.no_rc { Vec::new() } else { rc_files() };
+        self.start_shell_with(&rc_files)
+    }
+
+    fn start_shell_with(&self, rc_files: &[PathBuf]) -> anyhow::Result<Interpreter> {
+        let mut shell = Interpreter::new();
+        for path in rc_files {
+            report(shell.run_file(path))?;
+        }
+        report(self.run_startup(&mut shell))?;
+        Ok(shell)
+    }
+}
+
+/// Logs an error from startup, passing on only the ones from `exit`.
+fn report(result: anyhow::Result<()>) -> anyhow::Result<()> {
+    match result {
+        Err(e) if Exit::code(&e).is_none() => {
+            error!("{e:#}");
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// `~/.zenyxrc` followed by `.zenyxrc` in the workspace, for those that exist.
+pub fn rc_files() -> Vec<PathBuf> {
+    let home = dirs_next::home_dir();
+    let workspace = workspace::get_working_dir().ok();
+    rc_files_in(&[home, workspace].into_iter().flatten().collect::<Vec<_>>())
+}
+
+fn rc_files_in(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    dirs.iter()
+        .map(|dir| dir.join(".zenyxrc"))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Once};
+
+    use super::*;
+    use crate::{
+        commands,
+        core::repl::{commands::ExitCommand, zensh::parser::parse},
+    };
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(["zenyx"].iter().chain(args)).unwrap()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("zenyx-cli-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn register_exit() {
+        static SETUP: Once = Once::new();
+        SETUP.call_once(|| {
+            commands!(ExitCommand);
+        });
+    }
+
+    #[test]
+    fn interactive_unless_given_something_to_run() {
+        assert!(cli(&[]).is_interactive());
+        assert!(cli(&["--headless", "--no-splash"]).is_interactive());
+        assert!(!cli(&["-c", "echo hi"]).is_interactive());
+        assert!(!cli(&["setup.zensh", "a", "--b"]).is_interactive());
+        assert!(cli(&["-i", "setup.zensh"]).is_interactive());
+        assert!(cli(&["--interactive", "-c", "echo hi"]).is_interactive());
+    }
+
+    #[test]
+    fn script_arguments_may_look_like_options() {
+        let parsed = cli(&["setup.zensh", "a", "-c", "--headless"]);
+        assert_eq!(parsed.script, Some(PathBuf::from("setup.zensh")));
+        assert_eq!(parsed.args, ["a", "-c", "--headless"]);
+        assert!(parsed.command.is_none() && !parsed.headless);
+
+        // Before the first argument they need separating from the options
+        let parsed = cli(&["--headless", "setup.zensh", "--", "-c"]);
+        assert_eq!(parsed.args, ["-c"]);
+        assert!(parsed.command.is_none() && parsed.headless);
+    }
+
+    #[test]
+    fn sizes_are_width_by_height() {
+        assert_eq!(parse_size("800x600"), Ok((800, 600)));
+        assert_eq!(parse_size("1x1"), Ok((1, 1)));
+        for size in [
+            "800",
+            "0x600",
+            "800x0",
+            "800x-1",
+            "x600",
+            "800x600x2",
+            "wide",
+        ] {
+            assert_eq!(
+                parse_size(size),
+                Err(format!("expected WIDTHxHEIGHT, e.g. 800x600, got '{size}'"))
+            );
+        }
+    }
+
+    #[test]
+    fn offscreen_needs_headless_and_defaults_its_size() {
+        assert_eq!(
+            cli(&["--headless", "--offscreen"]).offscreen,
+            Some((800, 600))
+        );
+        assert_eq!(
+            cli(&["--headless", "--offscreen", "64x32"]).offscreen,
+            Some((64, 32))
+        );
+        assert!(Cli::try_parse_from(["zenyx", "--offscreen", "64x32"]).is_err());
+        assert!(Cli::try_parse_from(["zenyx", "-c", "a", "b.zensh"]).is_err());
+    }
+
+    #[test]
+    fn example_script_parses() {
+        parse(include_str!("../../../main.zensh")).unwrap();
+    }
+
+    #[test]
+    fn rc_files_run_home_first_and_only_if_they_are_files() {
+        let dirs = ["home", "workspace", "empty", "directory"].map(temp_dir);
+        fs::write(dirs[0].join(".zenyxrc"), "").unwrap();
+        fs::write(dirs[1].join(".zenyxrc"), "").unwrap();
+        fs::create_dir_all(dirs[3].join(".zenyxrc")).unwrap();
+
+        let found = rc_files_in(&dirs);
+        let reversed = rc_files_in(&[dirs[1].clone(), dirs[0].clone()]);
+        for dir in &dirs {
+            fs::remove_dir_all(dir).unwrap();
+        }
+        assert_eq!(found, [dirs[0].join(".zenyxrc"), dirs[1].join(".zenyxrc")]);
+        assert_eq!(
+            reversed,
+            [dirs[1].join(".zenyxrc"), dirs[0].join(".zenyxrc")]
+        );
+    }
+
+    #[test]
+    fn startup_commands_run_after_the_rc_files() {
+        let dir = temp_dir("order");
+        let rc_files = [dir.join("first"), dir.join("second"), dir.join("broken")];
+        fs::write(&rc_files[0], "set order first").unwrap();
+        fs::write(&rc_files[1], "set order \"$order second\"").unwrap();
+        fs::write(&rc_files[2], "set order \"$order broken\"; no_such_command").unwrap();
+
+        let shell = cli(&["-i", "-c", "set order \"$order command\""])
+            .start_shell_with(&rc_files)
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        // Errors don't stop the rc files or commands after them
+        assert_eq!(
+            shell.get_var("order").map(String::as_str),
+            Some("first second broken command")
+        );
+    }
+
+    #[test]
+    fn exit_in_an_rc_file_stops_startup() {
+        register_exit();
+        let dir = temp_dir("exit");
+        let rc_files = [dir.join("exits"), dir.join("after")];
+        fs::write(&rc_files[0], "exit 3").unwrap();
+        fs::write(&rc_files[1], "set ran yes").unwrap();
+
+        let result = cli(&[]).start_shell_with(&rc_files);
+        fs::remove_dir_all(&dir).unwrap();
+        let Err(e) = result else {
+            panic!("expected startup to stop");
+        };
+        assert_eq!(Exit::code(&e), Some(3));
+
+        let Err(e) = cli(&["-i", "-c", "exit 4; set ran yes"]).start_shell_with(&[]) else {
+            panic!("expected startup to stop");
+        };
+        assert_eq!(Exit::code(&e), Some(4));
+    }
+}
@@ -0,0 +1,73 @@
+use cgmath::{Quaternion, Vector3};
+
+use super::world::EntityId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    Step,
+    /// Each keyframe holds an in-tangent, the value and an out-tangent, in
+    /// that order.
+    CubicSpline,
+}
+
+/// The values a channel animates, one per keyframe, or three for cubic
+/// splines.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Keyframes {
+    Translation(Vec<Vector3<f32>>),
+    Rotation(Vec<Quaternion<f32>>),
+    Scale(Vec<Vector3<f32>>),
+    /// Morph target weights, with one weight per target for each keyframe.
+    Weights(Vec<f32>),
+}
+
+/// Animates one property of one entity's transform.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationChannel<T = EntityId> {
+    pub target: T,
+    pub interpolation: Interpolation,
+    /// Keyframe times in seconds.
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationClip<T = EntityId> {
+    pub name: String,
+    pub channels: Vec<AnimationChannel<T>>,
+}
+
+impl<T> AnimationClip<T> {
+    /// Length of the clip in seconds.
+    pub fn duration(&self) -> f32 {
+        self.channels
+            .iter()
+            .filter_map(|channel| channel.times.last())
+            .fold(0.0, |a, &b| a.max(b))
+    }
+
+    /// The same clip animating different targets. Channels whose target
+    /// maps to `None` are dropped.
+    pub fn filter_map_targets<U>(&self, mut map: impl FnMut(&T) -> Option<U>) -> AnimationClip<U> {
+        AnimationClip {
+            name: self.name.clone(),
+            channels: self
+                .channels
+                .iter()
+                .filter_map(|channel| {
+                    Some(AnimationChannel {
+                        target: map(&channel.target)?,
+                        interpolation: channel.interpolation,
+                        times: channel.times.clone(),
+                        keyframes: channel.keyframes.clone(),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Clips that animate this entity and its descendants.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Animations(pub Vec<AnimationClip>);
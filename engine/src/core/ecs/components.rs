@@ -0,0 +1,68 @@
+use cgmath::{Matrix4, Quaternion, Vector3};
+
+use super::world::EntityId;
+use crate::core::{assets::material::MaterialDesc, render::mesh::MeshId};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Name(pub String);
+
+/// Placement relative to the parent entity, or to the world for entities
+/// without one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parent(pub EntityId);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Children(pub Vec<EntityId>);
+
+/// Draws a registered mesh at the entity's transform.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshInstance {
+    pub mesh: MeshId,
+    pub material: Option<MaterialDesc>,
+}
+
+/// Joints that deform the meshes of this entity. Vertices refer to joints
+/// by their index in `joints`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skin {
+    /// `None` for joints that weren't spawned, such as nodes outside the
+    /// scene, so the others keep their indices.
+    pub joints: Vec<Option<EntityId>>,
+    /// Transforms each joint from model space into its own space, in the
+    /// pose the mesh was modelled in.
+    pub inverse_bind_matrices: Vec<Matrix4<f32>>,
+}
+
+/// The joints that move each vertex of the entity's mesh, as indices into
+/// the [`Skin`] of this entity, or of its parent for the entities a mesh's
+/// later primitives get. Nothing deforms meshes with them yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JointWeights {
+    pub joints: Vec<[u16; 4]>,
+    pub weights: Vec<[f32; 4]>,
+}
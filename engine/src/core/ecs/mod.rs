@@ -1,3 +1,7 @@
+pub mod animation;
+pub mod components;
+pub mod world;
+
 pub trait Component: Sized + 'static {
     fn update(&mut self, delta_time: f32);
     fn serialize(&self) -> Vec<u8>;
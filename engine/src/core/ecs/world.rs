@@ -0,0 +1,180 @@
+use std::{
+    any::{Any, TypeId},
+    collections::{BTreeSet, HashMap},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityId(u32);
+
+/// Stores entities and their components, one of each type per entity.
+#[derive(Default)]
+pub struct World {
+    entities: BTreeSet<EntityId>,
+    components: HashMap<TypeId, HashMap<EntityId, Box<dyn Any + Send + Sync>>>,
+    next_id: u32,
+}
+
+impl World {
+    pub fn new() -> World {
+        World::default()
+    }
+
+    pub fn spawn(&mut self) -> EntityId {
+        self.next_id += 1;
+        let id = EntityId(self.next_id);
+        self.entities.insert(id);
+        id
+    }
+
+    /// Removes an entity and all of its components.
+    pub fn despawn(&mut self, entity: EntityId) -> bool {
+        for storage in self.components.values_mut() {
+            storage.remove(&entity);
+        }
+        self.entities.remove(&entity)
+    }
+
+    pub fn contains(&self, entity: EntityId) -> bool {
+        self.entities.contains(&entity)
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.entities.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Adds a component to an entity, replacing one of the same type.
+    pub fn insert<C: Any + Send + Sync>(&mut self, entity: EntityId, component: C) {
+        debug_assert!(self.contains(entity), "{entity:?} doesn't exist");
+        self.components
+            .entry(TypeId::of::<C>())
+            .or_default()
+            .insert(entity, Box::new(component));
+    }
+
+    pub fn remove<C: Any + Send + Sync>(&mut self, entity: EntityId) -> Option<C> {
+        let component = self
+            .components
+            .get_mut(&TypeId::of::<C>())?
+            .remove(&entity)?;
+        component.downcast().ok().map(|component| *component)
+    }
+
+    pub fn get<C: Any + Send + Sync>(&self, entity: EntityId) -> Option<&C> {
+        self.components
+            .get(&TypeId::of::<C>())?
+            .get(&entity)?
+            .downcast_ref()
+    }
+
+    pub fn get_mut<C: Any + Send + Sync>(&mut self, entity: EntityId) -> Option<&mut C> {
+        self.components
+            .get_mut(&TypeId::of::<C>())?
+            .get_mut(&entity)?
+            .downcast_mut()
+    }
+
+    /// Every entity with a component of type `C`, in no particular order.
+    pub fn query<C: Any + Send + Sync>(&self) -> impl Iterator<Item = (EntityId, &C)> {
+        self.components
+            .get(&TypeId::of::<C>())
+            .into_iter()
+            .flatten()
+            .filter_map(|(&entity, component)| Some((entity, component.downcast_ref()?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct Label(&'static str);
+
+    #[test]
+    fn spawned_entities_are_distinct() {
+        let mut world = World::new();
+        assert!(world.is_empty());
+        let a = world.spawn();
+        let b = world.spawn();
+        assert_ne!(a, b);
+        assert_eq!(world.entities().collect::<Vec<_>>(), [a, b]);
+        assert_eq!(world.len(), 2);
+    }
+
+    #[test]
+    fn insert_replaces_a_component_of_the_same_type() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.insert(entity, Health(3));
+        world.insert(entity, Label("player"));
+        world.insert(entity, Health(5));
+        assert_eq!(world.get(entity), Some(&Health(5)));
+        assert_eq!(world.get(entity), Some(&Label("player")));
+
+        world.get_mut::<Health>(entity).unwrap().0 -= 1;
+        assert_eq!(world.get(entity), Some(&Health(4)));
+    }
+
+    #[test]
+    fn remove_returns_the_component() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.insert(entity, Health(3));
+        world.insert(entity, Label("player"));
+        assert_eq!(world.remove(entity), Some(Health(3)));
+        assert_eq!(world.remove::<Health>(entity), None);
+        assert_eq!(world.get::<Health>(entity), None);
+        assert_eq!(world.get(entity), Some(&Label("player")));
+        // Nothing of this type was ever inserted
+        assert_eq!(world.remove::<u8>(entity), None);
+    }
+
+    #[test]
+    fn query_finds_entities_with_a_component() {
+        let mut world = World::new();
+        let [a, b, c] = [world.spawn(), world.spawn(), world.spawn()];
+        world.insert(a, Health(1));
+        world.insert(b, Label("b"));
+        world.insert(c, Health(3));
+
+        let mut found: Vec<_> = world.query::<Health>().collect();
+        found.sort_by_key(|(entity, _)| *entity);
+        assert_eq!(found, [(a, &Health(1)), (c, &Health(3))]);
+        assert_eq!(world.query::<u8>().count(), 0);
+    }
+
+    #[test]
+    fn despawn_removes_the_entity_and_its_components() {
+        let mut world = World::new();
+        let a = world.spawn();
+        let b = world.spawn();
+        world.insert(a, Health(1));
+        world.insert(a, Label("a"));
+        world.insert(b, Health(2));
+
+        assert!(world.despawn(a));
+        assert!(!world.contains(a));
+        assert!(world.contains(b));
+        assert_eq!(world.get::<Health>(a), None);
+        assert_eq!(world.get::<Label>(a), None);
+        assert_eq!(
+            world.query::<Health>().collect::<Vec<_>>(),
+            [(b, &Health(2))]
+        );
+        assert!(!world.despawn(a));
+
+        // Ids aren't reused
+        assert_ne!(world.spawn(), a);
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, info, warn};
+
+use super::render::{
+    bus::{self, Connection, Notice, RenderMessage, RequestError},
+    ctx::{ContextError, WgpuCtx},
+};
+
+/// The engine's update loop for when there's no window, and so no event
+/// loop, to drive it. Runs on its own thread at a fixed tick rate and can
+/// draw each tick into an offscreen target.
+pub struct Simulation {
+    running: Arc<AtomicBool>,
+    thread: JoinHandle<u64>,
+}
+
+/// Creates the renderer for an offscreen target of the given size.
+type CreateRenderer = fn(u32, u32) -> Result<WgpuCtx<'static>, ContextError>;
+
+impl Simulation {
+    pub fn start(tick_rate: u32, offscreen: Option<(u32, u32)>) -> Simulation {
+        Simulation::start_with(tick_rate, offscreen, WgpuCtx::new_offscreen_blocking)
+    }
+
+    fn start_with(
+        tick_rate: u32,
+        offscreen: Option<(u32, u32)>,
+        create: CreateRenderer,
+    ) -> Simulation {
+        let running = Arc::new(AtomicBool::new(true));
+        // Connected before the thread starts, so requests made while the
+        // renderer is still being created wait for it
+        let bus = offscreen.map(|_| bus::connect_channel());
+        let thread = thread::Builder::new()
+            .name("simulation".into())
+            .spawn({
+                let running = Arc::clone(&running);
+                move || run(&running, tick_rate, offscreen, bus, create)
+            })
+            .expect("Failed to spawn simulation thread");
+        Simulation { running, thread }
+    }
+
+    /// Stops the loop and returns how many ticks it ran for.
+    pub fn stop(self) -> u64 {
+        self.running.store(false, Ordering::Relaxed);
+        self.thread.join().unwrap_or_else(|_| {
+            error!("Simulation thread panicked");
+            0
+        })
+    }
+}
+
+fn run(
+    running: &AtomicBool,
+    tick_rate: u32,
+    offscreen: Option<(u32, u32)>,
+    bus: Option<(Connection, mpsc::Receiver<RenderMessage>)>,
+    create: CreateRenderer,
+) -> u64 {
+    let mut renderer = offscreen
+        .zip(bus)
+        .and_then(
+            |((width, height), (connection, messages))| match create(width, height) {
+                Ok(ctx) => {
+                    info!("Rendering offscreen at {width}x{height}");
+                    Some((ctx, connection, messages))
+                }
+                Err(e) => {
+                    error!("Failed to create offscreen renderer: {e}");
+                    disconnect(connection, messages);
+                    None
+                }
+            },
+        );
+
+    let tick = Duration::from_secs_f64(1.0 / f64::from(tick_rate.max(1)));
+    let mut ticks = 0;
+    let mut next_tick = Instant::now();
+    while running.load(Ordering::Relaxed) {
+        let failure = renderer.as_mut().and_then(|(ctx, _, messages)| {
+            for message in messages.try_iter() {
+                message.handle(ctx, None);
+            }
+            ctx.draw().err()
+        });
+        if let Some(e) = failure {
+            renderer = renderer.and_then(|(ctx, connection, messages)| {
+                let e = match e {
+                    ContextError::DeviceLost(reason) => {
+                        warn!("Rendering device lost ({reason}), rebuilding the renderer");
+                        match ctx.rebuild_blocking() {
+                            Ok((ctx, reset)) => {
+                                bus::notify(Notice::SceneReset(reset));
+                                return Some((ctx, connection, messages));
+                            }
+                            Err(e) => e,
+                        }
+                    }
+                    e => e,
+                };
+                error!("Stopping the offscreen renderer: {e}");
+                disconnect(connection, messages);
+                None
+            });
+        }
+        ticks += 1;
+
+        next_tick += tick;
+        let now = Instant::now();
+        if next_tick > now {
+            thread::sleep(next_tick - now);
+        } else {
+            // Running behind, skip the missed ticks rather than rushing them
+            next_tick = now;
+        }
+    }
+    debug!("Simulation stopped after {ticks} ticks");
+    ticks
+}
+
+/// Stops requests reaching the renderer, failing the ones already sent.
+fn disconnect(connection: Connection, messages: mpsc::Receiver<RenderMessage>) {
+    drop(connection);
+    for message in messages.try_iter() {
+        message.fail(RequestError::NoRenderer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::render::bus::{CONNECTION_LOCK, RenderReply, RenderRequest};
+
+    fn clear_color() -> Result<RenderReply, RequestError> {
+        bus::request(RenderRequest::SetClearColor(wgpu::Color::BLACK))
+    }
+
+    #[test]
+    fn offscreen_simulation_handles_requests_until_stopped() {
+        let _lock = CONNECTION_LOCK.lock();
+        let simulation =
+            Simulation::start_with(240, Some((32, 32)), WgpuCtx::new_software_blocking);
+        // Waits for the renderer to be created
+        let reply = bus::request(RenderRequest::Spawn {
+            mesh: "cube".into(),
+            color: None,
+        });
+        if let Err(RequestError::NoRenderer) = reply {
+            simulation.stop();
+            eprintln!("Skipping offscreen simulation test: no adapter");
+            return;
+        }
+        assert!(matches!(reply, Ok(RenderReply::Spawned(_))), "{reply:?}");
+        assert!(matches!(clear_color(), Ok(RenderReply::Done)));
+
+        thread::sleep(Duration::from_millis(50));
+        let ticks = simulation.stop();
+        assert!(ticks > 1, "{ticks}");
+        // The thread has finished, and dropped its connection
+        assert!(matches!(clear_color(), Err(RequestError::NoRenderer)));
+    }
+
+    #[test]
+    fn simulation_without_a_target_keeps_ticking() {
+        let _lock = CONNECTION_LOCK.lock();
+        let simulation = Simulation::start(1000, None);
+        assert!(matches!(clear_color(), Err(RequestError::NoRenderer)));
+        thread::sleep(Duration::from_millis(20));
+        assert!(simulation.stop() > 1);
+    }
+
+    #[test]
+    fn failing_to_create_the_renderer_fails_requests() {
+        let _lock = CONNECTION_LOCK.lock();
+        let simulation = Simulation::start_with(1000, Some((32, 32)), |_, _| {
+            Err(ContextError::AdapterNotFound)
+        });
+        assert!(matches!(clear_color(), Err(RequestError::NoRenderer)));
+        // Still ticks without it
+        thread::sleep(Duration::from_millis(20));
+        assert!(simulation.stop() > 1);
+    }
+}
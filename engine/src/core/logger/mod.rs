@@ -59,7 +59,7 @@ impl Log for DynamicLogger {
             || target.starts_with("winit")
             || target.starts_with(env!("CARGO_PKG_NAME")); // Current crate name
 
-        is_relevant_target && metadata.level() <= Level::Debug
+        is_relevant_target && metadata.level() <= log::max_level()
     }
 
     fn log(&self, record: &Record) {
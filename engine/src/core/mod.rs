@@ -1,4 +1,7 @@
+pub mod assets;
+pub mod cli;
 pub mod ecs;
+pub mod headless;
 pub mod logger;
 pub mod panic;
 pub mod repl;
@@ -12,7 +12,7 @@ pub fn set_panic_hook() {
 
     use colored::Colorize;
 
-    use crate::workspace;
+    use crate::core::workspace;
 
     INIT.call_once(|| {
         let default_hook = std::panic::take_hook();
@@ -107,6 +107,7 @@ fn render_backtrace() -> String {
     for (entry_idx, (frame, symbol, name)) in symbols.iter().skip(begin_unwind_start).enumerate() {
         let ip = frame.ip();
         let _ = writeln!(backtrace, "{entry_idx:4}: {ip:HEX_WIDTH$?} - {name}");
+        #[allow(clippy::collapsible_if)]
         if let Some(symbol) = symbol {
             if let (Some(file), Some(line)) = (symbol.filename(), symbol.lineno()) {
                 let _ = writeln!(
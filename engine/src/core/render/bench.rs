@@ -0,0 +1,145 @@
+//! A scene of many cubes, for seeing how the renderer copes with lots of
+//! objects.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use cgmath::{Deg, Matrix4, Point3, Vector3};
+
+use super::{
+    camera::{Camera, Projection},
+    ctx::{ContextError, FrameStats, Object, WgpuCtx},
+    material::MaterialParams,
+    mesh::MeshData,
+};
+
+pub const DEFAULT_CUBES: usize = 10_000;
+pub const DEFAULT_FRAMES: u32 = 100;
+
+/// The cubes cycle through these, so they're drawn in a few batches rather
+/// than one.
+const COLORS: [[f32; 4]; 4] = [
+    [0.9, 0.3, 0.2, 1.0],
+    [0.2, 0.7, 0.3, 1.0],
+    [0.2, 0.4, 0.9, 1.0],
+    [0.9, 0.8, 0.2, 1.0],
+];
+/// Distance between the centres of neighbouring cubes.
+const SPACING: f32 = 1.5;
+const CUBE_SIZE: f32 = 0.75;
+
+/// Replaces the objects with `count` cubes in a square grid on the XZ plane,
+/// and moves the first view's camera to look over it.
+pub fn cube_grid(ctx: &mut WgpuCtx<'_>, count: usize) {
+    let cube = match ctx.meshes().find("cube") {
+        Some(cube) => cube,
+        None => ctx.add_mesh("cube", &MeshData::cube(1.0)),
+    };
+    let materials: Vec<_> = (COLORS.iter())
+        .map(|&base_color| {
+            let params = MaterialParams {
+                base_color,
+                ..MaterialParams::default()
+            };
+            ctx.create_material(params, None, None)
+        })
+        .collect();
+
+    let side = (count as f32).sqrt().ceil().max(1.0) as usize;
+    let width = (side - 1) as f32 * SPACING;
+    *ctx.objects_mut() = (0..count)
+        .map(|i| {
+            let (row, column) = (i / side, i % side);
+            let position = Vector3::new(
+                column as f32 * SPACING - width / 2.0,
+                0.0,
+                row as f32 * SPACING - width / 2.0,
+            );
+            Object {
+                material: Some(materials[(row + column) % materials.len()].clone()),
+                transform: Matrix4::from_translation(position) * Matrix4::from_scale(CUBE_SIZE),
+                ..Object::from(cube)
+            }
+        })
+        .collect();
+
+    if let Some(view) = ctx.views_mut().first_mut() {
+        let distance = width.max(1.0);
+        let projection = Projection::Perspective {
+            fov_y: Deg(45.0).into(),
+            near: 0.1,
+            far: distance * 4.0,
+        };
+        view.camera = Camera {
+            aspect: view.camera.aspect,
+            ..Camera::new(Point3::new(0.0, distance * 0.6, distance * 0.9), projection)
+                .looking_at(Point3::new(0.0, 0.0, 0.0))
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchmarkReport {
+    pub cubes: usize,
+    pub frames: u32,
+    pub total: Duration,
+    /// What went into the last frame.
+    pub stats: FrameStats,
+    /// Bind groups created over all the timed frames, which should be none
+    /// once the first frame has cached them.
+    pub bind_groups_created: usize,
+}
+
+impl BenchmarkReport {
+    pub fn frame_time(&self) -> Duration {
+        self.total / self.frames.max(1)
+    }
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frame_time = self.frame_time();
+        write!(
+            f,
+            "{} frames of {} cubes in {:.2?}: {:.2?} per frame ({:.1} fps), {} draw calls and {} pipeline switches per frame, {} bind groups created",
+            self.frames,
+            self.cubes,
+            self.total,
+            frame_time,
+            1.0 / frame_time.as_secs_f64().max(f64::EPSILON),
+            self.stats.draw_calls,
+            self.stats.pipeline_switches,
+            self.bind_groups_created,
+        )
+    }
+}
+
+/// Replaces the scene with a grid of `cubes` and times drawing `frames` of
+/// it, as fast as the target presents them. Pipelines and bind groups are
+/// created in a frame before the clock starts.
+pub fn run(
+    ctx: &mut WgpuCtx<'_>,
+    cubes: usize,
+    frames: u32,
+) -> Result<BenchmarkReport, ContextError> {
+    cube_grid(ctx, cubes);
+    ctx.draw()?;
+    ctx.wait_idle();
+
+    let start = Instant::now();
+    let mut bind_groups_created = 0;
+    for _ in 0..frames {
+        ctx.draw()?;
+        bind_groups_created += ctx.frame_stats().bindings.bind_groups_created;
+    }
+    ctx.wait_idle();
+    Ok(BenchmarkReport {
+        cubes,
+        frames,
+        total: start.elapsed(),
+        stats: ctx.frame_stats(),
+        bind_groups_created,
+    })
+}
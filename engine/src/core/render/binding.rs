@@ -0,0 +1,324 @@
+//! Bind groups and their layouts, each created the first time its
+//! combination of resources is bound and shared from then on.
+
+use std::collections::HashMap;
+
+/// A resource bound to a group, at the binding of its index in the group's
+/// list. wgpu handles compare by identity, so a group binding a resource
+/// that has been replaced is never looked up again.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Binding {
+    /// The whole buffer.
+    Buffer(wgpu::Buffer),
+    TextureView(wgpu::TextureView),
+    Sampler(wgpu::Sampler),
+}
+
+impl Binding {
+    fn resource(&self) -> wgpu::BindingResource<'_> {
+        match self {
+            Binding::Buffer(buffer) => buffer.as_entire_binding(),
+            Binding::TextureView(view) => wgpu::BindingResource::TextureView(view),
+            Binding::Sampler(sampler) => wgpu::BindingResource::Sampler(sampler),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct BindGroupKey {
+    layout: wgpu::BindGroupLayout,
+    bindings: Vec<Binding>,
+}
+
+struct CachedBindGroup {
+    bind_group: wgpu::BindGroup,
+    last_used: u64,
+}
+
+/// What the cache did during a frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BindingStats {
+    pub layouts_created: usize,
+    pub bind_groups_created: usize,
+    /// Bind groups asked for that were already cached.
+    pub bind_groups_reused: usize,
+    /// Bind groups dropped at the end of the frame.
+    pub bind_groups_evicted: usize,
+    /// Bind groups dropped since the last frame because something they bind
+    /// was replaced.
+    pub bind_groups_invalidated: usize,
+    /// Bind groups left cached at the end of the frame.
+    pub bind_groups_cached: usize,
+}
+
+/// Owned by the render context. Cached bind groups hold on to what they
+/// bind, so the context invalidates the ones binding a resource it replaces,
+/// and ones that go unused for a while are dropped, letting resources
+/// nothing else holds be freed.
+#[derive(Default)]
+pub struct BindingCache {
+    layouts: HashMap<Vec<wgpu::BindGroupLayoutEntry>, wgpu::BindGroupLayout>,
+    bind_groups: HashMap<BindGroupKey, CachedBindGroup>,
+    frame: u64,
+    stats: BindingStats,
+}
+
+impl BindingCache {
+    /// Frames a bind group may go unused before it's dropped.
+    const MAX_IDLE_FRAMES: u64 = 120;
+
+    /// The layout with these entries, shared by everything asking for the
+    /// same ones. `label` names it if it's created.
+    pub fn layout(
+        &mut self,
+        device: &wgpu::Device,
+        label: &str,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> wgpu::BindGroupLayout {
+        if let Some(layout) = self.layouts.get(entries) {
+            return layout.clone();
+        }
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries,
+        });
+        self.layouts.insert(entries.to_vec(), layout.clone());
+        self.stats.layouts_created += 1;
+        layout
+    }
+
+    /// The bind group of `layout` binding `bindings`, created if it hasn't
+    /// been yet.
+    pub fn bind_group(
+        &mut self,
+        device: &wgpu::Device,
+        label: &str,
+        layout: &wgpu::BindGroupLayout,
+        bindings: &[Binding],
+    ) -> wgpu::BindGroup {
+        let key = BindGroupKey {
+            layout: layout.clone(),
+            bindings: bindings.to_vec(),
+        };
+        if let Some(cached) = self.bind_groups.get_mut(&key) {
+            cached.last_used = self.frame;
+            self.stats.bind_groups_reused += 1;
+            return cached.bind_group.clone();
+        }
+        let entries: Vec<_> = (bindings.iter().enumerate())
+            .map(|(binding, resource)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: resource.resource(),
+            })
+            .collect();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries: &entries,
+        });
+        self.bind_groups.insert(
+            key,
+            CachedBindGroup {
+                bind_group: bind_group.clone(),
+                last_used: self.frame,
+            },
+        );
+        self.stats.bind_groups_created += 1;
+        bind_group
+    }
+
+    /// Drops every bind group binding `binding`, for when it's been
+    /// replaced.
+    pub fn invalidate(&mut self, binding: &Binding) {
+        let before = self.bind_groups.len();
+        self.bind_groups
+            .retain(|key, _| !key.bindings.contains(binding));
+        self.stats.bind_groups_invalidated += before - self.bind_groups.len();
+    }
+
+    /// Drops bind groups that have gone unused for too long and returns
+    /// what happened during the frame.
+    pub fn end_frame(&mut self) -> BindingStats {
+        let frame = self.frame;
+        let before = self.bind_groups.len();
+        self.bind_groups
+            .retain(|_, cached| frame - cached.last_used <= Self::MAX_IDLE_FRAMES);
+        let stats = BindingStats {
+            bind_groups_evicted: before - self.bind_groups.len(),
+            bind_groups_cached: self.bind_groups.len(),
+            ..self.stats
+        };
+        self.stats = BindingStats::default();
+        self.frame += 1;
+        stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.bind_groups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bind_groups.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use image::{Rgba, RgbaImage};
+
+    use super::super::{
+        ctx::{Object, WgpuCtx},
+        material::{Material, MaterialParams},
+        texture::TextureOptions,
+    };
+    use super::BindingCache;
+
+    fn software_ctx(test: &str) -> Option<WgpuCtx<'static>> {
+        match WgpuCtx::new_software_blocking(64, 64) {
+            Ok(ctx) => Some(ctx),
+            Err(e) => {
+                eprintln!("Skipping {test} test: {e}");
+                None
+            }
+        }
+    }
+
+    /// Draws a frame of every object with `material`.
+    fn draw_with(ctx: &mut WgpuCtx<'_>, material: &Arc<Material>) {
+        for object in ctx.objects_mut() {
+            object.material = Some(material.clone());
+        }
+        ctx.draw().unwrap();
+    }
+
+    #[test]
+    fn bind_groups_are_reused_in_later_frames() {
+        let Some(mut ctx) = software_ctx("bind group reuse") else {
+            return;
+        };
+        ctx.draw().unwrap();
+        let first = ctx.frame_stats().bindings;
+        assert!(first.bind_groups_created > 0);
+
+        ctx.draw().unwrap();
+        let second = ctx.frame_stats().bindings;
+        assert_eq!(second.layouts_created, 0);
+        assert_eq!(second.bind_groups_created, 0);
+        assert_eq!(
+            second.bind_groups_reused,
+            first.bind_groups_created + first.bind_groups_reused
+        );
+        assert_eq!(second.bind_groups_cached, first.bind_groups_cached);
+
+        // A new material needs a group of its own, and the scene's is kept
+        let material = ctx.create_material(MaterialParams::default(), None, None);
+        for object in ctx.objects_mut() {
+            object.material = Some(material.clone());
+        }
+        ctx.draw().unwrap();
+        let third = ctx.frame_stats().bindings;
+        assert_eq!(third.bind_groups_created, 1);
+        assert_eq!(third.bind_groups_reused, 1);
+        assert_eq!(third.bind_groups_evicted, 0);
+        assert_eq!(third.bind_groups_cached, second.bind_groups_cached + 1);
+    }
+
+    #[test]
+    fn unused_bind_groups_are_evicted() {
+        let Some(mut ctx) = software_ctx("bind group eviction") else {
+            return;
+        };
+        ctx.draw().unwrap();
+        let cached = ctx.frame_stats().bindings.bind_groups_cached;
+
+        // The default material's group goes unused from here on
+        let material = ctx.create_material(MaterialParams::default(), None, None);
+        draw_with(&mut ctx, &material);
+        for _ in 1..BindingCache::MAX_IDLE_FRAMES {
+            ctx.draw().unwrap();
+            assert_eq!(ctx.frame_stats().bindings.bind_groups_evicted, 0);
+        }
+        ctx.draw().unwrap();
+        let stats = ctx.frame_stats().bindings;
+        assert_eq!(stats.bind_groups_evicted, 1);
+        // Replaced by the new material's
+        assert_eq!(stats.bind_groups_cached, cached);
+    }
+
+    #[test]
+    fn replacing_a_material_invalidates_its_bind_group() {
+        let Some(mut ctx) = software_ctx("material invalidation") else {
+            return;
+        };
+        let old = ctx.create_material(MaterialParams::default(), None, None);
+        draw_with(&mut ctx, &old);
+        ctx.draw().unwrap();
+        let settled = ctx.frame_stats().bindings;
+        assert_eq!(settled.bind_groups_created, 0);
+
+        let new = ctx.create_material(MaterialParams::default(), None, None);
+        ctx.replace_material(&old, new.clone());
+        assert!(ctx.objects().iter().all(|object| {
+            (object.material.as_ref()).is_some_and(|material| Arc::ptr_eq(material, &new))
+        }));
+        ctx.draw().unwrap();
+        let stats = ctx.frame_stats().bindings;
+        assert_eq!(stats.bind_groups_invalidated, 1);
+        assert_eq!(stats.bind_groups_created, 1);
+        assert_eq!(stats.bind_groups_cached, settled.bind_groups_cached);
+    }
+
+    #[test]
+    fn replacing_a_texture_invalidates_the_groups_sampling_it() {
+        let Some(mut ctx) = software_ctx("texture invalidation") else {
+            return;
+        };
+        let image = |value| RgbaImage::from_pixel(2, 2, Rgba([value, value, value, 255]));
+        let old = ctx.create_texture("old", &image(0), TextureOptions::COLOR);
+        let new = ctx.create_texture("new", &image(255), TextureOptions::COLOR);
+        let material = ctx.create_material(MaterialParams::default(), Some(old.clone()), None);
+        draw_with(&mut ctx, &material);
+        let before = ctx.frame_stats().bindings;
+
+        ctx.replace_texture(&old, &new);
+        let replacement = ctx.objects()[0].material.clone().unwrap();
+        assert!(!Arc::ptr_eq(&replacement, &material));
+        assert!(Arc::ptr_eq(replacement.albedo(), &new));
+        assert_eq!(replacement.params(), material.params());
+        ctx.draw().unwrap();
+        let stats = ctx.frame_stats().bindings;
+        assert_eq!(stats.bind_groups_invalidated, 1);
+        assert_eq!(stats.bind_groups_created, 1);
+        assert_eq!(stats.bind_groups_cached, before.bind_groups_cached);
+    }
+
+    #[test]
+    fn removing_a_mesh_invalidates_materials_left_unused() {
+        let Some(mut ctx) = software_ctx("mesh invalidation") else {
+            return;
+        };
+        let cube = ctx.meshes().find("cube").unwrap();
+        let sphere = ctx.meshes().find("sphere").unwrap();
+        let only_cube = ctx.create_material(MaterialParams::default(), None, None);
+        let shared = ctx.create_material(MaterialParams::default(), None, None);
+        *ctx.objects_mut() = [(cube, &only_cube), (cube, &shared), (sphere, &shared)]
+            .into_iter()
+            .map(|(mesh, material)| Object {
+                material: Some(material.clone()),
+                ..Object::from(mesh)
+            })
+            .collect();
+        ctx.draw().unwrap();
+        let before = ctx.frame_stats().bindings;
+
+        ctx.remove_mesh(cube);
+        assert_eq!(ctx.objects().len(), 1);
+        ctx.draw().unwrap();
+        let stats = ctx.frame_stats().bindings;
+        assert_eq!(stats.bind_groups_invalidated, 1);
+        assert_eq!(stats.bind_groups_created, 0);
+        assert_eq!(stats.bind_groups_cached, before.bind_groups_cached - 1);
+    }
+}
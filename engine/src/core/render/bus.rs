@@ -0,0 +1,328 @@
+//! Requests from other threads, such as the REPL's, to whichever loop owns
+//! the renderer. A window's event loop is woken up by each request through
+//! its proxy, and the headless simulation checks for requests every tick.
+//! Senders block until the renderer replies. The renderer can't reach the
+//! senders otherwise, so what it does on its own is left as notices for
+//! them to pick up.
+
+use std::{
+    fmt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+    time::Duration,
+};
+
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use parking_lot::Mutex;
+use thiserror::Error;
+use winit::{dpi::PhysicalSize, event_loop::EventLoopProxy, window::Window};
+
+use super::{
+    bench::{self, BenchmarkReport},
+    camera::{Projection, View, controller::ControllerKind},
+    ctx::{ContextError, Object, SceneReset, WgpuCtx},
+    material::MaterialParams,
+};
+
+/// How long to wait for the renderer to reply.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Benchmarks draw many frames before replying.
+const BENCHMARK_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug)]
+pub enum RenderRequest {
+    /// Saves the next frame as a PNG.
+    Screenshot(PathBuf),
+    SetClearColor(wgpu::Color),
+    /// Resizes the window, or the offscreen target when there's no window.
+    Resize {
+        width: u32,
+        height: u32,
+    },
+    /// Draws a registered mesh, in a colour of its own if one is given.
+    Spawn {
+        mesh: String,
+        color: Option<[f32; 4]>,
+    },
+    /// Changes how the first view's camera is moved and projected. `None`
+    /// leaves the controller as it is, and `Some(None)` removes it.
+    Camera {
+        controller: Option<Option<ControllerKind>>,
+        projection: Option<Projection>,
+    },
+    /// Replaces the scene with a grid of cubes and times drawing it.
+    Benchmark {
+        cubes: usize,
+        frames: u32,
+    },
+    /// Closes the window, which ends its event loop.
+    Close,
+}
+
+impl RenderRequest {
+    fn reply_timeout(&self) -> Duration {
+        match self {
+            RenderRequest::Benchmark { .. } => BENCHMARK_TIMEOUT,
+            _ => REPLY_TIMEOUT,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RenderReply {
+    Done,
+    /// The index of the new object.
+    Spawned(usize),
+    Benchmark(BenchmarkReport),
+}
+
+#[derive(Debug, Error)]
+pub enum RequestError {
+    #[error("Nothing is rendering. Open a window, or run headless with --offscreen")]
+    NoRenderer,
+    #[error("The renderer stopped before replying")]
+    Disconnected,
+    #[error("Timed out waiting for the renderer")]
+    Timeout,
+    #[error("No mesh named '{0}'")]
+    UnknownMesh(String),
+    #[error(transparent)]
+    Context(#[from] ContextError),
+}
+
+/// Something the renderer did without being asked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notice {
+    /// The device was lost and the renderer rebuilt, starting the scene
+    /// over.
+    SceneReset(SceneReset),
+}
+
+impl fmt::Display for Notice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Notice::SceneReset(reset) => write!(f, "The renderer was rebuilt. {reset}"),
+        }
+    }
+}
+
+/// A request on its way to the renderer, with where to send the reply.
+pub struct RenderMessage {
+    request: RenderRequest,
+    reply: mpsc::Sender<Result<RenderReply, RequestError>>,
+}
+
+impl RenderMessage {
+    pub fn request(&self) -> &RenderRequest {
+        &self.request
+    }
+
+    /// Carries out the request and replies to the sender. Without a window,
+    /// resizing applies to the context's own target.
+    pub fn handle(self, ctx: &mut WgpuCtx<'_>, window: Option<&Window>) {
+        debug!("Handling {:?}", self.request);
+        let result = match self.request {
+            RenderRequest::Screenshot(path) => ctx
+                .save_screenshot(&path)
+                .map(|()| RenderReply::Done)
+                .map_err(RequestError::from),
+            RenderRequest::SetClearColor(color) => {
+                ctx.set_clear_color(color);
+                Ok(RenderReply::Done)
+            }
+            RenderRequest::Resize { width, height } => {
+                let size = PhysicalSize::new(width.max(1), height.max(1));
+                match window {
+                    // Otherwise the window is resized later, if at all, and
+                    // the context follows when it's told about it
+                    Some(window) => {
+                        if let Some(size) = window.request_inner_size(size) {
+                            ctx.resize(size.into());
+                        }
+                    }
+                    None => ctx.resize(size.into()),
+                }
+                Ok(RenderReply::Done)
+            }
+            RenderRequest::Spawn { mesh, color } => match ctx.meshes().find(&mesh) {
+                Some(id) => {
+                    let material = color.map(|base_color| {
+                        let params = MaterialParams {
+                            base_color,
+                            ..MaterialParams::default()
+                        };
+                        ctx.create_material(params, None, None)
+                    });
+                    ctx.objects_mut().push(Object {
+                        material,
+                        ..Object::from(id)
+                    });
+                    Ok(RenderReply::Spawned(ctx.objects().len() - 1))
+                }
+                None => Err(RequestError::UnknownMesh(mesh)),
+            },
+            RenderRequest::Camera {
+                controller,
+                projection,
+            } => {
+                if ctx.views().is_empty() {
+                    ctx.add_view(View::default());
+                }
+                let view = &mut ctx.views_mut()[0];
+                if let Some(controller) = controller {
+                    view.controller = controller.map(ControllerKind::create);
+                }
+                if let Some(projection) = projection {
+                    view.camera.projection = projection;
+                }
+                Ok(RenderReply::Done)
+            }
+            RenderRequest::Benchmark { cubes, frames } => bench::run(ctx, cubes, frames)
+                .map(RenderReply::Benchmark)
+                .map_err(RequestError::from),
+            // The window's event loop closes it before it gets here, and
+            // offscreen targets have nothing to close
+            RenderRequest::Close => Ok(RenderReply::Done),
+        };
+        // The sender may have timed out and stopped listening
+        let _ = self.reply.send(result);
+    }
+
+    /// Replies that the request was carried out by whoever received it.
+    pub fn done(self) {
+        let _ = self.reply.send(Ok(RenderReply::Done));
+    }
+
+    /// Replies without carrying out the request.
+    pub fn fail(self, error: RequestError) {
+        let _ = self.reply.send(Err(error));
+    }
+}
+
+enum Endpoint {
+    EventLoop(EventLoopProxy<RenderMessage>),
+    Channel(mpsc::Sender<RenderMessage>),
+}
+
+lazy_static! {
+    /// The endpoint requests go to, with the id of the connection that set
+    /// it.
+    static ref ENDPOINT: Mutex<Option<(u64, Endpoint)>> = Mutex::new(None);
+    static ref NOTICES: Mutex<Vec<Notice>> = Mutex::new(Vec::new());
+}
+
+static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(0);
+
+/// Held by tests that connect to the bus, since it's shared by the whole
+/// process.
+#[cfg(test)]
+pub static CONNECTION_LOCK: Mutex<()> = Mutex::new(());
+
+/// Held by the loop that owns the renderer. Requests go to the most recent
+/// connection until it's dropped. Dropping a connection that has since
+/// been replaced leaves the newer one in place.
+pub struct Connection {
+    id: u64,
+}
+
+impl Connection {
+    fn register(endpoint: Endpoint) -> Connection {
+        let id = NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed);
+        *ENDPOINT.lock() = Some((id, endpoint));
+        Connection { id }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let mut endpoint = ENDPOINT.lock();
+        if endpoint.as_ref().is_some_and(|(id, _)| *id == self.id) {
+            *endpoint = None;
+        }
+    }
+}
+
+/// Sends requests to a winit event loop as user events.
+pub fn connect_event_loop(proxy: EventLoopProxy<RenderMessage>) -> Connection {
+    Connection::register(Endpoint::EventLoop(proxy))
+}
+
+/// Sends requests down a channel, for loops without an event loop to wake.
+pub fn connect_channel() -> (Connection, mpsc::Receiver<RenderMessage>) {
+    let (sender, receiver) = mpsc::channel();
+    (Connection::register(Endpoint::Channel(sender)), receiver)
+}
+
+/// Sends a request to the renderer and waits for its reply.
+pub fn request(request: RenderRequest) -> Result<RenderReply, RequestError> {
+    let (reply, receiver) = mpsc::channel();
+    let timeout = request.reply_timeout();
+    let message = RenderMessage { request, reply };
+    match ENDPOINT.lock().as_ref().map(|(_, endpoint)| endpoint) {
+        Some(Endpoint::EventLoop(proxy)) => proxy
+            .send_event(message)
+            .map_err(|_| RequestError::Disconnected)?,
+        Some(Endpoint::Channel(sender)) => sender
+            .send(message)
+            .map_err(|_| RequestError::Disconnected)?,
+        None => return Err(RequestError::NoRenderer),
+    }
+    receiver.recv_timeout(timeout).map_err(|e| match e {
+        mpsc::RecvTimeoutError::Timeout => RequestError::Timeout,
+        mpsc::RecvTimeoutError::Disconnected => RequestError::Disconnected,
+    })?
+}
+
+/// Leaves a notice for the next [`take_notices`], and logs it.
+pub fn notify(notice: Notice) {
+    warn!("{notice}");
+    NOTICES.lock().push(notice);
+}
+
+/// The notices left since the last call, oldest first.
+pub fn take_notices() -> Vec<Notice> {
+    std::mem::take(&mut *NOTICES.lock())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connected() -> Option<u64> {
+        ENDPOINT.lock().as_ref().map(|(id, _)| *id)
+    }
+
+    #[test]
+    fn dropping_a_replaced_connection_keeps_the_newer_one() {
+        let _lock = CONNECTION_LOCK.lock();
+        let (old, _old_receiver) = connect_channel();
+        let (new, _new_receiver) = connect_channel();
+        let new_id = new.id;
+        drop(old);
+        assert_eq!(connected(), Some(new_id));
+        drop(new);
+        assert_eq!(connected(), None);
+        assert!(matches!(
+            request(RenderRequest::SetClearColor(wgpu::Color::BLACK)),
+            Err(RequestError::NoRenderer)
+        ));
+    }
+
+    #[test]
+    fn notices_are_taken_once() {
+        let reset = |objects| {
+            Notice::SceneReset(SceneReset {
+                objects,
+                ..SceneReset::default()
+            })
+        };
+        notify(reset(1));
+        notify(reset(2));
+        assert_eq!(take_notices(), [reset(1), reset(2)]);
+        assert_eq!(take_notices(), []);
+    }
+}
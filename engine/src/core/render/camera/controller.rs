@@ -0,0 +1,514 @@
+//! Controllers that move a camera from keyboard and mouse input.
+
+use std::collections::HashSet;
+
+use cgmath::{Deg, EuclideanSpace, InnerSpace, Point3, Rad, Vector2, Vector3};
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+use super::{Camera, Projection};
+
+/// Pixels of a touchpad scroll that count as one line of a mouse wheel.
+const PIXELS_PER_LINE: f32 = 40.0;
+/// How far the camera may pitch up or down, short of straight up so its
+/// yaw stays meaningful.
+const MAX_PITCH: Deg<f32> = Deg(89.0);
+
+/// The keys and buttons held down, and how the mouse moved since the last
+/// frame.
+#[derive(Debug)]
+pub struct InputState {
+    keys: HashSet<KeyCode>,
+    buttons: HashSet<MouseButton>,
+    cursor: Option<Vector2<f32>>,
+    cursor_delta: Vector2<f32>,
+    scroll: f32,
+    window_size: (u32, u32),
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        InputState {
+            keys: HashSet::new(),
+            buttons: HashSet::new(),
+            cursor: None,
+            cursor_delta: Vector2::new(0.0, 0.0),
+            scroll: 0.0,
+            window_size: (0, 0),
+        }
+    }
+}
+
+impl InputState {
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                if let PhysicalKey::Code(code) = event.physical_key {
+                    match event.state {
+                        ElementState::Pressed => self.keys.insert(code),
+                        ElementState::Released => self.keys.remove(&code),
+                    };
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                match state {
+                    ElementState::Pressed => self.buttons.insert(*button),
+                    ElementState::Released => self.buttons.remove(button),
+                };
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = Vector2::new(position.x as f32, position.y as f32);
+                if let Some(cursor) = self.cursor {
+                    self.cursor_delta += position - cursor;
+                }
+                self.cursor = Some(position);
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor = None,
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, lines) => *lines,
+                    MouseScrollDelta::PixelDelta(pixels) => pixels.y as f32 / PIXELS_PER_LINE,
+                };
+            }
+            WindowEvent::Resized(size) => self.set_window_size(*size),
+            // Releases that happen elsewhere never arrive
+            WindowEvent::Focused(false) => {
+                self.keys.clear();
+                self.buttons.clear();
+            }
+            _ => {}
+        }
+    }
+
+    pub fn set_window_size(&mut self, size: PhysicalSize<u32>) {
+        self.window_size = (size.width, size.height);
+    }
+
+    /// Forgets the movement since the last frame. Meant to be called once
+    /// the controllers have been updated.
+    pub fn end_frame(&mut self) {
+        self.cursor_delta = Vector2::new(0.0, 0.0);
+        self.scroll = 0.0;
+    }
+
+    pub fn is_key_down(&self, key: KeyCode) -> bool {
+        self.keys.contains(&key)
+    }
+
+    pub fn is_button_down(&self, button: MouseButton) -> bool {
+        self.buttons.contains(&button)
+    }
+
+    /// How far the cursor moved in pixels, with +Y down.
+    pub fn cursor_delta(&self) -> Vector2<f32> {
+        self.cursor_delta
+    }
+
+    /// Lines scrolled, positive away from the user.
+    pub fn scroll(&self) -> f32 {
+        self.scroll
+    }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        self.window_size
+    }
+
+    /// +1, -1 or 0 depending on which of two opposing keys are held.
+    fn axis(&self, positive: &[KeyCode], negative: &[KeyCode]) -> f32 {
+        let held = |keys: &[KeyCode]| keys.iter().any(|&key| self.is_key_down(key));
+        f32::from(u8::from(held(positive))) - f32::from(u8::from(held(negative)))
+    }
+}
+
+/// Moves a camera each frame from the input.
+pub trait CameraController: Send {
+    /// `dt` is the time since the last update in seconds.
+    fn update(&mut self, camera: &mut Camera, input: &InputState, dt: f32);
+}
+
+/// The controllers that can be picked by name, e.g. from the REPL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerKind {
+    Fly,
+    Orbit,
+    PanZoom,
+}
+
+impl ControllerKind {
+    pub fn from_name(name: &str) -> Option<ControllerKind> {
+        match name {
+            "fly" => Some(ControllerKind::Fly),
+            "orbit" => Some(ControllerKind::Orbit),
+            "pan" => Some(ControllerKind::PanZoom),
+            _ => None,
+        }
+    }
+
+    pub fn create(self) -> Box<dyn CameraController> {
+        match self {
+            ControllerKind::Fly => Box::new(FlyController::default()),
+            ControllerKind::Orbit => Box::new(OrbitController::default()),
+            ControllerKind::PanZoom => Box::new(PanZoomController::default()),
+        }
+    }
+}
+
+/// Turns `camera` by a mouse movement, keeping it from tipping over.
+fn turn(camera: &mut Camera, delta: Vector2<f32>, sensitivity: f32) {
+    let (yaw, pitch) = camera.yaw_pitch();
+    let max_pitch = Rad::from(MAX_PITCH);
+    let pitch = Rad((pitch.0 - delta.y * sensitivity).clamp(-max_pitch.0, max_pitch.0));
+    camera.set_yaw_pitch(yaw - Rad(delta.x * sensitivity), pitch);
+}
+
+/// Looks around while the right mouse button is held and moves with WASD,
+/// E or Space to go up and Q or Ctrl to go down. Shift moves faster.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlyController {
+    /// Units per second.
+    pub speed: f32,
+    pub fast_multiplier: f32,
+    /// Radians per pixel.
+    pub sensitivity: f32,
+}
+
+impl Default for FlyController {
+    fn default() -> Self {
+        FlyController {
+            speed: 3.0,
+            fast_multiplier: 4.0,
+            sensitivity: 0.004,
+        }
+    }
+}
+
+impl CameraController for FlyController {
+    fn update(&mut self, camera: &mut Camera, input: &InputState, dt: f32) {
+        if input.is_button_down(MouseButton::Right) {
+            turn(camera, input.cursor_delta(), self.sensitivity);
+        }
+
+        let forward = input.axis(&[KeyCode::KeyW], &[KeyCode::KeyS]);
+        let right = input.axis(&[KeyCode::KeyD], &[KeyCode::KeyA]);
+        let up = input.axis(
+            &[KeyCode::KeyE, KeyCode::Space],
+            &[KeyCode::KeyQ, KeyCode::ControlLeft],
+        );
+        let direction =
+            camera.forward() * forward + camera.right() * right + Vector3::unit_y() * up;
+        if direction.magnitude2() > 0.0 {
+            let fast =
+                input.is_key_down(KeyCode::ShiftLeft) || input.is_key_down(KeyCode::ShiftRight);
+            let speed = if fast {
+                self.speed * self.fast_multiplier
+            } else {
+                self.speed
+            };
+            camera.position += direction.normalize() * speed * dt;
+        }
+    }
+}
+
+/// Circles a target while the left mouse button is held, zooms with the
+/// wheel and moves the target with the middle button.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrbitController {
+    pub target: Point3<f32>,
+    /// Radians per pixel.
+    pub sensitivity: f32,
+    /// Fraction of the distance each line scrolled moves the camera.
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        OrbitController {
+            target: Point3::new(0.0, 0.0, 0.0),
+            sensitivity: 0.006,
+            zoom_speed: 0.1,
+            min_distance: 0.1,
+        }
+    }
+}
+
+impl CameraController for OrbitController {
+    fn update(&mut self, camera: &mut Camera, input: &InputState, _dt: f32) {
+        let mut distance = (camera.position - self.target).magnitude();
+        camera.look_at(self.target);
+        let delta = input.cursor_delta();
+        if input.is_button_down(MouseButton::Left) {
+            turn(camera, delta, self.sensitivity);
+        }
+        if input.is_button_down(MouseButton::Middle) {
+            let (_, height) = input.window_size();
+            let scale = camera.units_per_pixel(distance, height as f32);
+            self.target += (camera.up() * delta.y - camera.right() * delta.x) * scale;
+        }
+        distance = (distance * (1.0 - self.zoom_speed).powf(input.scroll())).max(self.min_distance);
+        camera.position = self.target - camera.forward() * distance;
+    }
+}
+
+/// For 2D scenes. Drags the view with the left or middle mouse button and
+/// zooms with the wheel, shrinking what an orthographic camera shows or
+/// moving a perspective one forward.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PanZoomController {
+    /// Fraction of the view each line scrolled zooms by.
+    pub zoom_speed: f32,
+    /// Bounds on an orthographic camera's height.
+    pub min_height: f32,
+    pub max_height: f32,
+}
+
+impl Default for PanZoomController {
+    fn default() -> Self {
+        PanZoomController {
+            zoom_speed: 0.1,
+            min_height: 0.01,
+            max_height: 1000.0,
+        }
+    }
+}
+
+impl CameraController for PanZoomController {
+    fn update(&mut self, camera: &mut Camera, input: &InputState, _dt: f32) {
+        // How far perspective cameras pan and zoom scales with their
+        // distance from the origin
+        let distance = camera.position.to_vec().magnitude();
+        if input.is_button_down(MouseButton::Left) || input.is_button_down(MouseButton::Middle) {
+            let (_, height) = input.window_size();
+            let scale = camera.units_per_pixel(distance, height as f32);
+            let delta = input.cursor_delta();
+            camera.position += (camera.up() * delta.y - camera.right() * delta.x) * scale;
+        }
+
+        let zoom = (1.0 - self.zoom_speed).powf(input.scroll());
+        match &mut camera.projection {
+            Projection::Orthographic { height, .. } => {
+                *height = (*height * zoom).clamp(self.min_height, self.max_height);
+            }
+            Projection::Perspective { .. } => {
+                camera.position += camera.forward() * distance * (1.0 - zoom);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, assert_abs_diff_eq};
+    use winit::{dpi::PhysicalPosition, event::DeviceId, event::TouchPhase};
+
+    use super::*;
+
+    fn cursor_moved(x: f64, y: f64) -> WindowEvent {
+        WindowEvent::CursorMoved {
+            device_id: DeviceId::dummy(),
+            position: PhysicalPosition::new(x, y),
+        }
+    }
+
+    fn mouse(button: MouseButton, state: ElementState) -> WindowEvent {
+        WindowEvent::MouseInput {
+            device_id: DeviceId::dummy(),
+            state,
+            button,
+        }
+    }
+
+    fn wheel(lines: f32) -> WindowEvent {
+        WindowEvent::MouseWheel {
+            device_id: DeviceId::dummy(),
+            delta: MouseScrollDelta::LineDelta(0.0, lines),
+            phase: TouchPhase::Moved,
+        }
+    }
+
+    /// An 800x600 window with `button` held while the cursor moves by
+    /// `(dx, dy)`.
+    fn dragging(button: MouseButton, dx: f64, dy: f64) -> InputState {
+        let mut input = InputState::default();
+        input.handle_event(&WindowEvent::Resized(PhysicalSize::new(800, 600)));
+        input.handle_event(&mouse(button, ElementState::Pressed));
+        input.handle_event(&cursor_moved(400.0, 300.0));
+        input.handle_event(&cursor_moved(400.0 + dx, 300.0 + dy));
+        input
+    }
+
+    fn holding(keys: &[KeyCode]) -> InputState {
+        let mut input = InputState::default();
+        input.keys.extend(keys);
+        input
+    }
+
+    #[test]
+    fn input_collects_movement_until_the_frame_ends() {
+        let mut input = dragging(MouseButton::Right, 10.0, -4.0);
+        input.handle_event(&cursor_moved(420.0, 290.0));
+        input.handle_event(&wheel(1.0));
+        input.handle_event(&WindowEvent::MouseWheel {
+            device_id: DeviceId::dummy(),
+            delta: MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, 20.0)),
+            phase: TouchPhase::Moved,
+        });
+        assert_eq!(input.cursor_delta(), Vector2::new(20.0, -10.0));
+        assert_eq!(input.scroll(), 1.5);
+        assert_eq!(input.window_size(), (800, 600));
+        assert!(input.is_button_down(MouseButton::Right));
+
+        input.end_frame();
+        assert_eq!(input.cursor_delta(), Vector2::new(0.0, 0.0));
+        assert_eq!(input.scroll(), 0.0);
+        // Held buttons stay held
+        assert!(input.is_button_down(MouseButton::Right));
+    }
+
+    #[test]
+    fn losing_focus_releases_everything() {
+        let mut input = dragging(MouseButton::Left, 0.0, 0.0);
+        input.keys.insert(KeyCode::KeyW);
+        input.handle_event(&WindowEvent::Focused(false));
+        assert!(!input.is_button_down(MouseButton::Left));
+        assert!(!input.is_key_down(KeyCode::KeyW));
+    }
+
+    #[test]
+    fn fly_moves_along_where_the_camera_looks() {
+        let mut fly = FlyController::default();
+        let mut camera = Camera::default();
+        fly.update(&mut camera, &holding(&[KeyCode::KeyW]), 0.5);
+        assert_abs_diff_eq!(camera.position, Point3::new(0.0, 0.0, 1.5), epsilon = 1e-5);
+
+        // Opposing keys cancel out, and up is always world up
+        let input = holding(&[KeyCode::KeyA, KeyCode::KeyD, KeyCode::Space]);
+        fly.update(&mut camera, &input, 0.5);
+        assert_abs_diff_eq!(camera.position, Point3::new(0.0, 1.5, 1.5), epsilon = 1e-5);
+
+        // Diagonals are no faster, and shift is
+        let input = holding(&[KeyCode::KeyS, KeyCode::KeyD, KeyCode::ShiftLeft]);
+        fly.update(&mut camera, &input, 0.5);
+        let step = 3.0 * 4.0 * 0.5 / 2.0_f32.sqrt();
+        assert_abs_diff_eq!(
+            camera.position,
+            Point3::new(step, 1.5, 1.5 + step),
+            epsilon = 1e-4
+        );
+    }
+
+    #[test]
+    fn fly_only_turns_while_the_right_button_is_held() {
+        let mut fly = FlyController::default();
+        let mut camera = Camera::default();
+        fly.update(&mut camera, &dragging(MouseButton::Left, 100.0, 0.0), 0.1);
+        assert_eq!(camera, Camera::default());
+
+        fly.update(&mut camera, &dragging(MouseButton::Right, 100.0, 0.0), 0.1);
+        let (yaw, pitch) = camera.yaw_pitch();
+        assert_abs_diff_eq!(yaw.0, -0.4, epsilon = 1e-5);
+        assert_abs_diff_eq!(pitch.0, 0.0, epsilon = 1e-5);
+        assert_eq!(camera.position, Camera::default().position);
+
+        // Pitch stops short of straight up
+        fly.update(
+            &mut camera,
+            &dragging(MouseButton::Right, 0.0, -10_000.0),
+            0.1,
+        );
+        let (_, pitch) = camera.yaw_pitch();
+        assert_abs_diff_eq!(pitch.0, Rad::from(MAX_PITCH).0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn orbit_keeps_its_distance_while_circling() {
+        let mut orbit = OrbitController::default();
+        let mut camera = Camera::default();
+        // Dragging a quarter turn to the left brings the camera round to +X
+        let quarter = Rad::from(Deg(90.0)).0 / orbit.sensitivity;
+        orbit.update(
+            &mut camera,
+            &dragging(MouseButton::Left, -quarter as f64, 0.0),
+            0.1,
+        );
+        assert_abs_diff_eq!(camera.position, Point3::new(3.0, 0.0, 0.0), epsilon = 1e-3);
+        assert_abs_diff_eq!(camera.forward(), -Vector3::unit_x(), epsilon = 1e-3);
+    }
+
+    #[test]
+    fn orbit_zooms_towards_its_target() {
+        let mut orbit = OrbitController {
+            target: Point3::new(0.0, 1.0, 0.0),
+            ..OrbitController::default()
+        };
+        let mut camera = Camera::new(Point3::new(0.0, 1.0, 4.0), Projection::default());
+        let mut input = InputState::default();
+        input.handle_event(&wheel(2.0));
+        orbit.update(&mut camera, &input, 0.1);
+        assert_abs_diff_eq!(
+            camera.position,
+            Point3::new(0.0, 1.0, 4.0 * 0.81),
+            epsilon = 1e-4
+        );
+
+        // But no closer than the minimum
+        input.handle_event(&wheel(1000.0));
+        orbit.update(&mut camera, &input, 0.1);
+        assert_abs_diff_eq!(camera.position, Point3::new(0.0, 1.0, 0.1), epsilon = 1e-4);
+    }
+
+    #[test]
+    fn orbit_pans_its_target_with_the_middle_button() {
+        let mut orbit = OrbitController::default();
+        let mut camera = Camera::new(Point3::new(0.0, 0.0, 5.0), Projection::orthographic(6.0));
+        // 6 units over 600 pixels, the target follows the cursor
+        orbit.update(
+            &mut camera,
+            &dragging(MouseButton::Middle, 100.0, 50.0),
+            0.1,
+        );
+        assert_abs_diff_eq!(orbit.target, Point3::new(-1.0, 0.5, 0.0), epsilon = 1e-4);
+        assert_abs_diff_eq!(camera.position, Point3::new(-1.0, 0.5, 5.0), epsilon = 1e-4);
+    }
+
+    #[test]
+    fn pan_zoom_drags_and_zooms_an_orthographic_view() {
+        let mut pan = PanZoomController::default();
+        let mut camera = Camera::new(Point3::new(0.0, 0.0, 5.0), Projection::orthographic(6.0));
+        pan.update(
+            &mut camera,
+            &dragging(MouseButton::Left, -200.0, 100.0),
+            0.1,
+        );
+        assert_abs_diff_eq!(camera.position, Point3::new(2.0, 1.0, 5.0), epsilon = 1e-4);
+
+        let mut input = InputState::default();
+        input.handle_event(&wheel(1.0));
+        pan.update(&mut camera, &input, 0.1);
+        let Projection::Orthographic { height, .. } = camera.projection else {
+            panic!("{:?}", camera.projection);
+        };
+        assert_abs_diff_eq!(height, 5.4, epsilon = 1e-5);
+        // Zooming leaves the position alone
+        assert_abs_diff_eq!(camera.position, Point3::new(2.0, 1.0, 5.0), epsilon = 1e-4);
+
+        // Within bounds
+        input.handle_event(&wheel(-1000.0));
+        pan.update(&mut camera, &input, 0.1);
+        assert_eq!(camera.projection, Projection::orthographic(pan.max_height));
+    }
+
+    #[test]
+    fn pan_zoom_moves_a_perspective_camera_forward() {
+        let mut pan = PanZoomController::default();
+        let mut camera = Camera::new(Point3::new(0.0, 0.0, 10.0), Projection::default());
+        let mut input = InputState::default();
+        input.handle_event(&wheel(1.0));
+        pan.update(&mut camera, &input, 0.1);
+        assert_abs_diff_eq!(camera.position, Point3::new(0.0, 0.0, 9.0), epsilon = 1e-4);
+        assert_eq!(camera.projection, Projection::default());
+    }
+}
@@ -0,0 +1,324 @@
+//! Cameras, and the views that decide where on screen, or into which
+//! texture, each one is drawn.
+
+use std::sync::Arc;
+
+use cgmath::{
+    Angle, Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, Quaternion, Rad, Rotation, Rotation3,
+    Vector3, ortho, perspective,
+};
+
+use self::controller::CameraController;
+use super::texture::RenderTexture;
+
+pub mod controller;
+
+/// cgmath's projections put depth between -1 and 1, like OpenGL, while wgpu
+/// expects it between 0 and 1.
+#[rustfmt::skip]
+const OPENGL_TO_WGPU: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// `fov_y` is the vertical field of view.
+    Perspective {
+        fov_y: Rad<f32>,
+        near: f32,
+        far: f32,
+    },
+    /// `height` is how many world units fit vertically in the view.
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+impl Projection {
+    pub fn perspective(fov_y: impl Into<Rad<f32>>) -> Projection {
+        Projection::Perspective {
+            fov_y: fov_y.into(),
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+
+    pub fn orthographic(height: f32) -> Projection {
+        Projection::Orthographic {
+            height,
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+
+    /// `aspect` is the width of the view over its height.
+    pub fn matrix(&self, aspect: f32) -> Matrix4<f32> {
+        let projection = match *self {
+            Projection::Perspective { fov_y, near, far } => perspective(fov_y, aspect, near, far),
+            Projection::Orthographic { height, near, far } => {
+                let (half_width, half_height) = (height * aspect / 2.0, height / 2.0);
+                ortho(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    near,
+                    far,
+                )
+            }
+        };
+        OPENGL_TO_WGPU * projection
+    }
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::perspective(Deg(45.0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub position: Point3<f32>,
+    /// Turns the camera from looking down -Z, with +Y up, to where it
+    /// looks.
+    pub rotation: Quaternion<f32>,
+    pub projection: Projection,
+    /// Width over height of what the camera draws into. The context keeps
+    /// it up to date for its views.
+    pub aspect: f32,
+}
+
+impl Camera {
+    pub fn new(position: Point3<f32>, projection: Projection) -> Camera {
+        Camera {
+            position,
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            projection,
+            aspect: 1.0,
+        }
+    }
+
+    /// Turns the camera towards `target`, keeping it upright.
+    pub fn look_at(&mut self, target: Point3<f32>) {
+        let direction = target - self.position;
+        if direction.magnitude2() > 0.0 {
+            let direction = direction.normalize();
+            let yaw = Rad((-direction.x).atan2(-direction.z));
+            let pitch = Rad(direction.y.clamp(-1.0, 1.0).asin());
+            self.set_yaw_pitch(yaw, pitch);
+        }
+    }
+
+    /// Builder form of [`Camera::look_at`].
+    pub fn looking_at(mut self, target: Point3<f32>) -> Camera {
+        self.look_at(target);
+        self
+    }
+
+    /// Turns the camera `yaw` around +Y from looking down -Z, then tilts it
+    /// `pitch` up.
+    pub fn set_yaw_pitch(&mut self, yaw: Rad<f32>, pitch: Rad<f32>) {
+        self.rotation = Quaternion::from_angle_y(yaw) * Quaternion::from_angle_x(pitch);
+    }
+
+    /// The inverse of [`Camera::set_yaw_pitch`]. Any roll is lost.
+    pub fn yaw_pitch(&self) -> (Rad<f32>, Rad<f32>) {
+        let forward = self.forward();
+        (
+            Rad((-forward.x).atan2(-forward.z)),
+            Rad(forward.y.clamp(-1.0, 1.0).asin()),
+        )
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        self.rotation.rotate_vector(-Vector3::unit_z())
+    }
+
+    pub fn right(&self) -> Vector3<f32> {
+        self.rotation.rotate_vector(Vector3::unit_x())
+    }
+
+    pub fn up(&self) -> Vector3<f32> {
+        self.rotation.rotate_vector(Vector3::unit_y())
+    }
+
+    /// World space to camera space.
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from(self.rotation.invert()) * Matrix4::from_translation(-self.position.to_vec())
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        self.projection.matrix(self.aspect)
+    }
+
+    pub fn view_projection(&self) -> Matrix4<f32> {
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    /// How many world units a pixel covers at `distance` in front of the
+    /// camera, when the view is `pixels` tall.
+    pub fn units_per_pixel(&self, distance: f32, pixels: f32) -> f32 {
+        let extent = match self.projection {
+            Projection::Perspective { fov_y, .. } => 2.0 * distance * (fov_y / 2.0).tan(),
+            Projection::Orthographic { height, .. } => height,
+        };
+        extent / pixels.max(1.0)
+    }
+}
+
+impl Default for Camera {
+    /// Three units back from the origin, looking at it.
+    fn default() -> Self {
+        Camera::new(Point3::new(0.0, 0.0, 3.0), Projection::default())
+    }
+}
+
+/// The part of a target a view draws into, as fractions of its size so it
+/// follows resizes. The origin is the top left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub const FULL: Viewport = Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    /// The viewport in pixels of a `size` target, as x, y, width and height.
+    /// It's kept inside the target and at least a pixel across.
+    pub fn pixels(&self, size: (u32, u32)) -> [u32; 4] {
+        let (width, height) = size;
+        let scale = |fraction: f32, length: u32| {
+            ((fraction.clamp(0.0, 1.0) * length as f32).round() as u32).min(length - 1)
+        };
+        let x = scale(self.x, width);
+        let y = scale(self.y, height);
+        let w = ((self.width * width as f32).round() as u32).clamp(1, width - x);
+        let h = ((self.height * height as f32).round() as u32).clamp(1, height - y);
+        [x, y, w, h]
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport::FULL
+    }
+}
+
+/// What a view draws into.
+#[derive(Clone, Default)]
+pub enum ViewTarget {
+    /// The window, or the context's offscreen target.
+    #[default]
+    Frame,
+    /// Drawn before any view of the frame, so the texture can be shown in
+    /// it. Objects with a material that samples the texture aren't drawn
+    /// into it.
+    Texture(Arc<RenderTexture>),
+}
+
+/// A camera, and where it's drawn. Views drawing into the same target after
+/// the first don't clear it, so overlapping ones show what's beneath them
+/// through their background.
+#[derive(Default)]
+pub struct View {
+    pub camera: Camera,
+    pub viewport: Viewport,
+    pub target: ViewTarget,
+    /// Moves the camera each frame, following the input.
+    pub controller: Option<Box<dyn CameraController>>,
+}
+
+impl View {
+    pub fn new(camera: Camera) -> View {
+        View {
+            camera,
+            ..View::default()
+        }
+    }
+
+    pub fn with_viewport(mut self, viewport: Viewport) -> View {
+        self.viewport = viewport;
+        self
+    }
+
+    pub fn with_target(mut self, target: ViewTarget) -> View {
+        self.target = target;
+        self
+    }
+
+    pub fn with_controller(mut self, controller: impl CameraController + 'static) -> View {
+        self.controller = Some(Box::new(controller));
+        self
+    }
+
+    /// Matches the camera's aspect to the viewport, for a target of `size`.
+    pub fn fit(&mut self, size: (u32, u32)) {
+        let [_, _, width, height] = self.viewport.pixels(size);
+        self.camera.aspect = width as f32 / height as f32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::render::ctx::WgpuCtx;
+
+    #[test]
+    fn views_fit_their_viewport() {
+        let mut view = View::default().with_viewport(Viewport {
+            x: 0.5,
+            y: 0.0,
+            width: 0.5,
+            height: 1.0,
+        });
+        view.fit((800, 600));
+        assert_eq!(view.camera.aspect, 400.0 / 600.0);
+        // A viewport rounding down to nothing is still a pixel across
+        view.viewport.height = 0.0;
+        view.fit((800, 600));
+        assert_eq!(view.camera.aspect, 400.0);
+    }
+
+    #[test]
+    fn resizing_keeps_aspects_up_to_date() {
+        let mut ctx = match WgpuCtx::new_software_blocking(64, 32) {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                eprintln!("Skipping aspect test: {e}");
+                return;
+            }
+        };
+        let texture = ctx.create_render_texture("aspect test", 30, 10);
+        let full = ctx.add_view(View::default());
+        let half = ctx.add_view(View::default().with_viewport(Viewport {
+            width: 0.5,
+            ..Viewport::FULL
+        }));
+        let textured = ctx.add_view(View::default().with_target(ViewTarget::Texture(texture)));
+        let aspect = |ctx: &WgpuCtx<'_>, view: usize| ctx.views()[view].camera.aspect;
+        assert_eq!(aspect(&ctx, full), 2.0);
+        assert_eq!(aspect(&ctx, half), 1.0);
+        assert_eq!(aspect(&ctx, textured), 3.0);
+
+        ctx.resize((40, 80));
+        assert_eq!(aspect(&ctx, full), 0.5);
+        assert_eq!(aspect(&ctx, half), 0.25);
+        // Textures keep their own size
+        assert_eq!(aspect(&ctx, textured), 3.0);
+
+        // Changes to a viewport are caught up with by the next resize
+        ctx.views_mut()[half].viewport = Viewport::FULL;
+        ctx.resize((40, 80));
+        assert_eq!(aspect(&ctx, half), 0.5);
+    }
+}
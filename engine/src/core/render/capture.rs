@@ -0,0 +1,26 @@
+use std::path::Path;
+
+use super::{
+    bus::{self, RenderRequest, RequestError},
+    ctx::WgpuCtx,
+};
+
+/// Size of the frame rendered for a screenshot when nothing else is rendering.
+const FALLBACK_SIZE: (u32, u32) = (800, 600);
+
+/// Saves a PNG of the next frame of the running renderer. When nothing is
+/// rendering, a frame is rendered offscreen just for the screenshot.
+pub fn screenshot(path: &Path) -> anyhow::Result<()> {
+    match bus::request(RenderRequest::Screenshot(path.to_path_buf())) {
+        Err(RequestError::NoRenderer) => {
+            let (width, height) = FALLBACK_SIZE;
+            let ctx = WgpuCtx::new_offscreen_blocking(width, height)?;
+            ctx.save_screenshot(path)?;
+            Ok(())
+        }
+        result => {
+            result?;
+            Ok(())
+        }
+    }
+}
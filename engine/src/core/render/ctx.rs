@@ -1,239 +1,238 @@
-﻿use std::borrow::Cow;
-use std::sync::Arc;
-use std::time::Instant;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant};
 
-use cgmath::{Matrix4, Point3, Rad, Vector3, perspective};
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Rad, SquareMatrix};
 use futures::executor::block_on;
+use image::{ImageFormat, RgbaImage};
+use log::{debug, warn};
+use parking_lot::Mutex;
 use thiserror::Error;
-use wgpu::util::DeviceExt;
 use winit::window::Window;
 
+use super::{
+    binding::{Binding, BindingCache, BindingStats},
+    camera::{Camera, View, ViewTarget, controller::InputState},
+    depth::{DepthBuffer, DepthSettings},
+    instance::{Instance, InstanceBuffer},
+    light::Lighting,
+    material::{Material, MaterialParams},
+    mesh::{Mesh, MeshData, MeshId, MeshRegistry},
+    pipeline::{PipelineCache, PipelineKey},
+    texture::{
+        COMPRESSION_FEATURES, RenderTexture, Texture, TextureCache, TextureError, TextureOptions,
+    },
+};
+use crate::core::assets::material::{MaterialDesc, TextureSource};
+
 #[derive(Debug, Error)]
 pub enum ContextError {
     #[error("Failed to create WGPU surface: {0}")]
     SurfaceCreationFailure(#[from] wgpu::CreateSurfaceError),
+    #[error("No suitable render adapter found")]
+    AdapterNotFound,
+    #[error("Failed to create rendering device: {0}")]
+    DeviceRequestFailed(#[from] wgpu::RequestDeviceError),
+    #[error("The window's surface isn't supported by the render adapter")]
+    SurfaceUnsupported,
+    #[error("Out of GPU memory for the next frame")]
+    OutOfMemory,
+    /// The context has to be rebuilt with [`WgpuCtx::rebuild`].
+    #[error("The rendering device was lost: {0}")]
+    DeviceLost(String),
+    #[error("Can't capture frames in the {0:?} format")]
+    UnsupportedCaptureFormat(wgpu::TextureFormat),
+    #[error("Failed to read back the frame: {0}")]
+    ReadbackFailed(#[from] wgpu::BufferAsyncError),
+    #[error("Failed to save image: {0}")]
+    ImageSaveFailed(#[from] image::ImageError),
 }
 
-const CUBE_SHADER: &str = r#"
-struct Uniforms {
-    mvp: mat4x4<f32>,
+/// Format of offscreen render targets.
+pub const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+pub const DEFAULT_CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.1,
+    b: 0.1,
+    a: 1.0,
 };
 
-@group(0) @binding(0)
-var<uniform> u: Uniforms;
+/// Matches `Scene` in the PBR shader.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SceneUniform {
+    view_proj: [[f32; 4]; 4],
+    camera_position: [f32; 4],
+    light_direction: [f32; 4],
+    light_color: [f32; 4],
+    ambient: [f32; 4],
+}
 
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) normal: vec3<f32>,
-};
+impl SceneUniform {
+    /// The layout of group 0.
+    fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 1] {
+        [wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<SceneUniform>() as u64),
+            },
+            count: None,
+        }]
+    }
+}
 
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) normal: vec3<f32>,
-};
+/// Where a [`WgpuCtx`] draws its frames.
+enum RenderTarget<'window> {
+    Surface(wgpu::Surface<'window>),
+    /// A texture that is never presented, for rendering without a window.
+    Offscreen(wgpu::Texture),
+}
 
-@vertex
-fn vs_main(input: VertexInput) -> VertexOutput {
-    var output: VertexOutput;
-    output.clip_position = u.mvp * vec4<f32>(input.position, 1.0);
-    output.normal = input.normal;
-    return output;
+/// A mesh drawn each frame, where, and what it's shaded with.
+#[derive(Clone)]
+pub struct Object {
+    pub mesh: MeshId,
+    /// Drawn with the default material when there's none.
+    pub material: Option<Arc<Material>>,
+    /// From the mesh's space to the world's.
+    pub transform: Matrix4<f32>,
 }
 
-@fragment
-fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-    let light_dir = normalize(vec3<f32>(0.5, 1.0, 0.5));
-    let brightness = clamp(dot(normalize(input.normal), light_dir), 0.0, 1.0);
-    return vec4<f32>(0.7 * brightness, 0.7 * brightness, 0.9 * brightness, 1.0);
+impl Object {
+    /// Whether it's drawn with `material`.
+    fn uses(&self, material: &Arc<Material>) -> bool {
+        (self.material.as_ref()).is_some_and(|own| Arc::ptr_eq(own, material))
+    }
 }
-"#;
 
-#[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
-    position: [f32; 3],
-    normal: [f32; 3],
+impl From<MeshId> for Object {
+    fn from(mesh: MeshId) -> Self {
+        Object {
+            mesh,
+            material: None,
+            transform: Matrix4::identity(),
+        }
+    }
 }
 
-impl Vertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 2] = [
-        wgpu::VertexAttribute {
-            offset: 0,
-            shader_location: 0,
-            format: wgpu::VertexFormat::Float32x3,
-        },
-        wgpu::VertexAttribute {
-            offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-            shader_location: 1,
-            format: wgpu::VertexFormat::Float32x3,
-        },
-    ];
+/// What went into the last frame drawn with [`WgpuCtx::draw`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub views: usize,
+    /// Objects drawn, counted once for each view they're drawn in.
+    pub instances: usize,
+    pub draw_calls: usize,
+    pub pipeline_switches: usize,
+    pub bindings: BindingStats,
+}
+
+/// What [`WgpuCtx::rebuild`] couldn't carry over to the new device, for
+/// callers that hold on to what they uploaded to put it back.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SceneReset {
+    /// Meshes that aren't built in, so the new context doesn't have them.
+    pub meshes: Vec<String>,
+    /// Objects in the scene, which went with their meshes and materials.
+    pub objects: usize,
+    /// Views drawing into textures, which were on the device too.
+    pub texture_views: usize,
+}
 
-    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &Self::ATTRIBS,
+impl std::fmt::Display for SceneReset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} objects and {} views of textures",
+            self.objects, self.texture_views
+        )?;
+        if !self.meshes.is_empty() {
+            write!(f, ", and the meshes {},", self.meshes.join(", "))?;
         }
+        write!(f, " were lost with the device")
     }
 }
 
-const CUBE_VERTICES: &[Vertex] = &[
-    Vertex {
-        position: [-0.5, -0.5, 0.5],
-        normal: [0.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [0.5, -0.5, 0.5],
-        normal: [0.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [0.5, 0.5, 0.5],
-        normal: [0.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [0.5, 0.5, 0.5],
-        normal: [0.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [-0.5, 0.5, 0.5],
-        normal: [0.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [-0.5, -0.5, 0.5],
-        normal: [0.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [0.5, -0.5, -0.5],
-        normal: [0.0, 0.0, -1.0],
-    },
-    Vertex {
-        position: [-0.5, -0.5, -0.5],
-        normal: [0.0, 0.0, -1.0],
-    },
-    Vertex {
-        position: [-0.5, 0.5, -0.5],
-        normal: [0.0, 0.0, -1.0],
-    },
-    Vertex {
-        position: [-0.5, 0.5, -0.5],
-        normal: [0.0, 0.0, -1.0],
-    },
-    Vertex {
-        position: [0.5, 0.5, -0.5],
-        normal: [0.0, 0.0, -1.0],
-    },
-    Vertex {
-        position: [0.5, -0.5, -0.5],
-        normal: [0.0, 0.0, -1.0],
-    },
-    Vertex {
-        position: [0.5, -0.5, 0.5],
-        normal: [1.0, 0.0, 0.0],
-    },
-    Vertex {
-        position: [0.5, -0.5, -0.5],
-        normal: [1.0, 0.0, 0.0],
-    },
-    Vertex {
-        position: [0.5, 0.5, -0.5],
-        normal: [1.0, 0.0, 0.0],
-    },
-    Vertex {
-        position: [0.5, 0.5, -0.5],
-        normal: [1.0, 0.0, 0.0],
-    },
-    Vertex {
-        position: [0.5, 0.5, 0.5],
-        normal: [1.0, 0.0, 0.0],
-    },
-    Vertex {
-        position: [0.5, -0.5, 0.5],
-        normal: [1.0, 0.0, 0.0],
-    },
-    Vertex {
-        position: [-0.5, -0.5, -0.5],
-        normal: [-1.0, 0.0, 0.0],
-    },
-    Vertex {
-        position: [-0.5, -0.5, 0.5],
-        normal: [-1.0, 0.0, 0.0],
-    },
-    Vertex {
-        position: [-0.5, 0.5, 0.5],
-        normal: [-1.0, 0.0, 0.0],
-    },
-    Vertex {
-        position: [-0.5, 0.5, 0.5],
-        normal: [-1.0, 0.0, 0.0],
-    },
-    Vertex {
-        position: [-0.5, 0.5, -0.5],
-        normal: [-1.0, 0.0, 0.0],
-    },
-    Vertex {
-        position: [-0.5, -0.5, -0.5],
-        normal: [-1.0, 0.0, 0.0],
-    },
-    Vertex {
-        position: [-0.5, 0.5, 0.5],
-        normal: [0.0, 1.0, 0.0],
-    },
-    Vertex {
-        position: [0.5, 0.5, 0.5],
-        normal: [0.0, 1.0, 0.0],
-    },
-    Vertex {
-        position: [0.5, 0.5, -0.5],
-        normal: [0.0, 1.0, 0.0],
-    },
-    Vertex {
-        position: [0.5, 0.5, -0.5],
-        normal: [0.0, 1.0, 0.0],
-    },
-    Vertex {
-        position: [-0.5, 0.5, -0.5],
-        normal: [0.0, 1.0, 0.0],
-    },
-    Vertex {
-        position: [-0.5, 0.5, 0.5],
-        normal: [0.0, 1.0, 0.0],
-    },
-    Vertex {
-        position: [-0.5, -0.5, -0.5],
-        normal: [0.0, -1.0, 0.0],
-    },
-    Vertex {
-        position: [0.5, -0.5, -0.5],
-        normal: [0.0, -1.0, 0.0],
-    },
-    Vertex {
-        position: [0.5, -0.5, 0.5],
-        normal: [0.0, -1.0, 0.0],
-    },
-    Vertex {
-        position: [0.5, -0.5, 0.5],
-        normal: [0.0, -1.0, 0.0],
-    },
-    Vertex {
-        position: [-0.5, -0.5, 0.5],
-        normal: [0.0, -1.0, 0.0],
-    },
-    Vertex {
-        position: [-0.5, -0.5, -0.5],
-        normal: [0.0, -1.0, 0.0],
-    },
-];
+/// What [`WgpuCtx::draw`] does when the surface has no frame to give.
+#[derive(Debug, PartialEq, Eq)]
+enum SkippedFrame {
+    /// Lost or outdated, such as after a minimise.
+    Reconfigure,
+    Skip,
+}
+
+impl SkippedFrame {
+    /// Frames that can't be drawn are skipped, unless the GPU is out of
+    /// memory.
+    fn from_error(error: wgpu::SurfaceError) -> Result<SkippedFrame, ContextError> {
+        match error {
+            wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated => {
+                debug!("Surface lost or outdated, reconfiguring it");
+                Ok(SkippedFrame::Reconfigure)
+            }
+            wgpu::SurfaceError::Timeout => {
+                debug!("Timed out waiting for the surface, skipping the frame");
+                Ok(SkippedFrame::Skip)
+            }
+            wgpu::SurfaceError::OutOfMemory => Err(ContextError::OutOfMemory),
+            wgpu::SurfaceError::Other => {
+                warn!("Failed to get the next frame, skipping it");
+                Ok(SkippedFrame::Skip)
+            }
+        }
+    }
+}
+
+/// Copies of a mesh drawn with the same material in one instanced draw.
+struct Batch<'a> {
+    key: PipelineKey,
+    mesh: &'a Mesh,
+    material: &'a Material,
+    instances: Vec<Instance>,
+}
 
 pub struct WgpuCtx<'window> {
+    /// Kept to create a new device from if this one is lost.
+    instance: wgpu::Instance,
     device: wgpu::Device,
+    /// Why the device was lost, set from wgpu's callback.
+    device_lost: Arc<Mutex<Option<String>>>,
     queue: wgpu::Queue,
-    surface: wgpu::Surface<'window>,
+    target: RenderTarget<'window>,
+    /// Size and format of the render target. Offscreen targets use it too,
+    /// even though they aren't configured through it.
     surface_config: wgpu::SurfaceConfiguration,
     adapter: wgpu::Adapter,
-    render_pipeline: wgpu::RenderPipeline,
+    /// Locked while rendering, which only needs shared access otherwise.
+    pipelines: Mutex<PipelineCache>,
+    /// Locked while rendering, like the pipelines.
+    bindings: Mutex<BindingCache>,
+    scene_layout: wgpu::BindGroupLayout,
+    depth: DepthSettings,
+    depth_buffer: DepthBuffer,
     uniform_buffer: wgpu::Buffer,
-    vertex_buffer: wgpu::Buffer,
+    /// Locked while rendering, like the pipelines.
+    instance_buffer: Mutex<InstanceBuffer>,
+    meshes: MeshRegistry,
+    textures: TextureCache,
+    material_layout: wgpu::BindGroupLayout,
+    /// Stand-ins for maps a material doesn't have.
+    white_texture: Arc<Texture>,
+    flat_normal_texture: Arc<Texture>,
+    default_material: Arc<Material>,
+    lighting: Lighting,
+    clear_color: wgpu::Color,
+    /// Drawn each frame.
+    objects: Vec<Object>,
+    /// Whether objects turn in place over time.
+    spinning: bool,
+    /// Cameras the objects are drawn through, in order.
+    views: Vec<View>,
+    frame_stats: FrameStats,
     start_time: Instant,
 }
 
@@ -241,191 +240,1107 @@ impl<'window> WgpuCtx<'window> {
     pub async fn new(window: Arc<Window>) -> Result<WgpuCtx<'window>, ContextError> {
         let instance = wgpu::Instance::default();
         let surface = instance.create_surface(Arc::clone(&window))?;
-        let adapter = instance
+        let adapter = Self::request_adapter(&instance, Some(&surface), false).await?;
+        let (device, queue) = Self::request_device(&adapter).await?;
+        let size = window.inner_size();
+        let surface_config = surface
+            .get_default_config(&adapter, size.width.max(1), size.height.max(1))
+            .ok_or(ContextError::SurfaceUnsupported)?;
+        surface.configure(&device, &surface_config);
+        Ok(Self::with_target(
+            instance,
+            adapter,
+            device,
+            queue,
+            RenderTarget::Surface(surface),
+            surface_config,
+        ))
+    }
+
+    /// Creates a context that renders into a texture instead of a window, so
+    /// it works on machines without a display.
+    pub async fn new_offscreen(width: u32, height: u32) -> Result<WgpuCtx<'window>, ContextError> {
+        let instance = wgpu::Instance::default();
+        let adapter = Self::request_adapter(&instance, None, false).await?;
+        Self::offscreen_with_adapter(instance, adapter, width, height).await
+    }
+
+    /// Like [`WgpuCtx::new_offscreen`], but prefers a software adapter so the
+    /// output doesn't depend on the GPU. Falls back to any adapter if there
+    /// is no software one.
+    pub async fn new_software(width: u32, height: u32) -> Result<WgpuCtx<'window>, ContextError> {
+        let instance = wgpu::Instance::default();
+        let adapter = match Self::request_adapter(&instance, None, true).await {
+            Ok(adapter) => adapter,
+            Err(_) => Self::request_adapter(&instance, None, false).await?,
+        };
+        Self::offscreen_with_adapter(instance, adapter, width, height).await
+    }
+
+    pub fn new_software_blocking(
+        width: u32,
+        height: u32,
+    ) -> Result<WgpuCtx<'window>, ContextError> {
+        block_on(Self::new_software(width, height))
+    }
+
+    async fn offscreen_with_adapter(
+        instance: wgpu::Instance,
+        adapter: wgpu::Adapter,
+        width: u32,
+        height: u32,
+    ) -> Result<WgpuCtx<'window>, ContextError> {
+        let (device, queue) = Self::request_device(&adapter).await?;
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: OFFSCREEN_FORMAT,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+        let texture = create_offscreen_texture(&device, &surface_config);
+        Ok(Self::with_target(
+            instance,
+            adapter,
+            device,
+            queue,
+            RenderTarget::Offscreen(texture),
+            surface_config,
+        ))
+    }
+
+    pub fn new_offscreen_blocking(
+        width: u32,
+        height: u32,
+    ) -> Result<WgpuCtx<'window>, ContextError> {
+        block_on(Self::new_offscreen(width, height))
+    }
+
+    async fn request_adapter(
+        instance: &wgpu::Instance,
+        compatible_surface: Option<&wgpu::Surface<'_>>,
+        force_fallback_adapter: bool,
+    ) -> Result<wgpu::Adapter, ContextError> {
+        instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::default(),
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
+                force_fallback_adapter,
+                compatible_surface,
             })
             .await
-            .expect("Failed to obtain render adapter");
-        let (device, queue) = adapter
+            .ok_or(ContextError::AdapterNotFound)
+    }
+
+    async fn request_device(
+        adapter: &wgpu::Adapter,
+    ) -> Result<(wgpu::Device, wgpu::Queue), ContextError> {
+        Ok(adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    required_features: wgpu::Features::empty(),
+                    required_features: adapter.features() & COMPRESSION_FEATURES,
                     required_limits: wgpu::Limits::downlevel_webgl2_defaults()
                         .using_resolution(adapter.limits()),
                     memory_hints: wgpu::MemoryHints::Performance,
                 },
                 None,
             )
-            .await
-            .expect("Failed to create rendering device");
-        let size = window.inner_size();
-        let width = size.width.max(1);
-        let height = size.height.max(1);
-        let surface_config = surface.get_default_config(&adapter, width, height).unwrap();
-        surface.configure(&device, &surface_config);
+            .await?)
+    }
+
+    fn with_target(
+        instance: wgpu::Instance,
+        adapter: wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        target: RenderTarget<'window>,
+        surface_config: wgpu::SurfaceConfiguration,
+    ) -> WgpuCtx<'window> {
         let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Uniform Buffer"),
-            size: 64,
+            label: Some("Scene Uniform Buffer"),
+            size: std::mem::size_of::<SceneUniform>() as u64,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Cube Vertex Buffer"),
-            contents: bytemuck::cast_slice(CUBE_VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Cube Shader"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(CUBE_SHADER)),
-        });
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Uniform Bind Group Layout"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: wgpu::BufferSize::new(64),
-                },
-                count: None,
-            }],
-        });
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Cube Pipeline Layout"),
-            bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
-        });
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Cube Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[Vertex::desc()],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_config.format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
+        let mut meshes = MeshRegistry::default();
+        let primitives = [
+            ("cube", MeshData::cube(1.0)),
+            ("sphere", MeshData::sphere(0.5, 32, 16)),
+            ("plane", MeshData::plane(2.0, 2.0)),
+            ("cylinder", MeshData::cylinder(0.5, 1.0, 32)),
+        ];
+        for (name, data) in &primitives {
+            meshes.add(name, Mesh::new(&device, name, data));
+        }
+        let objects = meshes.find("cube").into_iter().map(Object::from).collect();
+        let mut bindings = BindingCache::default();
+        let scene_layout = bindings.layout(
+            &device,
+            "Scene Bind Group Layout",
+            &SceneUniform::layout_entries(),
+        );
+        let material_layout = bindings.layout(
+            &device,
+            "Material Bind Group Layout",
+            &Material::layout_entries(),
+        );
+        let pipelines = PipelineCache::new(&device, &[&scene_layout, &material_layout]);
+        let depth = DepthSettings::default();
+        let depth_buffer = DepthBuffer::new(&device, surface_config.width, surface_config.height);
+        let white_texture = Arc::new(Texture::solid(
+            &device,
+            &queue,
+            "White Texture",
+            [255; 4],
+            true,
+        ));
+        let flat_normal_texture = Arc::new(Texture::solid(
+            &device,
+            &queue,
+            "Flat Normal Texture",
+            [128, 128, 255, 255],
+            false,
+        ));
+        let default_material = Arc::new(Material::new(
+            &device,
+            MaterialParams::default(),
+            white_texture.clone(),
+            flat_normal_texture.clone(),
+        ));
+        let instance_buffer = InstanceBuffer::new(&device);
+        let device_lost = Arc::new(Mutex::new(None));
+        let lost = Arc::clone(&device_lost);
+        device.set_device_lost_callback(move |reason, message| {
+            // Destroyed is the device being dropped along with the context
+            if reason == wgpu::DeviceLostReason::Unknown {
+                *lost.lock() = Some(message);
+            }
         });
-        Ok(WgpuCtx {
+        let mut ctx = WgpuCtx {
+            instance,
             device,
+            device_lost,
             queue,
-            surface,
+            target,
             surface_config,
             adapter,
-            render_pipeline,
+            pipelines: Mutex::new(pipelines),
+            bindings: Mutex::new(bindings),
+            scene_layout,
+            depth,
+            depth_buffer,
             uniform_buffer,
-            vertex_buffer,
+            instance_buffer: Mutex::new(instance_buffer),
+            meshes,
+            textures: TextureCache::default(),
+            material_layout,
+            white_texture,
+            flat_normal_texture,
+            default_material,
+            lighting: Lighting::default(),
+            clear_color: DEFAULT_CLEAR_COLOR,
+            objects,
+            spinning: true,
+            views: vec![View::default()],
+            frame_stats: FrameStats::default(),
             start_time: Instant::now(),
-        })
+        };
+        ctx.fit_views();
+        ctx
     }
 
     pub fn new_blocking(window: Arc<Window>) -> Result<WgpuCtx<'window>, ContextError> {
         block_on(Self::new(window))
     }
 
+    /// Creates a context on a new device drawing into the same window or
+    /// size of offscreen target, for after [`ContextError::DeviceLost`].
+    /// Settings and views of the frame are kept, but meshes, textures and
+    /// materials lived on the lost device, so the scene starts over. What
+    /// was lost is returned alongside the new context, to be passed on to
+    /// whoever built the scene.
+    pub async fn rebuild(self) -> Result<(WgpuCtx<'window>, SceneReset), ContextError> {
+        let mut meshes: Vec<String> = (self.meshes.names())
+            .map(|(name, _)| name.to_string())
+            .collect();
+        let objects = self.objects.len();
+        let (views, texture_views): (Vec<_>, Vec<_>) =
+            (self.views.into_iter()).partition(|view| matches!(view.target, ViewTarget::Frame));
+        let WgpuCtx {
+            instance,
+            target,
+            surface_config,
+            depth,
+            lighting,
+            clear_color,
+            spinning,
+            start_time,
+            ..
+        } = self;
+        let (width, height) = (surface_config.width, surface_config.height);
+        let mut ctx = match target {
+            RenderTarget::Surface(surface) => {
+                let adapter = Self::request_adapter(&instance, Some(&surface), false).await?;
+                let (device, queue) = Self::request_device(&adapter).await?;
+                let surface_config = surface
+                    .get_default_config(&adapter, width, height)
+                    .ok_or(ContextError::SurfaceUnsupported)?;
+                surface.configure(&device, &surface_config);
+                Self::with_target(
+                    instance,
+                    adapter,
+                    device,
+                    queue,
+                    RenderTarget::Surface(surface),
+                    surface_config,
+                )
+            }
+            RenderTarget::Offscreen(_) => {
+                let adapter = Self::request_adapter(&instance, None, false).await?;
+                Self::offscreen_with_adapter(instance, adapter, width, height).await?
+            }
+        };
+        ctx.depth = depth;
+        ctx.lighting = lighting;
+        ctx.clear_color = clear_color;
+        ctx.spinning = spinning;
+        ctx.views = views;
+        ctx.start_time = start_time;
+        ctx.fit_views();
+
+        meshes.retain(|name| ctx.meshes.find(name).is_none());
+        meshes.sort();
+        Ok((
+            ctx,
+            SceneReset {
+                meshes,
+                objects,
+                texture_views: texture_views.len(),
+            },
+        ))
+    }
+
+    pub fn rebuild_blocking(self) -> Result<(WgpuCtx<'window>, SceneReset), ContextError> {
+        block_on(self.rebuild())
+    }
+
+    /// Why the device was lost, if it has been.
+    pub fn device_lost(&self) -> Option<String> {
+        self.device_lost.lock().clone()
+    }
+
+    pub fn adapter_info(&self) -> wgpu::AdapterInfo {
+        self.adapter.get_info()
+    }
+
     pub fn resize(&mut self, new_size: (u32, u32)) {
         let (width, height) = new_size;
         self.surface_config.width = width.max(1);
         self.surface_config.height = height.max(1);
-        self.surface.configure(&self.device, &self.surface_config);
+        match &mut self.target {
+            RenderTarget::Surface(surface) => surface.configure(&self.device, &self.surface_config),
+            RenderTarget::Offscreen(texture) => {
+                *texture = create_offscreen_texture(&self.device, &self.surface_config);
+            }
+        }
+        self.depth_buffer = DepthBuffer::new(
+            &self.device,
+            self.surface_config.width,
+            self.surface_config.height,
+        );
+        self.fit_views();
     }
 
-    pub fn draw(&mut self) {
-        let elapsed = self.start_time.elapsed().as_secs_f32();
-        let model = Matrix4::from_angle_x(Rad(elapsed)) * Matrix4::from_angle_y(Rad(elapsed));
-        let view = Matrix4::look_at_rh(
-            Point3::new(0.0, 0.0, 3.0),
-            Point3::new(0.0, 0.0, 0.0),
-            Vector3::unit_y(),
-        );
-        let aspect = self.surface_config.width as f32 / self.surface_config.height as f32;
-        let proj = perspective(Rad(std::f32::consts::FRAC_PI_4), aspect, 0.1, 100.0);
-        let mvp = proj * view * model;
-        let mvp_array: [[f32; 4]; 4] = [
-            [mvp.x.x, mvp.x.y, mvp.x.z, mvp.x.w],
-            [mvp.y.x, mvp.y.y, mvp.y.z, mvp.y.w],
-            [mvp.z.x, mvp.z.y, mvp.z.z, mvp.z.w],
-            [mvp.w.x, mvp.w.y, mvp.w.z, mvp.w.w],
-        ];
-        self.queue
-            .write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&mvp_array));
-        let surface_texture = self
-            .surface
-            .get_current_texture()
-            .expect("Failed to get surface texture");
-        let view_texture = surface_texture
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Cube Command Encoder"),
-            });
+    pub fn meshes(&self) -> &MeshRegistry {
+        &self.meshes
+    }
+
+    /// Uploads geometry and registers it under `name`, replacing any mesh
+    /// already registered with that name.
+    pub fn add_mesh(&mut self, name: &str, data: &MeshData) -> MeshId {
+        let mesh = Mesh::new(&self.device, name, data);
+        self.meshes.add(name, mesh)
+    }
+
+    /// Removes the mesh and the objects drawn with it. The bind groups of
+    /// materials no object is left using are dropped with them.
+    pub fn remove_mesh(&mut self, id: MeshId) {
+        self.meshes.remove(id);
+        let (removed, kept) = std::mem::take(&mut self.objects)
+            .into_iter()
+            .partition::<Vec<_>, _>(|object| object.mesh == id);
+        self.objects = kept;
+        for material in removed.into_iter().filter_map(|object| object.material) {
+            if !self.objects.iter().any(|object| object.uses(&material)) {
+                self.bindings
+                    .get_mut()
+                    .invalidate(&material.uniform_binding());
+            }
+        }
+    }
+
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+
+    pub fn objects_mut(&mut self) -> &mut Vec<Object> {
+        &mut self.objects
+    }
+
+    pub fn is_spinning(&self) -> bool {
+        self.spinning
+    }
+
+    /// Turns every object in place over time, on top of its transform.
+    pub fn set_spinning(&mut self, spinning: bool) {
+        self.spinning = spinning;
+    }
+
+    pub fn views(&self) -> &[View] {
+        &self.views
+    }
+
+    /// Cameras' aspect ratios catch up with changes to their viewports and
+    /// targets on the next resize or draw.
+    pub fn views_mut(&mut self) -> &mut Vec<View> {
+        &mut self.views
+    }
+
+    /// Adds a view drawn after the others, returning its index.
+    pub fn add_view(&mut self, mut view: View) -> usize {
+        view.fit(self.target_size(&view.target));
+        self.views.push(view);
+        self.views.len() - 1
+    }
+
+    /// Lets each view's controller move its camera.
+    pub fn update_views(&mut self, input: &InputState, dt: f32) {
+        for view in &mut self.views {
+            if let Some(controller) = &mut view.controller {
+                controller.update(&mut view.camera, input, dt);
+            }
+        }
+    }
+
+    fn target_size(&self, target: &ViewTarget) -> (u32, u32) {
+        match target {
+            ViewTarget::Frame => (self.surface_config.width, self.surface_config.height),
+            ViewTarget::Texture(texture) => texture.size(),
+        }
+    }
+
+    fn fit_views(&mut self) {
+        let frame_size = (self.surface_config.width, self.surface_config.height);
+        for view in &mut self.views {
+            match &view.target {
+                ViewTarget::Frame => view.fit(frame_size),
+                ViewTarget::Texture(texture) => view.fit(texture.size()),
+            }
+        }
+    }
+
+    pub fn create_render_texture(
+        &self,
+        label: &str,
+        width: u32,
+        height: u32,
+    ) -> Arc<RenderTexture> {
+        Arc::new(RenderTexture::new(&self.device, label, width, height))
+    }
+
+    /// Loads a texture file, or shares the one already loaded from it.
+    pub fn load_texture(
+        &mut self,
+        path: &Path,
+        options: TextureOptions,
+    ) -> Result<Arc<Texture>, TextureError> {
+        self.textures.load(&self.device, &self.queue, path, options)
+    }
+
+    pub fn create_texture(
+        &self,
+        label: &str,
+        image: &RgbaImage,
+        options: TextureOptions,
+    ) -> Arc<Texture> {
+        Arc::new(Texture::from_image(
+            &self.device,
+            &self.queue,
+            label,
+            image,
+            options,
+        ))
+    }
+
+    /// Missing maps are replaced with ones that leave the surface as it is.
+    pub fn create_material(
+        &self,
+        params: MaterialParams,
+        albedo: Option<Arc<Texture>>,
+        normal: Option<Arc<Texture>>,
+    ) -> Arc<Material> {
+        Arc::new(Material::new(
+            &self.device,
+            params,
+            albedo.unwrap_or_else(|| self.white_texture.clone()),
+            normal.unwrap_or_else(|| self.flat_normal_texture.clone()),
+        ))
+    }
+
+    /// Draws the objects using `old` with `new` instead, dropping the bind
+    /// groups of `old`.
+    pub fn replace_material(&mut self, old: &Arc<Material>, new: Arc<Material>) {
+        for object in &mut self.objects {
+            if object.uses(old) {
+                object.material = Some(new.clone());
+            }
+        }
+        self.bindings.get_mut().invalidate(&old.uniform_binding());
+    }
+
+    /// Swaps `old` for `new` in the maps of the objects' materials, giving
+    /// each material that had it a replacement with the same parameters.
+    pub fn replace_texture(&mut self, old: &Arc<Texture>, new: &Arc<Texture>) {
+        let pick = |map: &Arc<Texture>| {
+            if Arc::ptr_eq(map, old) {
+                new.clone()
+            } else {
+                map.clone()
+            }
+        };
+        let mut replaced: Vec<(Arc<Material>, Arc<Material>)> = Vec::new();
+        for material in self
+            .objects
+            .iter()
+            .filter_map(|object| object.material.as_ref())
         {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Cube Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view_texture,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.1,
-                            b: 0.1,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-            render_pass.set_pipeline(&self.render_pipeline);
-            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("Uniform Bind Group"),
-                layout: &self.render_pipeline.get_bind_group_layout(0),
-                entries: &[wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: self.uniform_buffer.as_entire_binding(),
-                }],
-            });
-            render_pass.set_bind_group(0, &bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.draw(0..36, 0..1);
+            let samples =
+                Arc::ptr_eq(material.albedo(), old) || Arc::ptr_eq(material.normal(), old);
+            if samples && !replaced.iter().any(|(m, _)| Arc::ptr_eq(m, material)) {
+                let replacement = Arc::new(Material::new(
+                    &self.device,
+                    *material.params(),
+                    pick(material.albedo()),
+                    pick(material.normal()),
+                ));
+                replaced.push((material.clone(), replacement));
+            }
+        }
+        for (old, new) in replaced {
+            self.replace_material(&old, new);
+        }
+        self.bindings
+            .get_mut()
+            .invalidate(&Binding::TextureView(old.view().clone()));
+    }
+
+    /// Creates the material an asset describes, loading the maps it refers
+    /// to.
+    pub fn load_material(&mut self, desc: &MaterialDesc) -> Result<Arc<Material>, TextureError> {
+        let albedo = desc
+            .base_color_texture
+            .as_ref()
+            .map(|source| self.texture_from_source(&desc.name, source, TextureOptions::COLOR))
+            .transpose()?;
+        let normal = desc
+            .normal_texture
+            .as_ref()
+            .map(|source| self.texture_from_source(&desc.name, source, TextureOptions::DATA))
+            .transpose()?;
+        Ok(self.create_material(MaterialParams::from(desc), albedo, normal))
+    }
+
+    fn texture_from_source(
+        &mut self,
+        label: &str,
+        source: &TextureSource,
+        options: TextureOptions,
+    ) -> Result<Arc<Texture>, TextureError> {
+        match source {
+            TextureSource::Path(path) => self.load_texture(path, options),
+            TextureSource::Embedded(image) => Ok(self.create_texture(label, image, options)),
         }
-        self.queue.submit(Some(encoder.finish()));
-        surface_texture.present();
+    }
+
+    pub fn depth(&self) -> DepthSettings {
+        self.depth
+    }
+
+    /// Changes how the scene is depth tested. Pipelines are rebuilt as
+    /// they're next needed.
+    pub fn set_depth(&mut self, depth: DepthSettings) {
+        if depth == self.depth {
+            return;
+        }
+        self.depth = depth;
+        self.pipelines.get_mut().clear();
+    }
+
+    pub fn lighting(&self) -> Lighting {
+        self.lighting
+    }
+
+    pub fn set_lighting(&mut self, lighting: Lighting) {
+        self.lighting = lighting;
+    }
+
+    pub fn clear_color(&self) -> wgpu::Color {
+        self.clear_color
+    }
+
+    /// The colour the frame starts out as, in linear RGB.
+    pub fn set_clear_color(&mut self, color: wgpu::Color) {
+        self.clear_color = color;
+    }
+
+    /// How many render pipelines have been built for the materials and
+    /// meshes drawn so far.
+    pub fn pipeline_count(&self) -> usize {
+        self.pipelines.lock().len()
+    }
+
+    /// Blocks until the GPU has finished all the work submitted so far.
+    pub fn wait_idle(&self) {
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_stats
+    }
+
+    /// Draws a frame, unless the surface isn't ready for one. Surfaces that
+    /// are lost or outdated, such as after a minimise, are reconfigured for
+    /// the next frame.
+    pub fn draw(&mut self) -> Result<(), ContextError> {
+        if let Some(reason) = self.device_lost() {
+            return Err(ContextError::DeviceLost(reason));
+        }
+        self.fit_views();
+        let (surface_texture, view) = match &self.target {
+            RenderTarget::Surface(surface) => {
+                let surface_texture = match surface.get_current_texture() {
+                    Ok(surface_texture) => surface_texture,
+                    Err(e) => {
+                        if SkippedFrame::from_error(e)? == SkippedFrame::Reconfigure {
+                            surface.configure(&self.device, &self.surface_config);
+                        }
+                        return Ok(());
+                    }
+                };
+                let view = surface_texture
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(surface_texture), view)
+            }
+            RenderTarget::Offscreen(texture) => (
+                None,
+                texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            ),
+        };
+        self.frame_stats = self.render(&view, self.start_time.elapsed());
+        match surface_texture {
+            Some(surface_texture) => surface_texture.present(),
+            // Nothing presents offscreen frames, so reclaim finished work here
+            None => {
+                self.device.poll(wgpu::Maintain::Poll);
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders a frame and reads it back. Window surfaces can't be read
+    /// from, so for those the frame is rendered into a texture of the same
+    /// size instead of being presented.
+    pub fn capture(&self) -> Result<RgbaImage, ContextError> {
+        self.capture_at(self.start_time.elapsed())
+    }
+
+    /// Captures the scene as it is `elapsed` after startup, so the same frame
+    /// can be rendered again.
+    pub fn capture_at(&self, elapsed: Duration) -> Result<RgbaImage, ContextError> {
+        if let Some(reason) = self.device_lost() {
+            return Err(ContextError::DeviceLost(reason));
+        }
+        let capture_texture;
+        let texture = match &self.target {
+            RenderTarget::Offscreen(texture) => texture,
+            RenderTarget::Surface(_) => {
+                let config = wgpu::SurfaceConfiguration {
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                    ..self.surface_config.clone()
+                };
+                capture_texture = create_offscreen_texture(&self.device, &config);
+                &capture_texture
+            }
+        };
+        self.render(
+            &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            elapsed,
+        );
+        read_texture(&self.device, &self.queue, texture)
+    }
+
+    /// Captures a frame and saves it as a PNG.
+    pub fn save_screenshot(&self, path: &Path) -> Result<(), ContextError> {
+        self.capture()?.save_with_format(path, ImageFormat::Png)?;
+        Ok(())
+    }
+
+    /// Draws the scene as it is `elapsed` after startup through each view,
+    /// with `frame` as the frame's target.
+    fn render(&self, frame: &wgpu::TextureView, elapsed: Duration) -> FrameStats {
+        let elapsed = elapsed.as_secs_f32();
+        let spin = if self.spinning {
+            Matrix4::from_angle_x(Rad(elapsed)) * Matrix4::from_angle_y(Rad(elapsed))
+        } else {
+            Matrix4::identity()
+        };
+        let instances: Vec<Instance> = (self.objects.iter())
+            .map(|object| Instance::new(object.transform * spin))
+            .collect();
+        let light = &self.lighting;
+        let [r, g, b] = light.color.map(|channel| channel * light.intensity);
+
+        // Views of textures go first, so views of the frame can show them
+        let mut views: Vec<&View> = self.views.iter().collect();
+        views.sort_by_key(|view| matches!(view.target, ViewTarget::Frame));
+        let mut stats = FrameStats {
+            views: views.len(),
+            ..FrameStats::default()
+        };
+        let mut pipelines = self.pipelines.lock();
+        let mut instance_buffer = self.instance_buffer.lock();
+        let mut bindings = self.bindings.lock();
+        let scene_bind_group = bindings.bind_group(
+            &self.device,
+            "Scene Bind Group",
+            &self.scene_layout,
+            &[Binding::Buffer(self.uniform_buffer.clone())],
+        );
+        let mut drawn_into: Vec<Option<&Arc<RenderTexture>>> = Vec::new();
+        for view in views {
+            let (target, depth_buffer, format, texture) = match &view.target {
+                ViewTarget::Frame => (frame, &self.depth_buffer, self.surface_config.format, None),
+                ViewTarget::Texture(texture) => (
+                    texture.texture().view(),
+                    texture.depth_buffer(),
+                    RenderTexture::FORMAT,
+                    Some(texture),
+                ),
+            };
+            let same_target = |other: &Option<&Arc<RenderTexture>>| match (other, texture) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            };
+            let load = if drawn_into.iter().any(same_target) {
+                wgpu::LoadOp::Load
+            } else {
+                drawn_into.push(texture);
+                wgpu::LoadOp::Clear(self.clear_color)
+            };
+
+            let camera = &view.camera;
+            let uniform = SceneUniform {
+                view_proj: camera.view_projection().into(),
+                camera_position: camera.position.to_homogeneous().into(),
+                light_direction: light.direction.normalize().extend(0.0).into(),
+                light_color: [r, g, b, 1.0],
+                ambient: [light.ambient[0], light.ambient[1], light.ambient[2], 1.0],
+            };
+            // Buffer writes take effect at the next submit, so each view is
+            // submitted on its own
+            self.queue
+                .write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+
+            let batches = self.batch(
+                &instances,
+                camera,
+                format,
+                texture.map(|texture| &**texture),
+            );
+            let mut ranges = Vec::with_capacity(batches.len());
+            let mut view_instances = Vec::with_capacity(instances.len());
+            for batch in &batches {
+                pipelines.get_or_create(&self.device, &batch.key);
+                let start = view_instances.len() as u32;
+                view_instances.extend_from_slice(&batch.instances);
+                ranges.push(start..view_instances.len() as u32);
+            }
+            instance_buffer.write(&self.device, &self.queue, &view_instances);
+            stats.instances += view_instances.len();
+
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Scene Command Encoder"),
+                });
+            {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Scene Render Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: target,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: Some(depth_buffer.attachment()),
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                let [x, y, width, height] = view.viewport.pixels(self.target_size(&view.target));
+                render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                render_pass.set_scissor_rect(x, y, width, height);
+                render_pass.set_bind_group(0, &scene_bind_group, &[]);
+                render_pass.set_vertex_buffer(1, instance_buffer.buffer().slice(..));
+                let mut bound_pipeline = None;
+                let mut bound_material = None;
+                for (batch, range) in batches.iter().zip(ranges) {
+                    if bound_pipeline != Some(&batch.key) {
+                        let pipeline = pipelines
+                            .get(&batch.key)
+                            .expect("Pipelines were created above");
+                        render_pass.set_pipeline(pipeline);
+                        bound_pipeline = Some(&batch.key);
+                        stats.pipeline_switches += 1;
+                    }
+                    if bound_material != Some(batch.material as *const Material) {
+                        let bind_group = bindings.bind_group(
+                            &self.device,
+                            "Material Bind Group",
+                            &self.material_layout,
+                            &batch.material.bindings(),
+                        );
+                        render_pass.set_bind_group(1, &bind_group, &[]);
+                        bound_material = Some(batch.material as *const Material);
+                    }
+                    batch.mesh.draw(&mut render_pass, range);
+                    stats.draw_calls += 1;
+                }
+            }
+            self.queue.submit(Some(encoder.finish()));
+        }
+        stats.bindings = bindings.end_frame();
+        stats
+    }
+
+    /// Groups the objects into batches of one mesh and material, given each
+    /// object's instance. Opaque objects are batched together wherever they
+    /// are, keeping batches that share a pipeline together, and within
+    /// those, batches that share a material. Blended objects
+    /// are drawn after them, back to front as seen from `camera` so each
+    /// blends over what's behind it, and only share a batch with their
+    /// neighbours in that order. Objects that sample `drawn_into` are left
+    /// out.
+    fn batch(
+        &self,
+        instances: &[Instance],
+        camera: &Camera,
+        format: wgpu::TextureFormat,
+        drawn_into: Option<&RenderTexture>,
+    ) -> Vec<Batch<'_>> {
+        let key = |mesh: &Mesh, material: &Material| PipelineKey {
+            vertex_layout: mesh.layout().clone(),
+            variant: material.variant(),
+            depth: self.depth,
+            format,
+        };
+        let mut batches: Vec<Batch<'_>> = Vec::new();
+        let mut indices: HashMap<(MeshId, *const Material), usize> = HashMap::new();
+        let mut blended: Vec<(f32, Batch<'_>)> = Vec::new();
+        for (object, instance) in self.objects.iter().zip(instances) {
+            let Some(mesh) = self.meshes.get(object.mesh) else {
+                continue;
+            };
+            let material = object.material.as_ref().unwrap_or(&self.default_material);
+            // A texture can't be sampled while it's being drawn into
+            if let Some(texture) = drawn_into {
+                let samples = |map: &Arc<Texture>| Arc::ptr_eq(map, texture.texture());
+                if samples(material.albedo()) || samples(material.normal()) {
+                    continue;
+                }
+            }
+            if material.variant().blend {
+                let position = object.transform.w.truncate();
+                let depth = (position - camera.position.to_vec()).dot(camera.forward());
+                let batch = Batch {
+                    key: key(mesh, material),
+                    mesh,
+                    material,
+                    instances: vec![*instance],
+                };
+                blended.push((depth, batch));
+                continue;
+            }
+            let index = *indices
+                .entry((object.mesh, Arc::as_ptr(material)))
+                .or_insert_with(|| {
+                    batches.push(Batch {
+                        key: key(mesh, material),
+                        mesh,
+                        material,
+                        instances: Vec::new(),
+                    });
+                    batches.len() - 1
+                });
+            batches[index].instances.push(*instance);
+        }
+        // Pipeline keys and materials don't order, so each sorts by where
+        // it first appears, which groups its batches together
+        let mut key_order: HashMap<PipelineKey, usize> = HashMap::new();
+        let mut material_order: HashMap<*const Material, usize> = HashMap::new();
+        for batch in &batches {
+            let next = key_order.len();
+            key_order.entry(batch.key.clone()).or_insert(next);
+            let next = material_order.len();
+            material_order.entry(batch.material).or_insert(next);
+        }
+        batches.sort_by_cached_key(|batch| {
+            let material: *const Material = batch.material;
+            (key_order[&batch.key], material_order[&material])
+        });
+
+        blended.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        for (_, batch) in blended {
+            match batches.last_mut() {
+                Some(last)
+                    if std::ptr::eq(last.mesh, batch.mesh)
+                        && std::ptr::eq(last.material, batch.material) =>
+                {
+                    last.instances.extend(batch.instances);
+                }
+                _ => batches.push(batch),
+            }
+        }
+        batches
+    }
+}
+
+/// Copies a texture into a buffer and maps it, blocking until the GPU is
+/// done. Rows are padded to the alignment wgpu requires for copies.
+fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Result<RgbaImage, ContextError> {
+    let swap_red_blue = match texture.format() {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        format => return Err(ContextError::UnsupportedCaptureFormat(format)),
+    };
+    let (width, height) = (texture.width(), texture.height());
+    let row_bytes = width * 4;
+    let padded_row_bytes = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Capture Buffer"),
+        size: u64::from(padded_row_bytes) * u64::from(height),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Capture Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_bytes),
+                rows_per_image: None,
+            },
+        },
+        texture.size(),
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    // The callback is always called by the poll above
+    receiver.recv().unwrap_or(Err(wgpu::BufferAsyncError))?;
+
+    let mut pixels = Vec::with_capacity((row_bytes * height) as usize);
+    for row in slice.get_mapped_range().chunks(padded_row_bytes as usize) {
+        pixels.extend_from_slice(&row[..row_bytes as usize]);
+    }
+    buffer.unmap();
+    if swap_red_blue {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+    Ok(RgbaImage::from_raw(width, height, pixels).expect("Pixel data matches the texture size"))
+}
+
+fn create_offscreen_texture(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen Target"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: config.usage,
+        view_formats: &[],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn software_ctx(test: &str) -> Option<WgpuCtx<'static>> {
+        match WgpuCtx::new_software_blocking(64, 64) {
+            Ok(ctx) => Some(ctx),
+            Err(e) => {
+                eprintln!("Skipping {test} test: {e}");
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn opaque_batches_are_grouped_by_pipeline_then_material() {
+        let Some(mut ctx) = software_ctx("batching") else {
+            return;
+        };
+        let material = |double_sided| {
+            let params = MaterialParams {
+                double_sided,
+                ..MaterialParams::default()
+            };
+            ctx.create_material(params, None, None)
+        };
+        let (a, b, c) = (material(false), material(true), material(false));
+        let mesh = |name| ctx.meshes().find(name).unwrap();
+        let (cube, sphere, plane) = (mesh("cube"), mesh("sphere"), mesh("plane"));
+        *ctx.objects_mut() = [
+            (cube, &a),
+            (cube, &b),
+            (sphere, &c),
+            (sphere, &a),
+            (cube, &c),
+            (plane, &b),
+            (cube, &a),
+        ]
+        .into_iter()
+        .map(|(mesh, material)| Object {
+            material: Some(material.clone()),
+            ..Object::from(mesh)
+        })
+        .collect();
+
+        let instances = vec![Instance::new(Matrix4::identity()); ctx.objects().len()];
+        let batches = ctx.batch(
+            &instances,
+            &Camera::default(),
+            ctx.surface_config.format,
+            None,
+        );
+        let drawn: Vec<_> = (batches.iter())
+            .map(|batch| {
+                let material = [&a, &b, &c]
+                    .iter()
+                    .position(|material| std::ptr::eq(batch.material, &***material));
+                let mesh = [("cube", cube), ("sphere", sphere), ("plane", plane)]
+                    .into_iter()
+                    .find(|&(_, id)| std::ptr::eq(batch.mesh, ctx.meshes().get(id).unwrap()))
+                    .map(|(name, _)| name);
+                (mesh, material, batch.instances.len())
+            })
+            .collect();
+        assert_eq!(
+            drawn,
+            [
+                (Some("cube"), Some(0), 2),
+                (Some("sphere"), Some(0), 1),
+                (Some("sphere"), Some(2), 1),
+                (Some("cube"), Some(2), 1),
+                (Some("cube"), Some(1), 1),
+                (Some("plane"), Some(1), 1),
+            ]
+        );
+
+        ctx.draw().unwrap();
+        let stats = ctx.frame_stats();
+        assert_eq!(stats.pipeline_switches, 2);
+        assert_eq!(stats.draw_calls, 6);
+    }
+
+    #[test]
+    fn surface_errors_skip_the_frame_unless_out_of_memory() {
+        for (error, expected) in [
+            (wgpu::SurfaceError::Lost, Some(SkippedFrame::Reconfigure)),
+            (
+                wgpu::SurfaceError::Outdated,
+                Some(SkippedFrame::Reconfigure),
+            ),
+            (wgpu::SurfaceError::Timeout, Some(SkippedFrame::Skip)),
+            (wgpu::SurfaceError::Other, Some(SkippedFrame::Skip)),
+            (wgpu::SurfaceError::OutOfMemory, None),
+        ] {
+            let skipped = SkippedFrame::from_error(error.clone());
+            match expected {
+                Some(expected) => assert_eq!(skipped.unwrap(), expected, "{error:?}"),
+                None => assert!(matches!(skipped, Err(ContextError::OutOfMemory))),
+            }
+        }
+    }
+
+    #[test]
+    fn rebuilding_after_losing_the_device_keeps_settings_and_reports_the_scene() {
+        let Some(mut ctx) = software_ctx("rebuild") else {
+            return;
+        };
+        let custom = ctx.add_mesh("big cube", &MeshData::cube(2.0));
+        let cube = ctx.meshes().find("cube").unwrap();
+        *ctx.objects_mut() = vec![Object::from(custom), Object::from(cube)];
+        let clear_color = wgpu::Color::RED;
+        ctx.set_clear_color(clear_color);
+        ctx.set_spinning(false);
+        let texture = ctx.create_render_texture("rebuild test", 16, 16);
+        ctx.add_view(View::default().with_target(ViewTarget::Texture(texture)));
+        ctx.resize((48, 24));
+        let views = ctx.views().len();
+
+        // As set by wgpu's callback
+        *ctx.device_lost.lock() = Some("test".into());
+        assert!(matches!(ctx.draw(), Err(ContextError::DeviceLost(reason)) if reason == "test"));
+        assert!(matches!(ctx.capture(), Err(ContextError::DeviceLost(_))));
+
+        let (mut ctx, reset) = ctx.rebuild_blocking().unwrap();
+        assert_eq!(
+            reset,
+            SceneReset {
+                meshes: vec!["big cube".into()],
+                objects: 2,
+                texture_views: 1,
+            }
+        );
+        assert_eq!(ctx.device_lost(), None);
+        assert_eq!(ctx.clear_color(), clear_color);
+        assert!(!ctx.is_spinning());
+        assert_eq!(ctx.views().len(), views - 1);
+        assert_eq!(ctx.views()[0].camera.aspect, 2.0);
+        assert!(ctx.meshes().find("big cube").is_none());
+        assert!(ctx.meshes().find("cube").is_some());
+        ctx.draw().unwrap();
+        assert_eq!(ctx.capture().unwrap().dimensions(), (48, 24));
     }
 }
@@ -0,0 +1,84 @@
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// How a pipeline tests fragments against the depth buffer and whether it
+/// writes the ones that pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DepthSettings {
+    pub compare: wgpu::CompareFunction,
+    pub write: bool,
+}
+
+impl DepthSettings {
+    /// Drawn over everything, e.g. for overlays.
+    pub const DISABLED: DepthSettings = DepthSettings {
+        compare: wgpu::CompareFunction::Always,
+        write: false,
+    };
+    /// The nearest surface wins. For opaque geometry.
+    pub const OPAQUE: DepthSettings = DepthSettings {
+        compare: wgpu::CompareFunction::Less,
+        write: true,
+    };
+    /// Hidden behind what's already drawn without hiding anything itself.
+    /// For transparent geometry drawn after the opaque.
+    pub const READ_ONLY: DepthSettings = DepthSettings {
+        compare: wgpu::CompareFunction::Less,
+        write: false,
+    };
+
+    pub fn state(self) -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: self.write,
+            depth_compare: self.compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }
+    }
+}
+
+impl Default for DepthSettings {
+    fn default() -> Self {
+        DepthSettings::OPAQUE
+    }
+}
+
+/// A depth texture the size of a render target. It has to be recreated
+/// whenever the target is resized.
+pub struct DepthBuffer {
+    view: wgpu::TextureView,
+}
+
+impl DepthBuffer {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> DepthBuffer {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Buffer"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        DepthBuffer {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        }
+    }
+
+    /// Attaches the buffer to a render pass, cleared to the far plane.
+    pub fn attachment(&self) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &self.view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+        }
+    }
+}
@@ -0,0 +1,455 @@
+//! Golden-image tests for the renderer. Each scene is rendered offscreen,
+//! preferably on a software adapter, and compared against a reference image
+//! in `engine/tests/golden`.
+//!
+//! Run with `ZENYX_BLESS=1` to write the current output as the new references
+//! after an intended change. When a scene doesn't match, the rendered image
+//! and a diff highlighting the mismatched pixels are written to
+//! `engine/tests/golden/failures`.
+
+use std::{f32::consts::TAU, fs, path::PathBuf, sync::Arc, time::Duration};
+
+use cgmath::{EuclideanSpace, Matrix4, Point3, Vector3};
+use image::{Rgba, RgbaImage};
+
+use super::{
+    bench,
+    camera::{Camera, Projection, View, ViewTarget, Viewport},
+    ctx::{Object, WgpuCtx},
+    material::{Material, MaterialParams},
+    texture::TextureOptions,
+};
+use crate::core::assets::material::AlphaMode;
+
+/// How far apart a channel of two pixels may be for them to still match.
+/// Adapters differ slightly in rasterization and blending.
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Fraction of pixels that may mismatch, which allows for differences along
+/// triangle edges.
+const MAX_MISMATCHED: f64 = 0.002;
+
+struct Scene {
+    name: &'static str,
+    size: (u32, u32),
+    /// Time since startup that the scene is rendered at.
+    time: Duration,
+    /// Names of the registered meshes to draw.
+    objects: &'static [&'static str],
+    /// What the objects are shaded with, if not the default material.
+    material: Option<fn(&WgpuCtx<'_>) -> Arc<Material>>,
+    /// Changes the scene further once the objects are in place, e.g. its
+    /// views.
+    setup: Option<fn(&mut WgpuCtx<'_>)>,
+}
+
+/// Orange and white squares, 8 across.
+fn checker(ctx: &WgpuCtx<'_>) -> Arc<Material> {
+    let image = RgbaImage::from_fn(64, 64, |x, y| {
+        if (x / 8 + y / 8) % 2 == 0 {
+            Rgba([255, 140, 0, 255])
+        } else {
+            Rgba([255; 4])
+        }
+    });
+    let albedo = ctx.create_texture("Checker", &image, TextureOptions::COLOR);
+    ctx.create_material(MaterialParams::default(), Some(albedo), None)
+}
+
+fn gold(ctx: &WgpuCtx<'_>) -> Arc<Material> {
+    let params = MaterialParams {
+        base_color: [1.0, 0.77, 0.34, 1.0],
+        metallic: 1.0,
+        roughness: 0.3,
+        ..MaterialParams::default()
+    };
+    ctx.create_material(params, None, None)
+}
+
+/// Half see-through and glowing slightly.
+fn glass(ctx: &WgpuCtx<'_>) -> Arc<Material> {
+    let params = MaterialParams {
+        base_color: [0.2, 0.5, 1.0, 0.5],
+        roughness: 0.1,
+        emissive: [0.0, 0.05, 0.1],
+        alpha_mode: AlphaMode::Blend,
+        ..MaterialParams::default()
+    };
+    ctx.create_material(params, None, None)
+}
+
+/// A checker with every other square cut out, showing the back faces.
+fn cutout(ctx: &WgpuCtx<'_>) -> Arc<Material> {
+    let image = RgbaImage::from_fn(64, 64, |x, y| {
+        let alpha = if (x / 16 + y / 16) % 2 == 0 { 255 } else { 0 };
+        Rgba([80, 200, 120, alpha])
+    });
+    let albedo = ctx.create_texture("Cutout", &image, TextureOptions::COLOR);
+    let params = MaterialParams {
+        alpha_mode: AlphaMode::Mask { cutoff: 0.5 },
+        double_sided: true,
+        ..MaterialParams::default()
+    };
+    ctx.create_material(params, Some(albedo), None)
+}
+
+/// Vertical ridges, 4 across.
+fn ridges(ctx: &WgpuCtx<'_>) -> Arc<Material> {
+    let image = RgbaImage::from_fn(64, 64, |x, _| {
+        let slope = (x as f32 / 16.0 * TAU).sin() * 0.6;
+        let length = (slope * slope + 1.0).sqrt();
+        let encode = |value: f32| ((value / length * 0.5 + 0.5) * 255.0).round() as u8;
+        Rgba([encode(slope), 128, encode(1.0), 255])
+    });
+    let normal = ctx.create_texture("Ridges", &image, TextureOptions::DATA);
+    ctx.create_material(MaterialParams::default(), None, Some(normal))
+}
+
+/// Perspective on the left, and orthographic from above on the right.
+fn split_views(ctx: &mut WgpuCtx<'_>) {
+    let left = Viewport {
+        width: 0.5,
+        ..Viewport::FULL
+    };
+    let right = Viewport {
+        x: 0.5,
+        width: 0.5,
+        ..Viewport::FULL
+    };
+    let above = Camera::new(Point3::new(2.0, 3.0, 2.0), Projection::orthographic(3.0))
+        .looking_at(Point3::origin());
+    *ctx.views_mut() = vec![View::new(Camera::default()).with_viewport(left)];
+    ctx.add_view(View::new(above).with_viewport(right));
+}
+
+/// The cube shows the sphere hidden inside it on each face. It samples the
+/// texture it's drawn with, so it's left out of the texture's view.
+fn render_to_texture(ctx: &mut WgpuCtx<'_>) {
+    let texture = ctx.create_render_texture("Monitor", 128, 128);
+    let screen = ctx.create_material(
+        MaterialParams::default(),
+        Some(texture.texture().clone()),
+        None,
+    );
+    let cube = ctx.meshes().find("cube");
+    for object in ctx.objects_mut() {
+        if Some(object.mesh) == cube {
+            object.material = Some(screen.clone());
+        }
+    }
+    let close = Camera::new(Point3::new(0.0, 0.0, 1.5), Projection::default());
+    ctx.add_view(View::new(close).with_target(ViewTarget::Texture(texture)));
+}
+
+/// Red and blue glass cubes in a row going away from the camera, listed
+/// nearest first so they only blend right if they're sorted.
+fn glass_row(ctx: &mut WgpuCtx<'_>) {
+    let glass = |ctx: &WgpuCtx<'_>, base_color| {
+        let params = MaterialParams {
+            base_color,
+            alpha_mode: AlphaMode::Blend,
+            ..MaterialParams::default()
+        };
+        ctx.create_material(params, None, None)
+    };
+    let red = glass(ctx, [1.0, 0.1, 0.1, 0.5]);
+    let blue = glass(ctx, [0.1, 0.2, 1.0, 0.5]);
+    let cube = ctx.meshes().find("cube").expect("cube is built in");
+    *ctx.objects_mut() = [(0.8, &red), (-0.6, &blue), (-2.0, &red)]
+        .into_iter()
+        .map(|(z, material)| Object {
+            material: Some(material.clone()),
+            transform: Matrix4::from_translation(Vector3::new(z * -0.3, 0.0, z))
+                * Matrix4::from_scale(0.6),
+            ..Object::from(cube)
+        })
+        .collect();
+    let camera = Camera::new(Point3::new(0.0, 0.5, 4.0), Projection::default())
+        .looking_at(Point3::new(0.0, 0.0, -0.6));
+    *ctx.views_mut() = vec![View::new(camera)];
+}
+
+/// The benchmark's scene, small enough that the cubes can be told apart.
+fn cube_grid(ctx: &mut WgpuCtx<'_>) {
+    bench::cube_grid(ctx, 400);
+}
+
+const SCENES: &[Scene] = &[
+    Scene {
+        name: "cube_start",
+        size: (256, 256),
+        time: Duration::ZERO,
+        objects: &["cube"],
+        material: None,
+        setup: None,
+    },
+    Scene {
+        name: "cube_half_second",
+        size: (256, 256),
+        time: Duration::from_millis(500),
+        objects: &["cube"],
+        material: None,
+        setup: None,
+    },
+    Scene {
+        name: "cube_wide",
+        size: (320, 180),
+        time: Duration::from_millis(1250),
+        objects: &["cube"],
+        material: None,
+        setup: None,
+    },
+    Scene {
+        name: "sphere",
+        size: (256, 256),
+        time: Duration::from_millis(500),
+        objects: &["sphere"],
+        material: None,
+        setup: None,
+    },
+    Scene {
+        name: "cylinder",
+        size: (256, 256),
+        time: Duration::from_millis(800),
+        objects: &["cylinder"],
+        material: None,
+        setup: None,
+    },
+    Scene {
+        name: "cube_through_plane",
+        size: (256, 256),
+        time: Duration::from_millis(300),
+        objects: &["plane", "cube"],
+        material: None,
+        setup: None,
+    },
+    Scene {
+        name: "checker_cube",
+        size: (256, 256),
+        time: Duration::from_millis(500),
+        objects: &["cube"],
+        material: Some(checker),
+        setup: None,
+    },
+    Scene {
+        name: "checker_plane",
+        size: (256, 256),
+        time: Duration::from_millis(1400),
+        objects: &["plane"],
+        material: Some(checker),
+        setup: None,
+    },
+    Scene {
+        name: "ridged_sphere",
+        size: (256, 256),
+        time: Duration::from_millis(500),
+        objects: &["sphere"],
+        material: Some(ridges),
+        setup: None,
+    },
+    Scene {
+        name: "gold_sphere",
+        size: (256, 256),
+        time: Duration::from_millis(500),
+        objects: &["sphere"],
+        material: Some(gold),
+        setup: None,
+    },
+    Scene {
+        name: "glass_cube",
+        size: (256, 256),
+        time: Duration::from_millis(500),
+        objects: &["cube"],
+        material: Some(glass),
+        setup: None,
+    },
+    Scene {
+        name: "cutout_cube",
+        size: (256, 256),
+        time: Duration::from_millis(500),
+        objects: &["cube"],
+        material: Some(cutout),
+        setup: None,
+    },
+    Scene {
+        name: "split_views",
+        size: (320, 160),
+        time: Duration::from_millis(300),
+        objects: &["plane", "cube"],
+        material: None,
+        setup: Some(split_views),
+    },
+    Scene {
+        name: "render_to_texture",
+        size: (256, 256),
+        time: Duration::from_millis(500),
+        objects: &["sphere", "cube"],
+        material: None,
+        setup: Some(render_to_texture),
+    },
+    Scene {
+        name: "glass_row",
+        size: (256, 256),
+        time: Duration::ZERO,
+        objects: &[],
+        material: None,
+        setup: Some(glass_row),
+    },
+    Scene {
+        name: "cube_grid",
+        size: (320, 180),
+        time: Duration::from_millis(500),
+        objects: &[],
+        material: None,
+        setup: Some(cube_grid),
+    },
+];
+
+fn reference_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn failure_dir() -> PathBuf {
+    reference_dir().join("failures")
+}
+
+fn bless() -> bool {
+    std::env::var_os("ZENYX_BLESS").is_some_and(|value| value != "0")
+}
+
+struct Comparison {
+    mismatched: usize,
+    diff: RgbaImage,
+}
+
+/// Compares two images of the same size pixel by pixel. In the diff,
+/// mismatched pixels are red and matching ones are a dim grey copy of the
+/// expected image.
+fn compare(expected: &RgbaImage, actual: &RgbaImage) -> Comparison {
+    let mut mismatched = 0;
+    let diff = RgbaImage::from_fn(expected.width(), expected.height(), |x, y| {
+        let (want, got) = (expected.get_pixel(x, y), actual.get_pixel(x, y));
+        let matches = want
+            .0
+            .iter()
+            .zip(got.0)
+            .all(|(a, b)| a.abs_diff(b) <= CHANNEL_TOLERANCE);
+        if matches {
+            let luma = (u16::from(want[0]) + u16::from(want[1]) + u16::from(want[2])) / 9;
+            Rgba([luma as u8, luma as u8, luma as u8, 255])
+        } else {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        }
+    });
+    Comparison { mismatched, diff }
+}
+
+/// Checks one rendered scene against its reference, returning why it failed.
+fn check(scene: &Scene, actual: &RgbaImage) -> Result<(), String> {
+    let reference = reference_dir().join(format!("{}.png", scene.name));
+    if bless() {
+        fs::create_dir_all(reference_dir()).map_err(|e| e.to_string())?;
+        actual.save(&reference).map_err(|e| e.to_string())?;
+        return Ok(());
+    }
+
+    let expected = image::open(&reference)
+        .map_err(|e| {
+            format!(
+                "no reference at {} ({e}), run with ZENYX_BLESS=1 to create it",
+                reference.display()
+            )
+        })?
+        .into_rgba8();
+    let failure = if expected.dimensions() != actual.dimensions() {
+        Some((
+            format!(
+                "size is {:?}, expected {:?}",
+                actual.dimensions(),
+                expected.dimensions()
+            ),
+            None,
+        ))
+    } else {
+        let comparison = compare(&expected, actual);
+        let total = (actual.width() * actual.height()) as usize;
+        (comparison.mismatched as f64 / total as f64 > MAX_MISMATCHED).then(|| {
+            (
+                format!("{} of {total} pixels differ", comparison.mismatched),
+                Some(comparison.diff),
+            )
+        })
+    };
+    let Some((reason, diff)) = failure else {
+        return Ok(());
+    };
+
+    let dir = failure_dir();
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let actual_path = dir.join(format!("{}.actual.png", scene.name));
+    actual.save(&actual_path).map_err(|e| e.to_string())?;
+    if let Some(diff) = diff {
+        diff.save(dir.join(format!("{}.diff.png", scene.name)))
+            .map_err(|e| e.to_string())?;
+    }
+    Err(format!("{reason}, output written to {}", dir.display()))
+}
+
+#[test]
+fn golden_images() {
+    let (width, height) = SCENES[0].size;
+    let mut ctx = match WgpuCtx::new_software_blocking(width, height) {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            eprintln!("Skipping golden-image tests: {e}");
+            return;
+        }
+    };
+    let info = ctx.adapter_info();
+    eprintln!(
+        "Rendering golden images with {} ({:?})",
+        info.name, info.backend
+    );
+
+    let mut failures = Vec::new();
+    for scene in SCENES {
+        let objects = scene
+            .objects
+            .iter()
+            .map(|name| ctx.meshes().find(name).ok_or(name))
+            .collect::<Result<Vec<_>, _>>();
+        let material = scene.material.map(|material| material(&ctx));
+        match objects {
+            Ok(objects) => {
+                *ctx.objects_mut() = (objects.into_iter())
+                    .map(|mesh| Object {
+                        material: material.clone(),
+                        ..Object::from(mesh)
+                    })
+                    .collect();
+            }
+            Err(name) => {
+                failures.push(format!("{}: no mesh named '{name}'", scene.name));
+                continue;
+            }
+        }
+        *ctx.views_mut() = vec![View::default()];
+        if let Some(setup) = scene.setup {
+            setup(&mut ctx);
+        }
+        // Also fits the cameras to their viewports
+        ctx.resize(scene.size);
+        let result = ctx
+            .capture_at(scene.time)
+            .map_err(|e| e.to_string())
+            .and_then(|actual| check(scene, &actual));
+        if let Err(e) = result {
+            failures.push(format!("{}: {e}", scene.name));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "Golden images differ:\n{}",
+        failures.join("\n")
+    );
+}
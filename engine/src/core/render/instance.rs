@@ -0,0 +1,88 @@
+use cgmath::{Matrix, Matrix3, Matrix4, SquareMatrix};
+
+/// Where one copy of a mesh is drawn, read by the vertex shader from a
+/// second vertex buffer that steps once per instance.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Instance {
+    model: [[f32; 4]; 4],
+    /// Transforms normals, which only stay perpendicular to the surface
+    /// under the inverse transpose of the model matrix.
+    normal: [[f32; 3]; 3],
+}
+
+impl Instance {
+    const ATTRIBS: [wgpu::VertexAttribute; 7] = wgpu::vertex_attr_array![
+        3 => Float32x4,
+        4 => Float32x4,
+        5 => Float32x4,
+        6 => Float32x4,
+        7 => Float32x3,
+        8 => Float32x3,
+        9 => Float32x3,
+    ];
+
+    pub fn new(model: Matrix4<f32>) -> Instance {
+        let linear = Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate());
+        let normal = linear
+            .invert()
+            .map_or_else(Matrix3::identity, |inverse| inverse.transpose());
+        Instance {
+            model: model.into(),
+            normal: normal.into(),
+        }
+    }
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// A vertex buffer of instances, replaced with a bigger one when a frame
+/// has more than fit.
+pub struct InstanceBuffer {
+    buffer: wgpu::Buffer,
+    capacity: usize,
+}
+
+impl InstanceBuffer {
+    /// Room for this many instances before the buffer first grows.
+    const INITIAL_CAPACITY: usize = 64;
+
+    pub fn new(device: &wgpu::Device) -> InstanceBuffer {
+        InstanceBuffer {
+            buffer: Self::create(device, Self::INITIAL_CAPACITY),
+            capacity: Self::INITIAL_CAPACITY,
+        }
+    }
+
+    /// Uploads `instances`, which take effect at the next submit.
+    pub fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[Instance]) {
+        if instances.len() > self.capacity {
+            self.capacity = instances.len().next_power_of_two();
+            self.buffer = Self::create(device, self.capacity);
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(instances));
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn create(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Buffer"),
+            size: (capacity * std::mem::size_of::<Instance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+}
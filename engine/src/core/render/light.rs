@@ -0,0 +1,25 @@
+use cgmath::Vector3;
+
+/// A light infinitely far away, such as the sun, and the ambient light that
+/// stops unlit surfaces from being black.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lighting {
+    /// The direction the light travels in.
+    pub direction: Vector3<f32>,
+    /// Linear RGB.
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Linear RGB, added to every surface regardless of its normal.
+    pub ambient: [f32; 3],
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Lighting {
+            direction: Vector3::new(-0.5, -1.0, -0.5),
+            color: [1.0; 3],
+            intensity: 3.0,
+            ambient: [0.03; 3],
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+use super::{binding::Binding, texture::Texture};
+use crate::core::assets::material::{AlphaMode, MaterialDesc};
+
+/// The factors a material is shaded with. Each is multiplied with the
+/// matching map, if the material has one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaterialParams {
+    /// Linear RGBA.
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Linear RGB light given off by the surface.
+    pub emissive: [f32; 3],
+    pub alpha_mode: AlphaMode,
+    /// Whether back faces are drawn too, lit from their side.
+    pub double_sided: bool,
+}
+
+impl Default for MaterialParams {
+    fn default() -> Self {
+        MaterialParams {
+            base_color: [1.0; 4],
+            metallic: 0.0,
+            roughness: 0.5,
+            emissive: [0.0; 3],
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+impl From<&MaterialDesc> for MaterialParams {
+    fn from(desc: &MaterialDesc) -> Self {
+        MaterialParams {
+            base_color: desc.base_color,
+            metallic: desc.metallic,
+            roughness: desc.roughness,
+            emissive: desc.emissive,
+            alpha_mode: desc.alpha_mode,
+            double_sided: desc.double_sided,
+        }
+    }
+}
+
+/// Matches `Material` in the shader.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniform {
+    base_color: [f32; 4],
+    emissive: [f32; 4],
+    metallic: f32,
+    roughness: f32,
+    alpha_mode: u32,
+    alpha_cutoff: f32,
+}
+
+impl From<&MaterialParams> for MaterialUniform {
+    fn from(params: &MaterialParams) -> Self {
+        let [r, g, b] = params.emissive;
+        let (alpha_mode, alpha_cutoff) = match params.alpha_mode {
+            AlphaMode::Opaque => (0, 0.0),
+            AlphaMode::Mask { cutoff } => (1, cutoff),
+            AlphaMode::Blend => (2, 0.0),
+        };
+        MaterialUniform {
+            base_color: params.base_color,
+            emissive: [r, g, b, 0.0],
+            metallic: params.metallic,
+            roughness: params.roughness,
+            alpha_mode,
+            alpha_cutoff,
+        }
+    }
+}
+
+/// The parts of a material that need a render pipeline of their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialVariant {
+    pub blend: bool,
+    pub double_sided: bool,
+}
+
+/// A surface's parameters and maps, bound as group 1 of the PBR shader.
+pub struct Material {
+    params: MaterialParams,
+    albedo: Arc<Texture>,
+    normal: Arc<Texture>,
+    uniform_buffer: wgpu::Buffer,
+}
+
+impl Material {
+    /// The layout of group 1, in the order of [`Material::bindings`].
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 5] {
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let uniform = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(
+                    std::mem::size_of::<MaterialUniform>() as u64
+                ),
+            },
+            count: None,
+        };
+        [uniform, texture(1), sampler(2), texture(3), sampler(4)]
+    }
+
+    /// `normal` is a tangent-space normal map, as used by glTF.
+    pub fn new(
+        device: &wgpu::Device,
+        params: MaterialParams,
+        albedo: Arc<Texture>,
+        normal: Arc<Texture>,
+    ) -> Material {
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Material Uniform Buffer"),
+            contents: bytemuck::bytes_of(&MaterialUniform::from(&params)),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        Material {
+            params,
+            albedo,
+            normal,
+            uniform_buffer,
+        }
+    }
+
+    pub fn params(&self) -> &MaterialParams {
+        &self.params
+    }
+
+    /// Only this material binds its uniform buffer, unlike its maps.
+    pub fn uniform_binding(&self) -> Binding {
+        Binding::Buffer(self.uniform_buffer.clone())
+    }
+
+    pub fn variant(&self) -> MaterialVariant {
+        MaterialVariant {
+            blend: self.params.alpha_mode == AlphaMode::Blend,
+            double_sided: self.params.double_sided,
+        }
+    }
+
+    pub fn albedo(&self) -> &Arc<Texture> {
+        &self.albedo
+    }
+
+    pub fn normal(&self) -> &Arc<Texture> {
+        &self.normal
+    }
+
+    pub fn bindings(&self) -> [Binding; 5] {
+        [
+            self.uniform_binding(),
+            Binding::TextureView(self.albedo.view().clone()),
+            Binding::Sampler(self.albedo.sampler().clone()),
+            Binding::TextureView(self.normal.view().clone()),
+            Binding::Sampler(self.normal.sampler().clone()),
+        ]
+    }
+}
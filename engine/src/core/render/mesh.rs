@@ -0,0 +1,320 @@
+use std::{collections::HashMap, f32::consts::PI, ops::Range};
+
+use cgmath::{InnerSpace, Vector2, Vector3};
+use wgpu::util::DeviceExt;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    /// Texture coordinates, with the origin at the top left of the image.
+    pub uv: [f32; 2],
+}
+
+impl Vertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 3] = [
+        wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 0,
+            format: wgpu::VertexFormat::Float32x3,
+        },
+        wgpu::VertexAttribute {
+            offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+            shader_location: 1,
+            format: wgpu::VertexFormat::Float32x3,
+        },
+        wgpu::VertexAttribute {
+            offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+            shader_location: 2,
+            format: wgpu::VertexFormat::Float32x2,
+        },
+    ];
+
+    pub fn new(position: Vector3<f32>, normal: Vector3<f32>, uv: Vector2<f32>) -> Vertex {
+        Vertex {
+            position: position.into(),
+            normal: normal.into(),
+            uv: uv.into(),
+        }
+    }
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Geometry on the CPU side, before it's uploaded as a [`Mesh`]. Triangles
+/// are indexed and wound counter-clockwise when seen from the front.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    /// Adds a quad from its corners in counter-clockwise order, starting at
+    /// the bottom left of the texture.
+    fn push_quad(&mut self, corners: [Vector3<f32>; 4], normal: Vector3<f32>) {
+        let base = self.vertices.len() as u32;
+        let uvs = [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]];
+        self.vertices.extend(
+            corners
+                .iter()
+                .zip(uvs)
+                .map(|(&corner, uv)| Vertex::new(corner, normal, uv.into())),
+        );
+        self.indices
+            .extend([base, base + 1, base + 2, base + 2, base + 3, base]);
+    }
+
+    /// A cube centred on the origin with flat-shaded faces.
+    pub fn cube(size: f32) -> MeshData {
+        let half = size / 2.0;
+        let (x, y, z) = (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z());
+        // Each face's normal, and the directions right and up across it
+        let faces = [
+            (z, x, y),
+            (-z, -x, y),
+            (x, -z, y),
+            (-x, z, y),
+            (y, x, -z),
+            (-y, x, z),
+        ];
+
+        let mut data = MeshData::default();
+        for (normal, right, up) in faces {
+            let centre = normal * half;
+            let (right, up) = (right * half, up * half);
+            data.push_quad(
+                [
+                    centre - right - up,
+                    centre + right - up,
+                    centre + right + up,
+                    centre - right + up,
+                ],
+                normal,
+            );
+        }
+        data
+    }
+
+    /// A flat plane in the XZ plane facing up, centred on the origin.
+    pub fn plane(width: f32, depth: f32) -> MeshData {
+        let (x, z) = (width / 2.0, depth / 2.0);
+        let mut data = MeshData::default();
+        data.push_quad(
+            [
+                Vector3::new(-x, 0.0, z),
+                Vector3::new(x, 0.0, z),
+                Vector3::new(x, 0.0, -z),
+                Vector3::new(-x, 0.0, -z),
+            ],
+            Vector3::unit_y(),
+        );
+        data
+    }
+
+    /// A UV sphere centred on the origin, with `segments` slices around the
+    /// Y axis and `rings` stacked from pole to pole.
+    pub fn sphere(radius: f32, segments: u32, rings: u32) -> MeshData {
+        let segments = segments.max(3);
+        let rings = rings.max(2);
+        let mut data = MeshData::default();
+        for ring in 0..=rings {
+            let theta = ring as f32 / rings as f32 * PI;
+            for segment in 0..=segments {
+                let phi = segment as f32 / segments as f32 * 2.0 * PI;
+                let normal = Vector3::new(
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                    theta.sin() * phi.cos(),
+                );
+                let uv = Vector2::new(segment as f32 / segments as f32, ring as f32 / rings as f32);
+                data.vertices.push(Vertex::new(normal * radius, normal, uv));
+            }
+        }
+
+        let row = segments + 1;
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let top = ring * row + segment;
+                let bottom = top + row;
+                // The triangles touching a pole would be degenerate
+                if ring != 0 {
+                    data.indices.extend([top, bottom, top + 1]);
+                }
+                if ring != rings - 1 {
+                    data.indices.extend([top + 1, bottom, bottom + 1]);
+                }
+            }
+        }
+        data
+    }
+
+    /// A closed cylinder standing on the Y axis, centred on the origin.
+    pub fn cylinder(radius: f32, height: f32, segments: u32) -> MeshData {
+        let segments = segments.max(3);
+        let half = height / 2.0;
+        let around = |segment: u32| {
+            let phi = segment as f32 / segments as f32 * 2.0 * PI;
+            Vector3::new(phi.sin(), 0.0, phi.cos())
+        };
+
+        let mut data = MeshData::default();
+        for segment in 0..segments {
+            let (left, right) = (around(segment), around(segment + 1));
+            let (u_left, u_right) = (
+                segment as f32 / segments as f32,
+                (segment + 1) as f32 / segments as f32,
+            );
+            let up = Vector3::unit_y() * half;
+            let base = data.vertices.len() as u32;
+            data.vertices.extend([
+                Vertex::new(left * radius - up, left, Vector2::new(u_left, 1.0)),
+                Vertex::new(right * radius - up, right, Vector2::new(u_right, 1.0)),
+                Vertex::new(right * radius + up, right, Vector2::new(u_right, 0.0)),
+                Vertex::new(left * radius + up, left, Vector2::new(u_left, 0.0)),
+            ]);
+            data.indices
+                .extend([base, base + 1, base + 2, base + 2, base + 3, base]);
+        }
+
+        for normal in [Vector3::unit_y(), -Vector3::unit_y()] {
+            let centre = data.vertices.len() as u32;
+            data.vertices
+                .push(Vertex::new(normal * half, normal, Vector2::new(0.5, 0.5)));
+            for segment in 0..segments {
+                // Caps are mapped onto a circle filling the texture
+                let edge = around(segment);
+                data.vertices.push(Vertex::new(
+                    edge * radius + normal * half,
+                    normal,
+                    Vector2::new(0.5 + edge.x / 2.0, 0.5 + edge.z / 2.0),
+                ));
+                let (current, next) = (centre + 1 + segment, centre + 1 + (segment + 1) % segments);
+                if normal.y > 0.0 {
+                    data.indices.extend([centre, current, next]);
+                } else {
+                    data.indices.extend([centre, next, current]);
+                }
+            }
+        }
+        data
+    }
+
+    /// Replaces the normals with ones averaged from the triangles around
+    /// each vertex, weighted by their area.
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] =
+                [0, 1, 2].map(|i| Vector3::from(self.vertices[triangle[i] as usize].position));
+            let face = (b - a).cross(c - a);
+            for &index in triangle {
+                normals[index as usize] += face;
+            }
+        }
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            if normal.magnitude2() > 0.0 {
+                vertex.normal = normal.normalize().into();
+            }
+        }
+    }
+}
+
+/// Geometry uploaded to the GPU, ready to draw.
+pub struct Mesh {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    layout: wgpu::VertexBufferLayout<'static>,
+}
+
+impl Mesh {
+    pub fn new(device: &wgpu::Device, name: &str, data: &MeshData) -> Mesh {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{name} Vertex Buffer")),
+            contents: bytemuck::cast_slice(&data.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{name} Index Buffer")),
+            contents: bytemuck::cast_slice(&data.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        Mesh {
+            vertex_buffer,
+            index_buffer,
+            index_count: data.indices.len() as u32,
+            layout: Vertex::desc(),
+        }
+    }
+
+    /// How the vertex buffer is laid out, for building pipelines that can
+    /// draw this mesh.
+    pub fn layout(&self) -> &wgpu::VertexBufferLayout<'static> {
+        &self.layout
+    }
+
+    /// Draws a copy for each of `instances` in the instance buffer, which
+    /// has to be bound already.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass<'_>, instances: Range<u32>) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.index_count, 0, instances);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MeshId(u32);
+
+/// The meshes the renderer can draw, looked up by id or by name.
+#[derive(Default)]
+pub struct MeshRegistry {
+    meshes: HashMap<MeshId, Mesh>,
+    names: HashMap<String, MeshId>,
+    next_id: u32,
+}
+
+impl MeshRegistry {
+    /// Registers a mesh under a name. A mesh already registered under the
+    /// name is replaced and its id now refers to the new mesh.
+    pub fn add(&mut self, name: &str, mesh: Mesh) -> MeshId {
+        let id = *self.names.entry(name.to_string()).or_insert_with(|| {
+            self.next_id += 1;
+            MeshId(self.next_id)
+        });
+        self.meshes.insert(id, mesh);
+        id
+    }
+
+    pub fn get(&self, id: MeshId) -> Option<&Mesh> {
+        self.meshes.get(&id)
+    }
+
+    pub fn find(&self, name: &str) -> Option<MeshId> {
+        self.names.get(name).copied()
+    }
+
+    pub fn remove(&mut self, id: MeshId) -> Option<Mesh> {
+        self.names.retain(|_, named| *named != id);
+        self.meshes.remove(&id)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = (&str, MeshId)> {
+        self.names.iter().map(|(name, &id)| (name.as_str(), id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.meshes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.meshes.is_empty()
+    }
+}
@@ -1,32 +1,112 @@
 use std::sync::Arc;
+use std::time::Instant;
 
-use ctx::WgpuCtx;
-use log::{debug, trace};
+use bus::{Notice, RenderMessage, RenderRequest, RequestError};
+use camera::controller::{InputState, OrbitController};
+use ctx::{ContextError, WgpuCtx};
+use log::{debug, error, trace, warn};
 use winit::application::ApplicationHandler;
 use winit::event::WindowEvent;
 use winit::event_loop::ControlFlow;
 use winit::event_loop::{ActiveEventLoop, EventLoop};
 use winit::window::{Window, WindowId};
+pub mod bench;
+pub mod binding;
+pub mod bus;
+pub mod camera;
+pub mod capture;
 pub mod ctx;
+pub mod depth;
+#[cfg(test)]
+mod golden;
+pub mod instance;
+pub mod light;
+pub mod material;
+pub mod mesh;
+pub mod pipeline;
+pub mod texture;
 
 #[derive(Default)]
 pub struct App<'window> {
     window: Option<Arc<Window>>,
     ctx: Option<WgpuCtx<'window>>,
+    input: InputState,
+    last_frame: Option<Instant>,
+    /// Requests that arrived before the renderer was created.
+    pending: Vec<RenderMessage>,
+    /// Why the event loop was stopped, if it wasn't closed.
+    error: Option<anyhow::Error>,
 }
 
-impl ApplicationHandler for App<'_> {
+impl App<'_> {
+    fn fail(&mut self, event_loop: &ActiveEventLoop, error: anyhow::Error) {
+        error!("{error:#}");
+        self.error = Some(error);
+        event_loop.exit();
+    }
+
+    /// Rebuilds the context after its device is lost. Any other error stops
+    /// the renderer.
+    fn recover(&mut self, event_loop: &ActiveEventLoop, error: ContextError) {
+        let Some(ctx) = self.ctx.take() else {
+            return;
+        };
+        let error = match error {
+            ContextError::DeviceLost(reason) => {
+                warn!("Rendering device lost ({reason}), rebuilding the context");
+                // Nothing here keeps what was uploaded, so the window shows
+                // the default scene until it's loaded again
+                match ctx.rebuild_blocking() {
+                    Ok((ctx, reset)) => {
+                        bus::notify(Notice::SceneReset(reset));
+                        self.ctx = Some(ctx);
+                        return;
+                    }
+                    Err(e) => e,
+                }
+            }
+            e => e,
+        };
+        self.fail(
+            event_loop,
+            anyhow::Error::new(error).context("Stopped the renderer"),
+        );
+    }
+}
+
+impl ApplicationHandler<RenderMessage> for App<'_> {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if self.window.is_none() {
             let win_attr = Window::default_attributes().with_title("Zenyx");
-            let window = Arc::new(
-                event_loop
-                    .create_window(win_attr)
-                    .expect("create window err."),
-            );
+            let window = match event_loop.create_window(win_attr) {
+                Ok(window) => Arc::new(window),
+                Err(e) => {
+                    self.fail(
+                        event_loop,
+                        anyhow::Error::new(e).context("Failed to create the window"),
+                    );
+                    return;
+                }
+            };
             self.window = Some(window.clone());
-            let wgpu_ctx = WgpuCtx::new_blocking(window.clone()).unwrap();
-            self.ctx = Some(wgpu_ctx)
+            let mut wgpu_ctx = match WgpuCtx::new_blocking(window.clone()) {
+                Ok(ctx) => ctx,
+                Err(e) => {
+                    self.fail(
+                        event_loop,
+                        anyhow::Error::new(e).context("Failed to create the renderer"),
+                    );
+                    return;
+                }
+            };
+            if let Some(view) = wgpu_ctx.views_mut().first_mut() {
+                view.controller = Some(Box::new(OrbitController::default()));
+            }
+            for message in self.pending.drain(..) {
+                message.handle(&mut wgpu_ctx, Some(&window));
+            }
+            self.ctx = Some(wgpu_ctx);
+            self.input.set_window_size(window.inner_size());
         }
     }
 
@@ -36,15 +116,24 @@ impl ApplicationHandler for App<'_> {
         _window_id: WindowId,
         event: WindowEvent,
     ) {
+        self.input.handle_event(&event);
         match event {
             WindowEvent::CloseRequested => {
+                debug!("Window closed");
                 event_loop.exit();
-                debug!("Window closed, exiting");
-                std::process::exit(0)
             }
             WindowEvent::RedrawRequested => {
                 if let Some(ctx) = &mut self.ctx {
-                    ctx.draw();
+                    let now = Instant::now();
+                    let dt = self
+                        .last_frame
+                        .map_or(0.0, |last| now.duration_since(last).as_secs_f32());
+                    self.last_frame = Some(now);
+                    ctx.update_views(&self.input, dt);
+                    self.input.end_frame();
+                    if let Err(e) = ctx.draw() {
+                        self.recover(event_loop, e);
+                    }
                 }
                 if let Some(window) = &self.window {
                     window.request_redraw();
@@ -63,10 +152,30 @@ impl ApplicationHandler for App<'_> {
             _ => trace!("Unhandled window event"),
         }
     }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, message: RenderMessage) {
+        if let RenderRequest::Close = message.request() {
+            debug!("Closing the window");
+            event_loop.exit();
+            message.done();
+            return;
+        }
+        match &mut self.ctx {
+            Some(ctx) => message.handle(ctx, self.window.as_deref()),
+            // Handled once the window and its renderer are created
+            None if self.window.is_none() => self.pending.push(message),
+            None => message.fail(RequestError::NoRenderer),
+        }
+    }
 }
 
-pub fn init_renderer(event_loop: EventLoop<()>) {
+/// Runs the window's event loop until the window is closed, or fails if the
+/// window or its renderer stopped working. Requests made through [`bus`]
+/// before the window is created, which needs the bus connected to
+/// `event_loop` first, are handled once it is.
+pub fn init_renderer(event_loop: EventLoop<RenderMessage>) -> anyhow::Result<()> {
     event_loop.set_control_flow(ControlFlow::Poll);
     let mut app = App::default();
-    event_loop.run_app(&mut app).unwrap();
+    event_loop.run_app(&mut app)?;
+    app.error.map_or(Ok(()), Err)
 }
@@ -0,0 +1,126 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use super::{depth::DepthSettings, instance::Instance, material::MaterialVariant};
+
+const PBR_SHADER: &str = include_str!("shaders/pbr.wgsl");
+
+/// Everything that decides which render pipeline draws an object. Every
+/// pipeline takes instances from a second vertex buffer.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub vertex_layout: wgpu::VertexBufferLayout<'static>,
+    pub variant: MaterialVariant,
+    pub depth: DepthSettings,
+    pub format: wgpu::TextureFormat,
+}
+
+/// Render pipelines for the PBR shader, each built the first time a
+/// combination of material and vertex layout is drawn.
+pub struct PipelineCache {
+    shader: wgpu::ShaderModule,
+    layout: wgpu::PipelineLayout,
+    pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+}
+
+impl PipelineCache {
+    pub fn new(
+        device: &wgpu::Device,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+    ) -> PipelineCache {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("PBR Shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(PBR_SHADER)),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("PBR Pipeline Layout"),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+        PipelineCache {
+            shader,
+            layout,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, key: &PipelineKey) -> Option<&wgpu::RenderPipeline> {
+        self.pipelines.get(key)
+    }
+
+    pub fn get_or_create(
+        &mut self,
+        device: &wgpu::Device,
+        key: &PipelineKey,
+    ) -> &wgpu::RenderPipeline {
+        if !self.pipelines.contains_key(key) {
+            let pipeline = self.create(device, key);
+            self.pipelines.insert(key.clone(), pipeline);
+        }
+        &self.pipelines[key]
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    /// Drops every pipeline, for when the ones built so far won't be used
+    /// again.
+    pub fn clear(&mut self) {
+        self.pipelines.clear();
+    }
+
+    fn create(&self, device: &wgpu::Device, key: &PipelineKey) -> wgpu::RenderPipeline {
+        let (blend, depth) = if key.variant.blend {
+            // Blended surfaces are hidden by opaque ones but don't hide
+            // each other
+            let depth = DepthSettings {
+                write: false,
+                ..key.depth
+            };
+            (wgpu::BlendState::ALPHA_BLENDING, depth)
+        } else {
+            (wgpu::BlendState::REPLACE, key.depth)
+        };
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("PBR Render Pipeline"),
+            layout: Some(&self.layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: Some("vs_main"),
+                buffers: &[key.vertex_layout.clone(), Instance::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: key.format,
+                    blend: Some(blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: (!key.variant.double_sided).then_some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(depth.state()),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        })
+    }
+}
@@ -0,0 +1,153 @@
+// Metallic-roughness shading with a single directional light, following the
+// glTF 2.0 material model.
+
+const PI: f32 = 3.14159265;
+
+const ALPHA_OPAQUE: u32 = 0u;
+const ALPHA_MASK: u32 = 1u;
+
+struct Scene {
+    view_proj: mat4x4<f32>,
+    camera_position: vec4<f32>,
+    // The direction the light travels in
+    light_direction: vec4<f32>,
+    // Colour times intensity
+    light_color: vec4<f32>,
+    ambient: vec4<f32>,
+};
+
+struct Material {
+    base_color: vec4<f32>,
+    emissive: vec4<f32>,
+    metallic: f32,
+    roughness: f32,
+    alpha_mode: u32,
+    alpha_cutoff: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> scene: Scene;
+
+@group(1) @binding(0)
+var<uniform> material: Material;
+@group(1) @binding(1)
+var albedo_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var albedo_sampler: sampler;
+@group(1) @binding(3)
+var normal_texture: texture_2d<f32>;
+@group(1) @binding(4)
+var normal_sampler: sampler;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+};
+
+struct InstanceInput {
+    @location(3) model_0: vec4<f32>,
+    @location(4) model_1: vec4<f32>,
+    @location(5) model_2: vec4<f32>,
+    @location(6) model_3: vec4<f32>,
+    @location(7) normal_0: vec3<f32>,
+    @location(8) normal_1: vec3<f32>,
+    @location(9) normal_2: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(input: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let normal_matrix = mat3x3<f32>(instance.normal_0, instance.normal_1, instance.normal_2);
+    var output: VertexOutput;
+    let world_position = model * vec4<f32>(input.position, 1.0);
+    output.clip_position = scene.view_proj * world_position;
+    output.normal = normal_matrix * input.normal;
+    output.world_position = world_position.xyz;
+    output.uv = input.uv;
+    return output;
+}
+
+// Applies the normal map in a tangent frame worked out from how the position
+// and UVs change across the screen, so meshes don't need tangents.
+fn mapped_normal(normal: vec3<f32>, position: vec3<f32>, uv: vec2<f32>) -> vec3<f32> {
+    let mapped = textureSample(normal_texture, normal_sampler, uv).xyz * 2.0 - 1.0;
+    let dp1 = dpdx(position);
+    let dp2 = dpdy(position);
+    let duv1 = dpdx(uv);
+    let duv2 = dpdy(uv);
+    let dp2_perp = cross(dp2, normal);
+    let dp1_perp = cross(normal, dp1);
+    let tangent = dp2_perp * duv1.x + dp1_perp * duv2.x;
+    // Normal maps point +Y up the image, which is towards smaller V
+    let bitangent = -(dp2_perp * duv1.y + dp1_perp * duv2.y);
+    let scale = max(dot(tangent, tangent), dot(bitangent, bitangent));
+    if scale <= 0.0 {
+        return normal;
+    }
+    let frame = mat3x3<f32>(tangent * inverseSqrt(scale), bitangent * inverseSqrt(scale), normal);
+    return normalize(frame * mapped);
+}
+
+// Trowbridge-Reitz GGX normal distribution
+fn distribution(n_dot_h: f32, roughness: f32) -> f32 {
+    let a2 = pow(roughness, 4.0);
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Smith's shadowing-masking with the Schlick-GGX approximation
+fn geometry(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+fn fresnel(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+@fragment
+fn fs_main(input: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    let base_color = textureSample(albedo_texture, albedo_sampler, input.uv) * material.base_color;
+    // Back faces are only drawn for double-sided materials
+    let geometric_normal = normalize(input.normal) * select(-1.0, 1.0, front_facing);
+    let n = mapped_normal(geometric_normal, input.world_position, input.uv);
+
+    var alpha = base_color.a;
+    if material.alpha_mode == ALPHA_MASK {
+        if alpha < material.alpha_cutoff {
+            discard;
+        }
+        alpha = 1.0;
+    } else if material.alpha_mode == ALPHA_OPAQUE {
+        alpha = 1.0;
+    }
+
+    let v = normalize(scene.camera_position.xyz - input.world_position);
+    let l = normalize(-scene.light_direction.xyz);
+    let h = normalize(v + l);
+    let n_dot_v = max(dot(n, v), 1e-4);
+    let n_dot_l = max(dot(n, l), 0.0);
+    let n_dot_h = max(dot(n, h), 0.0);
+
+    let metallic = clamp(material.metallic, 0.0, 1.0);
+    let roughness = clamp(material.roughness, 0.04, 1.0);
+    let f0 = mix(vec3<f32>(0.04), base_color.rgb, metallic);
+    let f = fresnel(max(dot(h, v), 0.0), f0);
+    let specular = distribution(n_dot_h, roughness) * geometry(n_dot_v, n_dot_l, roughness) * f
+        / max(4.0 * n_dot_v * n_dot_l, 1e-4);
+    let diffuse = (1.0 - f) * (1.0 - metallic) * base_color.rgb / PI;
+
+    let lit = (diffuse + specular) * scene.light_color.rgb * n_dot_l;
+    let ambient = scene.ambient.rgb * base_color.rgb;
+    return vec4<f32>(lit + ambient + material.emissive.rgb, alpha);
+}
@@ -0,0 +1,508 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Weak},
+};
+
+use image::{RgbaImage, imageops::FilterType};
+use thiserror::Error;
+use wgpu::util::DeviceExt;
+
+use super::depth::DepthBuffer;
+
+#[derive(Debug, Error)]
+pub enum TextureError {
+    #[error("Failed to read {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to decode image: {0}")]
+    Decode(#[from] image::ImageError),
+    #[error("Failed to parse KTX2 texture: {0}")]
+    Ktx2(#[from] ktx2::ParseError),
+    #[error("Unsupported KTX2 texture: {0}")]
+    UnsupportedKtx2(String),
+}
+
+/// How a texture is filtered and what happens to coordinates outside it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerConfig {
+    pub address_mode: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    /// Maximum anisotropy, from 1 (off) to 16. Only used when every filter
+    /// is linear.
+    pub anisotropy: u16,
+}
+
+impl SamplerConfig {
+    /// Smoothly filtered and repeating.
+    pub const LINEAR: SamplerConfig = SamplerConfig {
+        address_mode: wgpu::AddressMode::Repeat,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        anisotropy: 1,
+    };
+    /// Unfiltered and repeating, for pixel art.
+    pub const NEAREST: SamplerConfig = SamplerConfig {
+        address_mode: wgpu::AddressMode::Repeat,
+        mag_filter: wgpu::FilterMode::Nearest,
+        min_filter: wgpu::FilterMode::Nearest,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        anisotropy: 1,
+    };
+
+    fn create(self, device: &wgpu::Device, label: &str) -> wgpu::Sampler {
+        let linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .iter()
+            .all(|&filter| filter == wgpu::FilterMode::Linear);
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(&format!("{label} Sampler")),
+            address_mode_u: self.address_mode,
+            address_mode_v: self.address_mode,
+            address_mode_w: self.address_mode,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            anisotropy_clamp: if linear {
+                self.anisotropy.clamp(1, 16)
+            } else {
+                1
+            },
+            ..Default::default()
+        })
+    }
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        SamplerConfig::LINEAR
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureOptions {
+    /// Whether the texels are sRGB-encoded colours rather than linear data.
+    /// KTX2 files carry this themselves, so it's ignored for them.
+    pub srgb: bool,
+    /// Whether to generate a full mip chain. KTX2 files use the levels
+    /// they contain instead.
+    pub mipmaps: bool,
+    pub sampler: SamplerConfig,
+}
+
+impl TextureOptions {
+    /// For colour textures such as albedo maps.
+    pub const COLOR: TextureOptions = TextureOptions {
+        srgb: true,
+        mipmaps: true,
+        sampler: SamplerConfig::LINEAR,
+    };
+    /// For textures holding other data, such as normal maps.
+    pub const DATA: TextureOptions = TextureOptions {
+        srgb: false,
+        mipmaps: true,
+        sampler: SamplerConfig::LINEAR,
+    };
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        TextureOptions::COLOR
+    }
+}
+
+/// A sampled 2D texture on the GPU.
+pub struct Texture {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+}
+
+const KTX2_MAGIC: &[u8] = b"\xABKTX 20\xBB\r\n\x1A\n";
+
+/// KTX2 formats that can be uploaded as they are.
+const KTX2_FORMATS: &[(ktx2::Format, wgpu::TextureFormat)] = &[
+    (ktx2::Format::R8_UNORM, wgpu::TextureFormat::R8Unorm),
+    (ktx2::Format::R8G8_UNORM, wgpu::TextureFormat::Rg8Unorm),
+    (
+        ktx2::Format::R8G8B8A8_UNORM,
+        wgpu::TextureFormat::Rgba8Unorm,
+    ),
+    (
+        ktx2::Format::R8G8B8A8_SRGB,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+    ),
+    (
+        ktx2::Format::B8G8R8A8_UNORM,
+        wgpu::TextureFormat::Bgra8Unorm,
+    ),
+    (
+        ktx2::Format::B8G8R8A8_SRGB,
+        wgpu::TextureFormat::Bgra8UnormSrgb,
+    ),
+    (
+        ktx2::Format::R16G16B16A16_SFLOAT,
+        wgpu::TextureFormat::Rgba16Float,
+    ),
+    (
+        ktx2::Format::BC1_RGBA_UNORM_BLOCK,
+        wgpu::TextureFormat::Bc1RgbaUnorm,
+    ),
+    (
+        ktx2::Format::BC1_RGBA_SRGB_BLOCK,
+        wgpu::TextureFormat::Bc1RgbaUnormSrgb,
+    ),
+    (
+        ktx2::Format::BC3_UNORM_BLOCK,
+        wgpu::TextureFormat::Bc3RgbaUnorm,
+    ),
+    (
+        ktx2::Format::BC3_SRGB_BLOCK,
+        wgpu::TextureFormat::Bc3RgbaUnormSrgb,
+    ),
+    (
+        ktx2::Format::BC4_UNORM_BLOCK,
+        wgpu::TextureFormat::Bc4RUnorm,
+    ),
+    (
+        ktx2::Format::BC5_UNORM_BLOCK,
+        wgpu::TextureFormat::Bc5RgUnorm,
+    ),
+    (
+        ktx2::Format::BC7_UNORM_BLOCK,
+        wgpu::TextureFormat::Bc7RgbaUnorm,
+    ),
+    (
+        ktx2::Format::BC7_SRGB_BLOCK,
+        wgpu::TextureFormat::Bc7RgbaUnormSrgb,
+    ),
+    (
+        ktx2::Format::ETC2_R8G8B8A8_UNORM_BLOCK,
+        wgpu::TextureFormat::Etc2Rgba8Unorm,
+    ),
+    (
+        ktx2::Format::ETC2_R8G8B8A8_SRGB_BLOCK,
+        wgpu::TextureFormat::Etc2Rgba8UnormSrgb,
+    ),
+    (
+        ktx2::Format::ASTC_4x4_UNORM_BLOCK,
+        wgpu::TextureFormat::Astc {
+            block: wgpu::AstcBlock::B4x4,
+            channel: wgpu::AstcChannel::Unorm,
+        },
+    ),
+    (
+        ktx2::Format::ASTC_4x4_SRGB_BLOCK,
+        wgpu::TextureFormat::Astc {
+            block: wgpu::AstcBlock::B4x4,
+            channel: wgpu::AstcChannel::UnormSrgb,
+        },
+    ),
+];
+
+/// Features that let compressed KTX2 textures be used, if the adapter has
+/// them.
+pub const COMPRESSION_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_COMPRESSION_BC
+    .union(wgpu::Features::TEXTURE_COMPRESSION_ETC2)
+    .union(wgpu::Features::TEXTURE_COMPRESSION_ASTC);
+
+impl Texture {
+    /// Reads a PNG, JPEG or KTX2 file.
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path,
+        options: TextureOptions,
+    ) -> Result<Texture, TextureError> {
+        let bytes = fs::read(path).map_err(|source| TextureError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Texture::from_bytes(device, queue, &path.display().to_string(), &bytes, options)
+    }
+
+    /// Decodes an encoded image, telling the format from its contents.
+    pub fn from_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+        bytes: &[u8],
+        options: TextureOptions,
+    ) -> Result<Texture, TextureError> {
+        if bytes.starts_with(KTX2_MAGIC) {
+            return Texture::from_ktx2(device, queue, label, bytes, options.sampler);
+        }
+        let image = image::load_from_memory(bytes)?.into_rgba8();
+        Ok(Texture::from_image(device, queue, label, &image, options))
+    }
+
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+        image: &RgbaImage,
+        options: TextureOptions,
+    ) -> Texture {
+        let (width, height) = image.dimensions();
+        let mut data = image.as_raw().clone();
+        let mut level_count = 1;
+        if options.mipmaps {
+            // Mips are downsampled from the full image each time, which
+            // blurs less than downsampling the previous level
+            let (mut level_width, mut level_height) = (width, height);
+            while level_width > 1 || level_height > 1 {
+                level_width = (level_width / 2).max(1);
+                level_height = (level_height / 2).max(1);
+                let level =
+                    image::imageops::resize(image, level_width, level_height, FilterType::Triangle);
+                data.extend_from_slice(level.as_raw());
+                level_count += 1;
+            }
+        }
+        let format = if options.srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        };
+        Texture::create(
+            device,
+            queue,
+            label,
+            (width, height, level_count),
+            format,
+            &data,
+            options.sampler,
+        )
+    }
+
+    /// A 1x1 texture of a single colour, for materials without a map.
+    pub fn solid(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+        color: [u8; 4],
+        srgb: bool,
+    ) -> Texture {
+        let image = RgbaImage::from_pixel(1, 1, image::Rgba(color));
+        let options = TextureOptions {
+            srgb,
+            mipmaps: false,
+            sampler: SamplerConfig::LINEAR,
+        };
+        Texture::from_image(device, queue, label, &image, options)
+    }
+
+    fn from_ktx2(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+        bytes: &[u8],
+        sampler: SamplerConfig,
+    ) -> Result<Texture, TextureError> {
+        let unsupported = |message: String| TextureError::UnsupportedKtx2(message);
+        let reader = ktx2::Reader::new(bytes)?;
+        let header = reader.header();
+        if let Some(scheme) = header.supercompression_scheme {
+            return Err(unsupported(format!("{scheme:?} supercompression")));
+        }
+        if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+            return Err(unsupported(
+                "only single 2D images are supported, not arrays, cubemaps or volumes".into(),
+            ));
+        }
+        let ktx_format = header
+            .format
+            .ok_or_else(|| unsupported("Basis Universal textures need transcoding".into()))?;
+        let format = KTX2_FORMATS
+            .iter()
+            .find(|(ktx, _)| *ktx == ktx_format)
+            .map(|&(_, format)| format)
+            .ok_or_else(|| unsupported(format!("the {ktx_format:?} format")))?;
+        if !device.features().contains(format.required_features()) {
+            return Err(unsupported(format!("the GPU can't sample {format:?}")));
+        }
+        let (block_width, block_height) = format.block_dimensions();
+        let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+        if width % block_width != 0 || height % block_height != 0 {
+            return Err(unsupported(format!(
+                "{width}x{height} isn't a multiple of the {block_width}x{block_height} block size"
+            )));
+        }
+
+        // Levels are stored from the largest down, which is the order wgpu
+        // wants them in too
+        let data: Vec<u8> = reader
+            .levels()
+            .flat_map(|level| level.data.iter().copied())
+            .collect();
+        let level_count = header.level_count.max(1);
+        Ok(Texture::create(
+            device,
+            queue,
+            label,
+            (width, height, level_count),
+            format,
+            &data,
+            sampler,
+        ))
+    }
+
+    fn create(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+        (width, height, mip_level_count): (u32, u32, u32),
+        format: wgpu::TextureFormat,
+        data: &[u8],
+        sampler: SamplerConfig,
+    ) -> Texture {
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::MipMajor,
+            data,
+        );
+        Texture {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            sampler: sampler.create(device, label),
+            texture,
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.texture.width(), self.texture.height())
+    }
+
+    pub fn mip_level_count(&self) -> u32 {
+        self.texture.mip_level_count()
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.texture.format()
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+}
+
+/// A texture that views can draw into and materials can then sample, with
+/// a depth buffer of its own.
+pub struct RenderTexture {
+    texture: Arc<Texture>,
+    depth_buffer: DepthBuffer,
+}
+
+impl RenderTexture {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub fn new(device: &wgpu::Device, label: &str, width: u32, height: u32) -> RenderTexture {
+        let (width, height) = (width.max(1), height.max(1));
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let sampler = SamplerConfig {
+            address_mode: wgpu::AddressMode::ClampToEdge,
+            ..SamplerConfig::LINEAR
+        };
+        let texture = Texture {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            sampler: sampler.create(device, label),
+            texture,
+        };
+        RenderTexture {
+            texture: Arc::new(texture),
+            depth_buffer: DepthBuffer::new(device, width, height),
+        }
+    }
+
+    /// What to give a material to show what was drawn.
+    pub fn texture(&self) -> &Arc<Texture> {
+        &self.texture
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.texture.size()
+    }
+
+    pub fn depth_buffer(&self) -> &DepthBuffer {
+        &self.depth_buffer
+    }
+}
+
+/// Textures loaded from files, shared by everything that loads the same
+/// file with the same options. A texture is dropped once nothing holds it,
+/// and loaded again the next time it's asked for.
+#[derive(Default)]
+pub struct TextureCache {
+    textures: HashMap<(PathBuf, TextureOptions), Weak<Texture>>,
+}
+
+impl TextureCache {
+    pub fn load(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path,
+        options: TextureOptions,
+    ) -> Result<Arc<Texture>, TextureError> {
+        // Different spellings of the same path share a texture
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let key = (path, options);
+        if let Some(texture) = self.textures.get(&key).and_then(Weak::upgrade) {
+            return Ok(texture);
+        }
+        let texture = Arc::new(Texture::load(device, queue, &key.0, options)?);
+        self.textures
+            .retain(|_, texture| texture.strong_count() > 0);
+        self.textures.insert(key, Arc::downgrade(&texture));
+        Ok(texture)
+    }
+
+    /// How many cached textures are still in use.
+    pub fn len(&self) -> usize {
+        self.textures
+            .values()
+            .filter(|texture| texture.strong_count() > 0)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, anyhow};
+use log::warn;
+
+use super::handler::{COMMAND_MANAGER, CommandManager};
+use crate::core::workspace;
+
+pub fn aliases_path() -> anyhow::Result<PathBuf> {
+    Ok(workspace::get_working_dir()?.join("aliases.conf"))
+}
+
+/// Splits a `name=expansion` definition.
+pub fn parse_definition(definition: &str) -> anyhow::Result<(&str, &str)> {
+    let (name, expansion) = definition
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected name=expansion, got '{}'", definition))?;
+    Ok((name.trim(), expansion.trim()))
+}
+
+/// Registers the aliases saved in the workspace.
+pub fn load_aliases() -> anyhow::Result<()> {
+    read_aliases(&aliases_path()?, &COMMAND_MANAGER.read())
+}
+
+/// Saves the aliases to the workspace.
+pub fn save_aliases() -> anyhow::Result<()> {
+    // `alias` and `unalias` save while the shell holds a read lock
+    write_aliases(&aliases_path()?, &COMMAND_MANAGER.read_recursive())
+}
+
+/// Registers the aliases saved at `path` with `manager`. A missing file just
+/// means no aliases have been defined yet.
+fn read_aliases(path: &Path, manager: &CommandManager) -> anyhow::Result<()> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_definition(line) {
+            Ok((name, expansion)) => manager.add_alias(name, expansion),
+            Err(e) => warn!("{}:{}: {}", path.display(), number + 1, e),
+        }
+    }
+    Ok(())
+}
+
+fn write_aliases(path: &Path, manager: &CommandManager) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    let aliases = manager.aliases.read();
+    let mut sorted: Vec<_> = aliases.iter().collect();
+    sorted.sort();
+    let mut contents = String::from("# Aliases defined with the `alias` command\n");
+    for (name, expansion) in sorted {
+        contents.push_str(&format!("{}={}\n", name, expansion));
+    }
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn aliases(manager: &CommandManager) -> HashMap<String, String> {
+        manager.aliases.read().clone()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("zenyx-aliases-{}-{name}.conf", std::process::id()))
+    }
+
+    #[test]
+    fn definitions_split_at_the_first_equals_sign() {
+        assert_eq!(parse_definition("ll = ls -l").unwrap(), ("ll", "ls -l"));
+        assert_eq!(
+            parse_definition("eq=echo a=b | grep =").unwrap(),
+            ("eq", "echo a=b | grep =")
+        );
+        assert_eq!(
+            parse_definition("ll").unwrap_err().to_string(),
+            "Expected name=expansion, got 'll'"
+        );
+    }
+
+    #[test]
+    fn aliases_survive_save_and_load() {
+        let path = temp_path("round-trip");
+        let saved = CommandManager::init();
+        saved.add_alias("Greet", "echo \"hello there\"");
+        saved.add_alias("eq", "echo a=b | grep =");
+        saved.add_alias("quiet", "  history clear  ");
+        write_aliases(&path, &saved).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "# Aliases defined with the `alias` command",
+                "eq=echo a=b | grep =",
+                "greet=echo \"hello there\"",
+                "quiet=history clear",
+            ]
+        );
+
+        let loaded = CommandManager::init();
+        read_aliases(&path, &loaded).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(aliases(&loaded), aliases(&saved));
+    }
+
+    #[test]
+    fn loading_skips_comments_and_bad_lines() {
+        let path = temp_path("bad-lines");
+        fs::write(&path, "# comment\n\nno equals sign\n LL = ls -l \n").unwrap();
+        let manager = CommandManager::init();
+        read_aliases(&path, &manager).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            aliases(&manager),
+            HashMap::from([("ll".to_string(), "ls -l".to_string())])
+        );
+    }
+
+    #[test]
+    fn missing_file_loads_nothing() {
+        let manager = CommandManager::init();
+        read_aliases(&temp_path("missing"), &manager).unwrap();
+        assert!(aliases(&manager).is_empty());
+    }
+}
@@ -0,0 +1,490 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use super::completion::{complete_path, filter_prefix};
+
+#[derive(Debug, Error)]
+pub enum ArgError {
+    #[error("Missing required argument '{0}'")]
+    Missing(&'static str),
+    #[error("Too many arguments: expected at most {expected}, got {got}")]
+    TooMany { expected: usize, got: usize },
+    #[error("Invalid value '{value}' for argument '{name}': expected {kind}")]
+    Invalid {
+        name: &'static str,
+        kind: ArgKind,
+        value: String,
+    },
+    /// A command asked for an argument its spec doesn't declare.
+    #[error("No argument named '{0}'")]
+    Undeclared(String),
+    /// A command asked for an argument as a different kind than its spec
+    /// declares.
+    #[error("Argument '{name}' is not {kind}")]
+    WrongKind { name: String, kind: &'static str },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgKind {
+    String,
+    Int,
+    Float,
+    Bool,
+    Path,
+    Enum(&'static [&'static str]),
+}
+
+impl fmt::Display for ArgKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgKind::String => write!(f, "string"),
+            ArgKind::Int => write!(f, "int"),
+            ArgKind::Float => write!(f, "float"),
+            ArgKind::Bool => write!(f, "bool"),
+            ArgKind::Path => write!(f, "path"),
+            ArgKind::Enum(choices) => write!(f, "{}", choices.join("|")),
+        }
+    }
+}
+
+impl ArgKind {
+    /// Default completions for a value of this kind.
+    pub fn complete(&self, partial: &str) -> Vec<String> {
+        match self {
+            ArgKind::Bool => filter_prefix(["true", "false"], partial),
+            ArgKind::Enum(choices) => filter_prefix(choices.iter().copied(), partial),
+            ArgKind::Path => complete_path(partial, None),
+            ArgKind::String | ArgKind::Int | ArgKind::Float => Vec::new(),
+        }
+    }
+
+    fn parse(&self, name: &'static str, raw: &str) -> Result<Value, ArgError> {
+        let invalid = || ArgError::Invalid {
+            name,
+            kind: self.clone(),
+            value: raw.to_string(),
+        };
+        match self {
+            ArgKind::String => Ok(Value::String(raw.to_string())),
+            ArgKind::Int => raw.parse().map(Value::Int).map_err(|_| invalid()),
+            ArgKind::Float => raw.parse().map(Value::Float).map_err(|_| invalid()),
+            ArgKind::Bool => match raw.to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Ok(Value::Bool(true)),
+                "false" | "no" | "off" | "0" => Ok(Value::Bool(false)),
+                _ => Err(invalid()),
+            },
+            ArgKind::Path => Ok(Value::Path(PathBuf::from(raw))),
+            ArgKind::Enum(choices) => choices
+                .iter()
+                .find(|choice| choice.eq_ignore_ascii_case(raw))
+                .map(|choice| Value::String(choice.to_string()))
+                .ok_or_else(invalid),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Required,
+    Optional,
+    Variadic,
+}
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub arity: Arity,
+    pub description: &'static str,
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.arity {
+            Arity::Required => write!(f, "<{}:{}>", self.name, self.kind),
+            Arity::Optional => write!(f, "[{}:{}]", self.name, self.kind),
+            Arity::Variadic => write!(f, "[{}:{}...]", self.name, self.kind),
+        }
+    }
+}
+
+/// Declarative description of the arguments a command accepts.
+///
+/// Required parameters must come before optional ones, and a variadic
+/// parameter can only be the last one.
+#[derive(Debug, Clone, Default)]
+pub struct Params {
+    params: Vec<Param>,
+}
+
+impl Params {
+    pub fn none() -> Params {
+        Params::default()
+    }
+
+    pub fn required(self, name: &'static str, kind: ArgKind, description: &'static str) -> Self {
+        debug_assert!(
+            self.params.iter().all(|p| p.arity == Arity::Required),
+            "required parameter '{name}' follows an optional or variadic one"
+        );
+        self.push(name, kind, Arity::Required, description)
+    }
+
+    pub fn optional(self, name: &'static str, kind: ArgKind, description: &'static str) -> Self {
+        debug_assert!(
+            self.params.iter().all(|p| p.arity != Arity::Variadic),
+            "optional parameter '{name}' follows a variadic one"
+        );
+        self.push(name, kind, Arity::Optional, description)
+    }
+
+    pub fn variadic(self, name: &'static str, kind: ArgKind, description: &'static str) -> Self {
+        debug_assert!(
+            self.params.iter().all(|p| p.arity != Arity::Variadic),
+            "only one variadic parameter is allowed"
+        );
+        self.push(name, kind, Arity::Variadic, description)
+    }
+
+    fn push(
+        mut self,
+        name: &'static str,
+        kind: ArgKind,
+        arity: Arity,
+        description: &'static str,
+    ) -> Self {
+        self.params.push(Param {
+            name,
+            kind,
+            arity,
+            description,
+        });
+        self
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Param> {
+        self.params.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    /// Returns the parameter the argument at `index` binds to, if any.
+    pub fn get(&self, index: usize) -> Option<&Param> {
+        self.params
+            .get(index)
+            .or_else(|| self.params.last().filter(|p| p.arity == Arity::Variadic))
+    }
+
+    pub fn usage(&self, command: &str) -> String {
+        let mut usage = command.to_string();
+        for param in &self.params {
+            usage.push(' ');
+            usage.push_str(&param.to_string());
+        }
+        usage
+    }
+
+    pub fn validate(&self, args: Option<Vec<String>>) -> Result<Args, ArgError> {
+        let raw = args.unwrap_or_default();
+        let mut values = HashMap::new();
+        let mut variadic = Vec::new();
+        let mut remaining = raw.iter();
+
+        for param in &self.params {
+            match param.arity {
+                Arity::Required => {
+                    let arg = remaining.next().ok_or(ArgError::Missing(param.name))?;
+                    values.insert(param.name, param.kind.parse(param.name, arg)?);
+                }
+                Arity::Optional => {
+                    if let Some(arg) = remaining.next() {
+                        values.insert(param.name, param.kind.parse(param.name, arg)?);
+                    }
+                }
+                Arity::Variadic => {
+                    for arg in remaining.by_ref() {
+                        variadic.push(param.kind.parse(param.name, arg)?);
+                    }
+                }
+            }
+        }
+
+        if remaining.next().is_some() {
+            return Err(ArgError::TooMany {
+                expected: self.params.len(),
+                got: raw.len(),
+            });
+        }
+
+        Ok(Args {
+            values,
+            variadic,
+            raw,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Path(PathBuf),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{s}"),
+            Value::Int(i) => write!(f, "{i}"),
+            Value::Float(x) => write!(f, "{x}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Path(p) => write!(f, "{}", p.display()),
+        }
+    }
+}
+
+/// Arguments that have been checked against a command's [`Params`].
+#[derive(Debug, Clone, Default)]
+pub struct Args {
+    values: HashMap<&'static str, Value>,
+    variadic: Vec<Value>,
+    raw: Vec<String>,
+}
+
+impl Args {
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    pub fn string(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn int(&self, name: &str) -> Option<i64> {
+        match self.get(name)? {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn float(&self, name: &str) -> Option<f64> {
+        match self.get(name)? {
+            Value::Float(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn bool(&self, name: &str) -> Option<bool> {
+        match self.get(name)? {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn path(&self, name: &str) -> Option<&Path> {
+        match self.get(name)? {
+            Value::Path(p) => Some(p),
+            _ => None,
+        }
+    }
+
+    /// Like [`Args::string`], for parameters the spec requires. A mismatch
+    /// between the spec and the command is an error rather than a panic.
+    pub fn required_string(&self, name: &str) -> Result<&str, ArgError> {
+        self.required(name, "a string", |value| match value {
+            Value::String(s) => Some(s.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn required_int(&self, name: &str) -> Result<i64, ArgError> {
+        self.required(name, "an int", |value| match value {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        })
+    }
+
+    pub fn required_path(&self, name: &str) -> Result<&Path, ArgError> {
+        self.required(name, "a path", |value| match value {
+            Value::Path(p) => Some(p.as_path()),
+            _ => None,
+        })
+    }
+
+    fn required<'a, T>(
+        &'a self,
+        name: &str,
+        kind: &'static str,
+        extract: impl FnOnce(&'a Value) -> Option<T>,
+    ) -> Result<T, ArgError> {
+        let value = self
+            .get(name)
+            .ok_or_else(|| ArgError::Undeclared(name.to_string()))?;
+        extract(value).ok_or_else(|| ArgError::WrongKind {
+            name: name.to_string(),
+            kind,
+        })
+    }
+
+    pub fn variadic(&self) -> &[Value] {
+        &self.variadic
+    }
+
+    pub fn raw(&self) -> &[String] {
+        &self.raw
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.raw.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(args: &[&str]) -> Option<Vec<String>> {
+        Some(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    fn params() -> Params {
+        Params::none()
+            .required("count", ArgKind::Int, "")
+            .optional("mode", ArgKind::Enum(&["fast", "slow"]), "")
+            .variadic("rest", ArgKind::Float, "")
+    }
+
+    #[test]
+    fn builder_keeps_declaration_order() {
+        let params = params();
+        let names: Vec<_> = params.iter().map(|param| param.name).collect();
+        assert_eq!(names, ["count", "mode", "rest"]);
+        assert_eq!(
+            params.usage("run"),
+            "run <count:int> [mode:fast|slow] [rest:float...]"
+        );
+        // Arguments past the last parameter bind to the variadic one
+        assert_eq!(params.get(5).unwrap().name, "rest");
+        assert!(
+            Params::none()
+                .optional("a", ArgKind::Int, "")
+                .get(1)
+                .is_none()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "follows an optional or variadic one")]
+    fn required_after_optional_is_rejected() {
+        let _ = Params::none()
+            .optional("a", ArgKind::Int, "")
+            .required("b", ArgKind::Int, "");
+    }
+
+    #[test]
+    #[should_panic(expected = "only one variadic parameter")]
+    fn second_variadic_is_rejected() {
+        let _ = Params::none()
+            .variadic("a", ArgKind::Int, "")
+            .variadic("b", ArgKind::Int, "");
+    }
+
+    #[test]
+    fn validate_binds_each_arity() {
+        let args = params().validate(raw(&["3", "SLOW", "1.5", "2"])).unwrap();
+        assert_eq!(args.int("count"), Some(3));
+        // Enum values are matched case-insensitively and normalised
+        assert_eq!(args.string("mode"), Some("slow"));
+        assert_eq!(args.variadic(), [Value::Float(1.5), Value::Float(2.0)]);
+        assert_eq!(args.raw().len(), 4);
+
+        let args = params().validate(raw(&["3"])).unwrap();
+        assert_eq!(args.string("mode"), None);
+        assert!(args.variadic().is_empty());
+    }
+
+    #[test]
+    fn validate_checks_arity() {
+        let error = params().validate(None).unwrap_err();
+        assert_eq!(error.to_string(), "Missing required argument 'count'");
+
+        let params = Params::none().optional("a", ArgKind::String, "");
+        let error = params.validate(raw(&["x", "y", "z"])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Too many arguments: expected at most 1, got 3"
+        );
+        assert!(Params::none().validate(raw(&[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn validate_coerces_kinds() {
+        let params = Params::none()
+            .required("int", ArgKind::Int, "")
+            .required("float", ArgKind::Float, "")
+            .required("bool", ArgKind::Bool, "")
+            .required("path", ArgKind::Path, "");
+        let args = params.validate(raw(&["-4", "0.25", "on", "a/b"])).unwrap();
+        assert_eq!(args.int("int"), Some(-4));
+        assert_eq!(args.float("float"), Some(0.25));
+        assert_eq!(args.bool("bool"), Some(true));
+        assert_eq!(args.path("path"), Some(Path::new("a/b")));
+
+        for (value, expected) in [("yes", true), ("1", true), ("OFF", false), ("no", false)] {
+            let args = Params::none()
+                .required("b", ArgKind::Bool, "")
+                .validate(raw(&[value]))
+                .unwrap();
+            assert_eq!(args.bool("b"), Some(expected), "{value}");
+        }
+    }
+
+    #[test]
+    fn invalid_values_name_the_expected_kind() {
+        let cases = [
+            (ArgKind::Int, "1.5", "expected int"),
+            (ArgKind::Float, "abc", "expected float"),
+            (ArgKind::Bool, "maybe", "expected bool"),
+            (ArgKind::Enum(&["a", "b"]), "c", "expected a|b"),
+        ];
+        for (kind, value, expected) in cases {
+            let error = Params::none()
+                .required("x", kind, "")
+                .validate(raw(&[value]))
+                .unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("Invalid value '{value}' for argument 'x': {expected}")
+            );
+        }
+    }
+
+    #[test]
+    fn required_accessors_report_spec_mismatches() {
+        let args = Params::none()
+            .required("name", ArgKind::String, "")
+            .validate(raw(&["zenyx"]))
+            .unwrap();
+        assert_eq!(args.required_string("name").unwrap(), "zenyx");
+        assert_eq!(
+            args.required_int("name").unwrap_err().to_string(),
+            "Argument 'name' is not an int"
+        );
+        assert_eq!(
+            args.required_path("file").unwrap_err().to_string(),
+            "No argument named 'file'"
+        );
+    }
+}
@@ -1,44 +1,60 @@
-use std::{fs, path::PathBuf, str::FromStr};
-
-use anyhow::anyhow;
+use std::{fmt::Write, fs};
 
+use anyhow::{Context, anyhow};
 use parking_lot::RwLock;
 use regex::Regex;
 
-use super::{handler::Command, input::tokenize};
-use crate::core::repl::handler::COMMAND_MANAGER;
+use super::{
+    aliases::{parse_definition, save_aliases},
+    args::{ArgKind, Args, Params, Value},
+    completion::{complete_path, filter_prefix},
+    handler::{Command, CommandState, Exit, HistoryEntry, Output},
+    history::HISTORY,
+    zensh::{Interpreter, interpreter::parse_alias, lexer::is_valid_name},
+};
+use crate::core::{
+    render::{
+        bench,
+        bus::{self, RenderReply, RenderRequest},
+        camera::{Projection, controller::ControllerKind},
+        capture,
+    },
+    repl::handler::COMMAND_MANAGER,
+};
 
 #[derive(Default)]
 pub struct HelpCommand;
 
 impl Command for HelpCommand {
-    fn execute(&self, _args: Option<Vec<String>>) -> Result<(), anyhow::Error> {
-        let manager = COMMAND_MANAGER.read();
-        println!("Available commands:\n");
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        // Commands run while the shell holds a read lock
+        let manager = COMMAND_MANAGER.read_recursive();
+        let mut help = String::new();
+
+        if let Some(name) = args.string("command") {
+            let command = manager
+                .commands
+                .get(&name.to_lowercase())
+                .ok_or(anyhow!("Command '{}' not found.", name))?;
+            write_command_help(&mut help, command.as_ref())?;
+            return Ok(Output::Text(help));
+        }
 
+        writeln!(help, "Available commands:\n")?;
         for (_, command) in manager.get_commands() {
-            println!(
-                "Command: {}\n\tDescription: {}\n\tParameters: {}\n\tHelp: {}\n",
-                command.get_name().to_lowercase(),
-                command.get_description(),
-                command.get_params(),
-                command.get_help()
-            );
+            write_command_help(&mut help, command.as_ref())?;
         }
 
-        if !manager.aliases.is_empty() {
-            println!("Aliases:");
-            for (alias, command) in &manager.aliases {
-                println!("\t{} -> {}", alias, command);
+        let aliases = manager.aliases.read();
+        if !aliases.is_empty() {
+            writeln!(help, "Aliases:")?;
+            for (alias, expansion) in aliases.iter() {
+                writeln!(help, "\t{} -> {}", alias, expansion)?;
             }
         }
-        Ok(())
+        Ok(Output::Text(help))
     }
 
-    fn undo(&self) {}
-
-    fn redo(&self) {}
-
     fn get_description(&self) -> String {
         String::from("help")
     }
@@ -47,34 +63,61 @@ impl Command for HelpCommand {
         String::from("Displays a list of available commands and their descriptions.")
     }
 
-    fn get_params(&self) -> String {
-        String::from("No parameters required.")
+    fn get_params(&self) -> Params {
+        Params::none().optional(
+            "command",
+            ArgKind::String,
+            "Only show help for this command",
+        )
+    }
+
+    fn complete(&self, index: usize, partial: &str) -> Vec<String> {
+        if index > 0 {
+            return Vec::new();
+        }
+        // The completer is already holding a read lock
+        let manager = COMMAND_MANAGER.read_recursive();
+        filter_prefix(
+            manager.get_commands().map(|(name, _)| name.as_str()),
+            partial,
+        )
     }
 
     fn get_name(&self) -> String {
         String::from("Help")
     }
 }
+
+fn write_command_help(help: &mut String, command: &dyn Command) -> std::fmt::Result {
+    let name = command.get_name().to_lowercase();
+    let params = command.get_params();
+    writeln!(
+        help,
+        "Command: {}\n\tDescription: {}\n\tUsage: {}",
+        name,
+        command.get_description(),
+        params.usage(&name)
+    )?;
+    for param in params.iter() {
+        writeln!(help, "\t\t{}: {}", param, param.description)?;
+    }
+    writeln!(help, "\tHelp: {}\n", command.get_help())
+}
 #[derive(Default)]
 pub struct ClearCommand;
 
 impl Command for ClearCommand {
-    fn execute(&self, _args: Option<Vec<String>>) -> Result<(), anyhow::Error> {
-        println!("Clearing screen..., running command");
+    fn execute(&self, _args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
         let _result = if cfg!(target_os = "windows") {
             std::process::Command::new("cmd")
                 .args(["/c", "cls"])
-                .spawn()
+                .status()
         } else {
-            std::process::Command::new("clear").spawn()
+            std::process::Command::new("clear").status()
         };
-        Ok(())
+        Ok(Output::None)
     }
 
-    fn undo(&self) {}
-
-    fn redo(&self) {}
-
     fn get_description(&self) -> String {
         String::from("A simple command that clears the terminal")
     }
@@ -87,8 +130,8 @@ impl Command for ClearCommand {
         String::from("Clears the terminal")
     }
 
-    fn get_params(&self) -> String {
-        String::from("None")
+    fn get_params(&self) -> Params {
+        Params::none()
     }
 }
 
@@ -96,80 +139,345 @@ impl Command for ClearCommand {
 pub struct ExitCommand;
 
 impl Command for ExitCommand {
-    fn execute(&self, args: Option<Vec<String>>) -> Result<(), anyhow::Error> {
-        match args {
-            Some(args) => {
-                let exit_code = args[0].parse()?;
-                std::process::exit(exit_code);
-                // Ok(())
-            }
-            None => {
-                std::process::exit(0);
-            }
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        let exit_code = args.int("code").unwrap_or(0);
+        let exit_code = u8::try_from(exit_code)
+            .map_err(|_| anyhow!("Exit code {} is out of range", exit_code))?;
+        Err(Exit(exit_code).into())
+    }
+
+    fn get_description(&self) -> String {
+        String::from("Gracefully exists the program")
+    }
+
+    fn get_name(&self) -> String {
+        String::from("exit")
+    }
+
+    fn get_help(&self) -> String {
+        String::from("Exits, probably")
+    }
+
+    fn get_params(&self) -> Params {
+        Params::none().optional(
+            "code",
+            ArgKind::Int,
+            "Process exit code from 0 to 255, defaults to 0",
+        )
+    }
+}
+#[derive(Default)]
+pub struct ScreenshotCommand;
+
+impl Command for ScreenshotCommand {
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        let path = args.required_path("path")?;
+        capture::screenshot(path)
+            .with_context(|| format!("Failed to take a screenshot to {}", path.display()))?;
+        Ok(Output::Text(format!(
+            "Saved screenshot to {}",
+            path.display()
+        )))
+    }
+
+    fn get_description(&self) -> String {
+        String::from("Saves the next frame as a PNG")
+    }
+
+    fn get_name(&self) -> String {
+        String::from("screenshot")
+    }
+
+    fn get_help(&self) -> String {
+        String::from(
+            "Captures the next frame of the window or offscreen renderer. If nothing is rendering, a frame is rendered offscreen for it.",
+        )
+    }
+
+    fn get_params(&self) -> Params {
+        Params::none().required("path", ArgKind::Path, "Where to save the PNG")
+    }
+
+    fn complete(&self, index: usize, partial: &str) -> Vec<String> {
+        match index {
+            0 => complete_path(partial, Some("png")),
+            _ => Vec::new(),
         }
     }
+}
+
+/// Parses `rrggbb` or `rrggbbaa` hex, optionally starting with `#`, into
+/// linear RGBA. zensh treats an unquoted `#` as a comment.
+fn parse_color(text: &str) -> anyhow::Result<[f32; 4]> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return Err(anyhow!(
+            "'{text}' isn't a colour, expected rrggbb or rrggbbaa"
+        ));
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .map(|value| f32::from(value) / 255.0)
+            .map_err(|_| anyhow!("'{text}' isn't a colour, expected rrggbb or rrggbbaa"))
+    };
+    let srgb_to_linear = |c: f32| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let alpha = if hex.len() == 8 { channel(6)? } else { 1.0 };
+    Ok([
+        srgb_to_linear(channel(0)?),
+        srgb_to_linear(channel(2)?),
+        srgb_to_linear(channel(4)?),
+        alpha,
+    ])
+}
 
-    fn undo(&self) {
-        todo!()
+#[derive(Default)]
+pub struct ClearColorCommand;
+
+impl Command for ClearColorCommand {
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        let text = args.required_string("color")?;
+        let [r, g, b, a] = parse_color(text)?;
+        bus::request(RenderRequest::SetClearColor(wgpu::Color {
+            r: r.into(),
+            g: g.into(),
+            b: b.into(),
+            a: a.into(),
+        }))?;
+        Ok(Output::None)
+    }
+
+    fn get_description(&self) -> String {
+        String::from("Sets the background colour")
+    }
+
+    fn get_name(&self) -> String {
+        String::from("clearcolor")
+    }
+
+    fn get_help(&self) -> String {
+        String::from("Sets the colour frames are cleared to, as rrggbb or rrggbbaa hex.")
+    }
+
+    fn get_params(&self) -> Params {
+        Params::none().required("color", ArgKind::String, "The colour, e.g. 1a1a1a")
     }
+}
 
-    fn redo(&self) {
-        todo!()
+#[derive(Default)]
+pub struct ResizeCommand;
+
+impl Command for ResizeCommand {
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        let size = |name| {
+            let value = args.required_int(name)?;
+            u32::try_from(value)
+                .ok()
+                .filter(|&value| value > 0)
+                .ok_or_else(|| anyhow!("{name} must be a positive number of pixels, got {value}"))
+        };
+        bus::request(RenderRequest::Resize {
+            width: size("width")?,
+            height: size("height")?,
+        })?;
+        Ok(Output::None)
     }
 
     fn get_description(&self) -> String {
-        String::from("Gracefully exists the program")
+        String::from("Resizes the window")
     }
 
     fn get_name(&self) -> String {
-        String::from("exit")
+        String::from("resize")
     }
 
     fn get_help(&self) -> String {
-        String::from("Exits, probably")
+        String::from(
+            "Asks for the window to be resized, in physical pixels. Headless runs resize their offscreen target instead.",
+        )
     }
 
-    fn get_params(&self) -> String {
-        String::from("None")
+    fn get_params(&self) -> Params {
+        Params::none()
+            .required("width", ArgKind::Int, "Width in pixels")
+            .required("height", ArgKind::Int, "Height in pixels")
     }
 }
+
 #[derive(Default)]
-pub struct ExecFile;
+pub struct SpawnCommand;
+
+impl Command for SpawnCommand {
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        let mesh = args.required_string("mesh")?;
+        let color = args.string("color").map(parse_color).transpose()?;
+        let reply = bus::request(RenderRequest::Spawn {
+            mesh: mesh.to_string(),
+            color,
+        })?;
+        match reply {
+            RenderReply::Spawned(index) => Ok(Output::Text(format!("Spawned object {index}"))),
+            reply => Err(anyhow!("Unexpected reply from the renderer: {reply:?}")),
+        }
+    }
 
-impl Command for ExecFile {
-    fn execute(&self, args: Option<Vec<String>>) -> Result<(), anyhow::Error> {
-        match args {
-            Some(args) => {
-                let file_path = PathBuf::from_str(&args[0])?;
-                if file_path.extension().is_some() && file_path.extension().unwrap() != "zensh" {
-                    return Err(anyhow!("Selected file was not a zensh file"));
-                } else {
-                    let zscript = fs::read_to_string(file_path)?;
-                    if let Ok(command) = eval(zscript) {
-                        println!("{:#?}", command);
-                        for (cmd_name, cmd_args) in command {
-                            match COMMAND_MANAGER.read().execute(&cmd_name, cmd_args) {
-                                Ok(_) => (),
-                                Err(e) => {
-                                    println!(
-                                        "Error executing command returned an error: {}. Aborting script",
-                                        e
-                                    );
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
-                Ok(())
-            }
-            None => Err(anyhow!("Not enough argumentss")),
+    fn get_description(&self) -> String {
+        String::from("Adds a mesh to the scene")
+    }
+
+    fn get_name(&self) -> String {
+        String::from("spawn")
+    }
+
+    fn get_help(&self) -> String {
+        String::from(
+            "Draws a registered mesh, such as cube, sphere, plane or cylinder, with the default material or a plain colour.",
+        )
+    }
+
+    fn get_params(&self) -> Params {
+        Params::none()
+            .required("mesh", ArgKind::String, "Name of the mesh")
+            .optional("color", ArgKind::String, "Colour as rrggbb or rrggbbaa hex")
+    }
+
+    fn complete(&self, index: usize, partial: &str) -> Vec<String> {
+        match index {
+            0 => filter_prefix(["cube", "sphere", "plane", "cylinder"], partial),
+            _ => Vec::new(),
         }
     }
+}
 
-    fn undo(&self) {}
+/// How many units an orthographic camera shows vertically when switched to,
+/// about what the default perspective camera shows at the origin.
+const ORTHOGRAPHIC_HEIGHT: f32 = 2.5;
 
-    fn redo(&self) {}
+#[derive(Default)]
+pub struct CameraCommand;
+
+impl Command for CameraCommand {
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        let controller = match args.required_string("controller")? {
+            "none" => None,
+            name => Some(
+                ControllerKind::from_name(name)
+                    .ok_or_else(|| anyhow!("Unknown camera controller '{}'", name))?,
+            ),
+        };
+        let projection = args
+            .string("projection")
+            .map(|projection| match projection {
+                "orthographic" => Projection::orthographic(ORTHOGRAPHIC_HEIGHT),
+                _ => Projection::default(),
+            });
+        bus::request(RenderRequest::Camera {
+            controller: Some(controller),
+            projection,
+        })?;
+        Ok(Output::None)
+    }
+
+    fn get_description(&self) -> String {
+        String::from("Changes how the camera moves")
+    }
+
+    fn get_name(&self) -> String {
+        String::from("camera")
+    }
+
+    fn get_help(&self) -> String {
+        String::from(
+            "Picks the main camera's controller. fly looks around with the right mouse button and moves with WASD, orbit circles the origin with the left button and zooms with the wheel, and pan drags with the left button and zooms with the wheel. Optionally switches between perspective and orthographic projection.",
+        )
+    }
+
+    fn get_params(&self) -> Params {
+        Params::none()
+            .required(
+                "controller",
+                ArgKind::Enum(&["fly", "orbit", "pan", "none"]),
+                "How input moves the camera",
+            )
+            .optional(
+                "projection",
+                ArgKind::Enum(&["perspective", "orthographic"]),
+                "How the camera projects the scene",
+            )
+    }
+}
+
+#[derive(Default)]
+pub struct BenchmarkCommand;
+
+impl Command for BenchmarkCommand {
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        let count = |name, default: usize| {
+            let Some(value) = args.int(name) else {
+                return Ok(default);
+            };
+            usize::try_from(value)
+                .ok()
+                .filter(|&value| value > 0)
+                .ok_or_else(|| anyhow!("{name} must be a positive number, got {value}"))
+        };
+        let cubes = count("cubes", bench::DEFAULT_CUBES)?;
+        let frames = count("frames", bench::DEFAULT_FRAMES as usize)?;
+        let reply = bus::request(RenderRequest::Benchmark {
+            cubes,
+            frames: u32::try_from(frames)?,
+        })?;
+        match reply {
+            RenderReply::Benchmark(report) => Ok(Output::Text(report.to_string())),
+            reply => Err(anyhow!("Unexpected reply from the renderer: {reply:?}")),
+        }
+    }
+
+    fn get_description(&self) -> String {
+        String::from("Times drawing a grid of cubes")
+    }
+
+    fn get_name(&self) -> String {
+        String::from("benchmark")
+    }
+
+    fn get_help(&self) -> String {
+        String::from(
+            "Replaces the scene with a grid of cubes, 10000 by default, and reports how long drawing 100 frames of it takes.",
+        )
+    }
+
+    fn get_params(&self) -> Params {
+        Params::none()
+            .optional("cubes", ArgKind::Int, "How many cubes to draw")
+            .optional("frames", ArgKind::Int, "How many frames to time")
+    }
+}
+
+#[derive(Default)]
+pub struct ExecFile;
+
+impl Command for ExecFile {
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        let file_path = args.required_path("file")?;
+        if file_path
+            .extension()
+            .is_some_and(|extension| extension != "zensh")
+        {
+            return Err(anyhow!("Selected file was not a zensh file"));
+        }
+        let zscript = fs::read_to_string(file_path)?;
+        let script_args = args.variadic().iter().map(Value::to_string).collect();
+        let mut script = Interpreter::for_script(&file_path.display().to_string(), script_args);
+        // Printed as the script runs rather than held back until it's done
+        script.run(&zscript)?;
+        Ok(Output::None)
+    }
 
     fn get_description(&self) -> String {
         String::from("Executes a file path")
@@ -180,11 +488,96 @@ impl Command for ExecFile {
     }
 
     fn get_help(&self) -> String {
-        String::from("this will read the contents of a .zensh file, evaluate it, and run its input")
+        String::from(
+            "this will read the contents of a .zensh file, evaluate it, and run its input. The script's output is printed as it runs",
+        )
+    }
+
+    fn get_params(&self) -> Params {
+        Params::none()
+            .required("file", ArgKind::Path, "Path to a .zensh script")
+            .variadic(
+                "args",
+                ArgKind::String,
+                "Arguments passed to the script as $1, $2, ...",
+            )
     }
 
-    fn get_params(&self) -> String {
-        String::from("1: File path")
+    fn complete(&self, index: usize, partial: &str) -> Vec<String> {
+        match index {
+            0 => complete_path(partial, Some("zensh")),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct EchoCommand;
+
+impl Command for EchoCommand {
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        let words: Vec<String> = args.variadic().iter().map(Value::to_string).collect();
+        Ok(Output::Text(words.join(" ")))
+    }
+
+    fn get_description(&self) -> String {
+        String::from("Prints its arguments")
+    }
+
+    fn get_name(&self) -> String {
+        String::from("echo")
+    }
+
+    fn get_help(&self) -> String {
+        String::from(
+            "Prints its arguments separated by spaces, useful for showing variables in scripts",
+        )
+    }
+
+    fn get_params(&self) -> Params {
+        Params::none().variadic("words", ArgKind::String, "Words to print")
+    }
+}
+
+#[derive(Default)]
+pub struct GrepCommand;
+
+impl Command for GrepCommand {
+    fn execute(&self, args: &Args, input: Output) -> Result<Output, anyhow::Error> {
+        let pattern = args.required_string("pattern")?;
+        let pattern = Regex::new(pattern)?;
+        let invert = args.string("mode") == Some("invert");
+        let matches = input
+            .lines()
+            .into_iter()
+            .filter(|line| pattern.is_match(line) != invert)
+            .map(String::from)
+            .collect();
+        Ok(Output::Lines(matches))
+    }
+
+    fn get_description(&self) -> String {
+        String::from("Filters piped input by a regular expression")
+    }
+
+    fn get_name(&self) -> String {
+        String::from("grep")
+    }
+
+    fn get_help(&self) -> String {
+        String::from(
+            "Keeps the lines of its input that match the pattern, e.g. `help | grep Usage`",
+        )
+    }
+
+    fn get_params(&self) -> Params {
+        Params::none()
+            .required("pattern", ArgKind::String, "Regular expression to match")
+            .optional(
+                "mode",
+                ArgKind::Enum(&["match", "invert"]),
+                "Use 'invert' to keep the lines that don't match",
+            )
     }
 }
 
@@ -194,20 +587,35 @@ pub struct CounterCommand {
 }
 
 impl Command for CounterCommand {
-    fn execute(&self, _args: Option<Vec<String>>) -> Result<(), anyhow::Error> {
+    fn execute(&self, _args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
         // Increment the counter
         let mut count = self.counter.write();
         *count += 1;
-        println!("CounterCommand executed. Current count: {}", *count);
-        Ok(())
+        Ok(Output::Text(format!(
+            "CounterCommand executed. Current count: {}",
+            *count
+        )))
+    }
+
+    fn is_undoable(&self) -> bool {
+        true
     }
 
-    fn undo(&self) {
-        println!("Undo CounterCommand.");
+    fn capture(&self) -> Option<CommandState> {
+        Some(Box::new(*self.counter.read()))
     }
 
-    fn redo(&self) {
-        println!("Redo CounterCommand.");
+    fn undo(&self, entry: &HistoryEntry) -> Result<Output, anyhow::Error> {
+        let previous = entry
+            .state
+            .as_ref()
+            .and_then(|state| state.downcast_ref::<u32>())
+            .ok_or(anyhow!("CounterCommand history entry is missing its state"))?;
+        *self.counter.write() = *previous;
+        Ok(Output::Text(format!(
+            "Undo CounterCommand. Current count: {}",
+            previous
+        )))
     }
 
     fn get_description(&self) -> String {
@@ -218,20 +626,108 @@ impl Command for CounterCommand {
         String::from("Increments a counter every time it's executed.")
     }
 
-    fn get_params(&self) -> String {
-        String::from("No parameters for CounterCommand.")
+    fn get_params(&self) -> Params {
+        Params::none()
     }
 
     fn get_name(&self) -> String {
         String::from("count")
     }
 }
+#[derive(Default)]
+pub struct UndoCommand;
+
+impl Command for UndoCommand {
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        let steps = parse_steps(args, "undo")?;
+        // Commands run while the shell holds a read lock
+        let manager = COMMAND_MANAGER.read_recursive();
+        let available = manager.history.lock().undo_len();
+        if steps > available {
+            return Err(anyhow!("Only {} steps to undo", available));
+        }
+        let mut report = Vec::new();
+        for _ in 0..steps {
+            let (name, output) = manager.undo()?;
+            report.extend(output.lines().into_iter().map(String::from));
+            report.push(format!("Undid '{}'", name));
+        }
+        Ok(Output::Lines(report))
+    }
+
+    fn get_description(&self) -> String {
+        String::from("Undoes the most recent undoable command")
+    }
+
+    fn get_name(&self) -> String {
+        String::from("undo")
+    }
+
+    fn get_help(&self) -> String {
+        String::from("Walks back through the command history, undoing one command per step")
+    }
+
+    fn get_params(&self) -> Params {
+        Params::none().optional("steps", ArgKind::Int, "Number of steps, defaults to 1")
+    }
+}
+
+#[derive(Default)]
+pub struct RedoCommand;
+
+impl Command for RedoCommand {
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        let steps = parse_steps(args, "redo")?;
+        // Commands run while the shell holds a read lock
+        let manager = COMMAND_MANAGER.read_recursive();
+        let available = manager.history.lock().redo_len();
+        if steps > available {
+            return Err(anyhow!("Only {} steps to redo", available));
+        }
+        let mut report = Vec::new();
+        for _ in 0..steps {
+            let (name, output) = manager.redo()?;
+            report.extend(output.lines().into_iter().map(String::from));
+            report.push(format!("Redid '{}'", name));
+        }
+        Ok(Output::Lines(report))
+    }
+
+    fn get_description(&self) -> String {
+        String::from("Re-applies the most recently undone command")
+    }
+
+    fn get_name(&self) -> String {
+        String::from("redo")
+    }
+
+    fn get_help(&self) -> String {
+        String::from("Walks forward through the command history, redoing one command per step")
+    }
+
+    fn get_params(&self) -> Params {
+        Params::none().optional("steps", ArgKind::Int, "Number of steps, defaults to 1")
+    }
+}
+
+/// `verb` is what's being repeated, for the error message.
+fn parse_steps(args: &Args, verb: &str) -> Result<usize, anyhow::Error> {
+    let steps = args.int("steps").unwrap_or(1);
+    usize::try_from(steps).map_err(|_| anyhow!("Cannot {} {} times", verb, steps))
+}
+
 #[derive(Default)]
 pub struct PanicCommmand;
 impl Command for PanicCommmand {
-    fn execute(&self, args: Option<Vec<String>>) -> Result<(), anyhow::Error> {
-        if args.is_some() {
-            let panic_msg = &args.unwrap()[0];
+    #[allow(clippy::unnecessary_literal_unwrap)]
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        if !args.variadic().is_empty() {
+            let panic_msg = args
+                .variadic()
+                .iter()
+                .map(Value::to_string)
+                .collect::<Vec<_>>()
+                .join(" ");
             panic!("{}", panic_msg)
         }
         let option: Option<i32> = None;
@@ -239,10 +735,6 @@ impl Command for PanicCommmand {
         panic!("Panic command was called")
     }
 
-    fn undo(&self) {}
-
-    fn redo(&self) {}
-
     fn get_description(&self) -> String {
         String::from("causes a panic with your provided message")
     }
@@ -255,39 +747,241 @@ impl Command for PanicCommmand {
         String::from("")
     }
 
-    fn get_params(&self) -> String {
-        String::from("optional: panic msg")
+    fn get_params(&self) -> Params {
+        Params::none().variadic("message", ArgKind::String, "Message to panic with")
     }
 }
 
-fn eval(input: String) -> Result<Vec<(String, Option<Vec<String>>)>, anyhow::Error> {
-    if input.trim().is_empty() {
-        return Err(anyhow!("Input was empty"));
+#[derive(Default)]
+pub struct HistoryCommand;
+
+fn format_history_entry((number, entry): (usize, &str)) -> String {
+    format!("{:>5}  {}", number, entry)
+}
+
+impl HistoryCommand {
+    const ACTIONS: &'static [&'static str] = &["list", "search", "clear", "size", "dedup"];
+
+    /// The arguments each action takes after its name.
+    fn action_params(action: &str) -> Params {
+        match action {
+            "list" => {
+                Params::none().optional("count", ArgKind::Int, "How many recent entries to list")
+            }
+            "search" => Params::none().required("text", ArgKind::String, "Text to search for"),
+            "size" => Params::none().optional("limit", ArgKind::Int, "New size limit"),
+            "dedup" => Params::none().optional("enabled", ArgKind::Bool, "on or off"),
+            _ => Params::none(),
+        }
     }
+}
 
-    let pattern = Regex::new(r"[;|\n]").unwrap();
-    let commands: Vec<&str> = pattern.split(&input).collect();
-    let mut evaluted = vec![];
+impl Command for HistoryCommand {
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        let action = args.string("action").unwrap_or("list");
+        let params = Self::action_params(action);
+        let rest = args.raw().get(1..).map(<[String]>::to_vec);
+        let args = params.validate(rest).map_err(|e| {
+            anyhow!(
+                "{}\nUsage: {}",
+                e,
+                params.usage(&format!("history {}", action))
+            )
+        })?;
+        let count = |name| {
+            args.int(name)
+                .map(|value| {
+                    usize::try_from(value)
+                        .map_err(|_| anyhow!("{} must not be negative, got {}", name, value))
+                })
+                .transpose()
+        };
 
-    for command in commands {
-        let command = command.trim();
-        if command.is_empty() {
-            println!("Empty command, skipping.");
-            continue;
+        let mut history = HISTORY.lock();
+        match action {
+            "list" => {
+                let count = count("count")?.unwrap_or(history.len());
+                let skip = history.len().saturating_sub(count);
+                Ok(Output::Lines(
+                    history
+                        .entries()
+                        .skip(skip)
+                        .map(format_history_entry)
+                        .collect(),
+                ))
+            }
+            "search" => {
+                let text = args.required_string("text")?;
+                Ok(Output::Lines(
+                    history.search(text).map(format_history_entry).collect(),
+                ))
+            }
+            "clear" => {
+                history.clear();
+                Ok(Output::None)
+            }
+            "size" => match count("limit")? {
+                Some(limit) => {
+                    history.set_limit(limit);
+                    Ok(Output::None)
+                }
+                None => Ok(Output::Text(format!(
+                    "History size limit: {}",
+                    history.limit()
+                ))),
+            },
+            "dedup" => match args.bool("enabled") {
+                Some(enabled) => {
+                    history.set_dedup(enabled);
+                    Ok(Output::None)
+                }
+                None => Ok(Output::Text(format!(
+                    "History deduplication: {}",
+                    if history.dedup() { "on" } else { "off" }
+                ))),
+            },
+            other => Err(anyhow!("Unknown history action '{}'", other)),
         }
+    }
 
-        let tokens = tokenize(command);
-        if tokens.is_empty() {
-            println!("Empty command, skipping.");
-            continue;
+    fn get_description(&self) -> String {
+        String::from("Lists, searches and configures the REPL history")
+    }
+
+    fn get_name(&self) -> String {
+        String::from("history")
+    }
+
+    fn get_help(&self) -> String {
+        String::from(
+            "history [list [count]] | search <text> | clear | size [limit] | dedup [on|off]. \
+             Entries can be re-run with !n, !-n, !! or !prefix",
+        )
+    }
+
+    fn get_params(&self) -> Params {
+        Params::none()
+            .optional(
+                "action",
+                ArgKind::Enum(Self::ACTIONS),
+                "What to do, defaults to list",
+            )
+            .variadic(
+                "args",
+                ArgKind::String,
+                "Arguments for the action, checked against its own parameters",
+            )
+    }
+
+    fn complete(&self, index: usize, partial: &str) -> Vec<String> {
+        match index {
+            0 => filter_prefix(Self::ACTIONS.iter().copied(), partial),
+            1 => filter_prefix(["on", "off"], partial),
+            _ => Vec::new(),
         }
-        let cmd_name = &tokens[0];
-        let args: Option<Vec<String>> = if tokens.len() > 1 {
-            Some(tokens[1..].iter().map(|s| s.to_string()).collect())
-        } else {
-            None
-        };
-        evaluted.push((cmd_name.to_owned(), args));
     }
-    Ok(evaluted)
+}
+
+#[derive(Default)]
+pub struct AliasCommand;
+
+impl Command for AliasCommand {
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        // Nested inside the read lock the shell holds while running commands
+        let manager = COMMAND_MANAGER.read_recursive();
+        if args.is_empty() {
+            let aliases = manager.aliases.read();
+            let mut lines: Vec<String> = aliases
+                .iter()
+                .map(|(name, expansion)| format!("{}={}", name, expansion))
+                .collect();
+            lines.sort();
+            return Ok(Output::Lines(lines));
+        }
+
+        let words: Vec<String> = args.variadic().iter().map(Value::to_string).collect();
+        let definition = words.join(" ");
+        if !definition.contains('=') {
+            let expansion = manager
+                .get_alias(&definition)
+                .ok_or(anyhow!("No alias named '{}'", definition))?;
+            return Ok(Output::Text(format!("{}={}", definition, expansion)));
+        }
+
+        let (name, expansion) = parse_definition(&definition)?;
+        if !is_valid_name(name) {
+            return Err(anyhow!("Invalid alias name '{}'", name));
+        }
+        parse_alias(expansion).with_context(|| format!("Invalid expansion for '{}'", name))?;
+        manager.add_alias(name, expansion);
+        save_aliases()?;
+        Ok(Output::None)
+    }
+
+    fn get_description(&self) -> String {
+        String::from("Defines or lists aliases")
+    }
+
+    fn get_name(&self) -> String {
+        String::from("alias")
+    }
+
+    fn get_help(&self) -> String {
+        String::from(
+            "alias name=expansion defines an alias for a command line, e.g. \
+             alias errors='grep error'. Arguments given to the alias are appended to it. \
+             Aliases are saved to the workspace. With just a name it prints that alias, \
+             without arguments all of them",
+        )
+    }
+
+    fn get_params(&self) -> Params {
+        Params::none().variadic(
+            "definition",
+            ArgKind::String,
+            "name=expansion, or a name to look up",
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct UnaliasCommand;
+
+impl Command for UnaliasCommand {
+    fn execute(&self, args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+        let name = args
+            .string("name")
+            .ok_or(anyhow!("Expected an alias name"))?;
+        COMMAND_MANAGER
+            .read_recursive()
+            .remove_alias(name)
+            .ok_or(anyhow!("No alias named '{}'", name))?;
+        save_aliases()?;
+        Ok(Output::None)
+    }
+
+    fn get_description(&self) -> String {
+        String::from("Removes an alias")
+    }
+
+    fn get_name(&self) -> String {
+        String::from("unalias")
+    }
+
+    fn get_help(&self) -> String {
+        String::from("Removes an alias defined with alias and deletes it from the workspace")
+    }
+
+    fn get_params(&self) -> Params {
+        Params::none().required("name", ArgKind::String, "Alias to remove")
+    }
+
+    fn complete(&self, index: usize, partial: &str) -> Vec<String> {
+        if index > 0 {
+            return Vec::new();
+        }
+        let manager = COMMAND_MANAGER.read_recursive();
+        let aliases = manager.aliases.read();
+        filter_prefix(aliases.keys().map(String::as_str), partial)
+    }
 }
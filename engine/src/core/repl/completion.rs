@@ -0,0 +1,483 @@
+use std::{fs, path::Path};
+
+use rustyline::completion::{Completer, Pair};
+
+use super::{
+    handler::{COMMAND_MANAGER, CommandManager},
+    zensh::interpreter::parse_alias,
+};
+
+/// Keywords after which the next word names a command again.
+const COMMAND_KEYWORDS: &[&str] = &["if", "else", "while", "!"];
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Redirect,
+}
+
+#[derive(Debug, PartialEq)]
+enum Position {
+    Command,
+    Argument { command: String, index: usize },
+    RedirectTarget,
+}
+
+/// The word under the cursor and the words of the command it belongs to.
+#[derive(Debug)]
+struct Cursor {
+    /// Byte offset in the line where the word starts.
+    start: usize,
+    /// The word as typed so far, with quotes and escapes removed.
+    partial: String,
+    /// The quote the cursor is inside of, if any.
+    quote: Option<char>,
+    preceding: Vec<Token>,
+}
+
+impl Cursor {
+    fn new(start: usize) -> Cursor {
+        Cursor {
+            start,
+            partial: String::new(),
+            quote: None,
+            preceding: Vec::new(),
+        }
+    }
+
+    /// Scans the line up to the cursor using the lexer's quoting rules.
+    /// Unlike the lexer this never fails, since the line is usually
+    /// incomplete while being typed.
+    fn scan(line: &str) -> Cursor {
+        let mut cursor = Cursor::new(line.len());
+        let mut in_word = false;
+        // The words around each `$(` the cursor is inside of
+        let mut outer = Vec::new();
+        let mut chars = line.char_indices();
+
+        while let Some((i, c)) = chars.next() {
+            match (cursor.quote, c) {
+                (None | Some('"'), '$') if chars.clone().next().map(|(_, c)| c) == Some('(') => {
+                    chars.next();
+                    // The substitution is part of a word that carries on
+                    // after it, and its own words make up a command of
+                    // their own
+                    if !in_word {
+                        cursor.start = i;
+                    }
+                    outer.push(std::mem::replace(&mut cursor, Cursor::new(line.len())));
+                    in_word = false;
+                }
+                (None, ')') if !outer.is_empty() => {
+                    cursor = outer.pop().unwrap();
+                    in_word = true;
+                }
+                (Some('\''), '\'') => cursor.quote = None,
+                (Some('\''), _) => cursor.partial.push(c),
+                (Some(_), '"') => cursor.quote = None,
+                (Some(_), '\\') => cursor.partial.extend(chars.next().map(|(_, c)| c)),
+                (Some(_), _) => cursor.partial.push(c),
+                (None, ';' | '|' | '{' | '}' | '\n') => {
+                    cursor.finish_word(&mut in_word);
+                    cursor.preceding.clear();
+                }
+                (None, '<' | '>') => {
+                    // `2>` redirects stderr rather than passing an argument
+                    if in_word && cursor.partial == "2" {
+                        in_word = false;
+                        cursor.partial.clear();
+                    }
+                    cursor.finish_word(&mut in_word);
+                    if cursor.preceding.last() != Some(&Token::Redirect) {
+                        cursor.preceding.push(Token::Redirect);
+                    }
+                }
+                (None, c) if c.is_whitespace() => cursor.finish_word(&mut in_word),
+                (None, _) => {
+                    if !in_word {
+                        in_word = true;
+                        cursor.start = i;
+                    }
+                    match c {
+                        '\'' | '"' => cursor.quote = Some(c),
+                        '\\' => match chars.next() {
+                            Some((_, '\n')) | None => {}
+                            Some((_, escaped)) => cursor.partial.push(escaped),
+                        },
+                        _ => cursor.partial.push(c),
+                    }
+                }
+            }
+        }
+
+        if !in_word {
+            cursor.start = line.len();
+        }
+        cursor
+    }
+
+    fn finish_word(&mut self, in_word: &mut bool) {
+        if std::mem::take(in_word) {
+            self.preceding
+                .push(Token::Word(std::mem::take(&mut self.partial)));
+        }
+    }
+
+    fn position(&self) -> Position {
+        let mut words = Vec::new();
+        let mut redirect = false;
+        for token in &self.preceding {
+            match token {
+                Token::Redirect => redirect = true,
+                Token::Word(_) if redirect => redirect = false,
+                Token::Word(word) => words.push(word.as_str()),
+            }
+        }
+        if redirect {
+            return Position::RedirectTarget;
+        }
+
+        let keywords = words
+            .iter()
+            .take_while(|word| COMMAND_KEYWORDS.contains(word))
+            .count();
+        match words[keywords..].split_first() {
+            None => Position::Command,
+            Some((command, args)) => Position::Argument {
+                command: command.to_string(),
+                index: args.len(),
+            },
+        }
+    }
+
+    /// Escapes a candidate so it reads back as the same word, opening and,
+    /// unless it's a directory that will be completed further, closing the
+    /// quote the cursor is in.
+    fn replacement(&self, candidate: &str) -> String {
+        let Some(quote) = self.quote else {
+            let mut escaped = String::new();
+            for c in candidate.chars() {
+                if c.is_whitespace() || "'\"\\$;|{}<>#".contains(c) {
+                    escaped.push('\\');
+                }
+                escaped.push(c);
+            }
+            return escaped;
+        };
+
+        let mut quoted = quote.to_string();
+        for c in candidate.chars() {
+            if quote == '"' && matches!(c, '"' | '\\' | '$') {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        if !candidate.ends_with('/') {
+            quoted.push(quote);
+        }
+        quoted
+    }
+}
+
+#[derive(Default)]
+pub struct CommandCompleter;
+
+impl CommandCompleter {
+    pub fn new() -> Self {
+        CommandCompleter {}
+    }
+
+    fn candidates(manager: &CommandManager, cursor: &Cursor) -> Vec<String> {
+        match cursor.position() {
+            Position::Command => {
+                let aliases = manager.aliases.read();
+                let names = manager
+                    .get_commands()
+                    .map(|(name, _)| name.as_str())
+                    .chain(aliases.keys().map(String::as_str));
+                filter_prefix(names, &cursor.partial)
+            }
+            Position::RedirectTarget => complete_path(&cursor.partial, None),
+            Position::Argument { command, index } => {
+                // Typed arguments are appended to the last command of an
+                // alias, after any arguments it already has
+                let expansion = manager.get_alias(&command);
+                let last = expansion
+                    .and_then(|expansion| parse_alias(&expansion).ok())
+                    .and_then(|pipeline| pipeline.commands.last().cloned());
+                let (command, index) = match last {
+                    Some(call) => match call.words.first().and_then(|word| word.as_bare()) {
+                        Some(name) => (name.to_lowercase(), index + call.words.len() - 1),
+                        None => return Vec::new(),
+                    },
+                    None => (command, index),
+                };
+                manager
+                    .commands
+                    .get(&command)
+                    .map(|cmd| cmd.complete(index, &cursor.partial))
+                    .unwrap_or_default()
+            }
+        }
+    }
+}
+
+impl Completer for CommandCompleter {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Self::Candidate>)> {
+        let cursor = Cursor::scan(&line[..pos]);
+        let mut candidates = Self::candidates(&COMMAND_MANAGER.read(), &cursor);
+        candidates.sort();
+        candidates.dedup();
+
+        let pairs = candidates
+            .into_iter()
+            .map(|candidate| Pair {
+                replacement: cursor.replacement(&candidate),
+                display: candidate,
+            })
+            .collect();
+        Ok((cursor.start, pairs))
+    }
+}
+
+pub fn filter_prefix<'a>(items: impl IntoIterator<Item = &'a str>, partial: &str) -> Vec<String> {
+    items
+        .into_iter()
+        .filter(|item| item.starts_with(partial))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Completes a file system path. Directories always match and end in `/` so
+/// they can be completed further, files only match if they have the given
+/// extension.
+pub fn complete_path(partial: &str, extension: Option<&str>) -> Vec<String> {
+    let (dir, prefix) = match partial.rfind('/') {
+        Some(i) => partial.split_at(i + 1),
+        None => ("", partial),
+    };
+    let search = if dir.is_empty() { "." } else { dir };
+    let Ok(entries) = fs::read_dir(Path::new(search)) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden entries are only offered once a '.' has been typed
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let path = entry.path();
+            if path.is_dir() {
+                Some(format!("{dir}{name}/"))
+            } else if extension.is_none_or(|ext| path.extension().is_some_and(|e| e == ext)) {
+                Some(format!("{dir}{name}"))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::repl::commands::{
+        CameraCommand, EchoCommand, ExecFile, HistoryCommand, SpawnCommand,
+    };
+
+    fn manager() -> CommandManager {
+        let mut manager = CommandManager::init();
+        manager.add_command(Box::new(CameraCommand));
+        manager.add_command(Box::new(EchoCommand));
+        manager.add_command(Box::new(ExecFile));
+        manager.add_command(Box::new(HistoryCommand));
+        manager.add_command(Box::new(SpawnCommand));
+        manager.add_alias("hist", "history");
+        manager.add_alias("quiet", "echo x | history clear");
+        manager
+    }
+
+    fn complete(line: &str) -> Vec<String> {
+        let mut candidates = CommandCompleter::candidates(&manager(), &Cursor::scan(line));
+        candidates.sort();
+        candidates
+    }
+
+    fn words(cursor: &Cursor) -> Vec<&str> {
+        (cursor.preceding.iter())
+            .map(|token| match token {
+                Token::Word(word) => word.as_str(),
+                Token::Redirect => ">",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scan_finds_the_word_under_the_cursor() {
+        let cursor = Cursor::scan("echo hi the");
+        assert_eq!(words(&cursor), ["echo", "hi"]);
+        assert_eq!((cursor.start, cursor.partial.as_str()), (8, "the"));
+
+        // After a space a new word starts at the cursor
+        let cursor = Cursor::scan("echo hi ");
+        assert_eq!(words(&cursor), ["echo", "hi"]);
+        assert_eq!((cursor.start, cursor.partial.as_str()), (8, ""));
+    }
+
+    #[test]
+    fn commands_are_completed_at_the_start_of_each_pipeline_stage() {
+        assert_eq!(complete("sp"), ["spawn"]);
+        assert_eq!(complete("echo hi | sp"), ["spawn"]);
+        assert_eq!(complete("echo hi; hi"), ["hist", "history"]);
+        assert_eq!(complete("if ex"), ["exec"]);
+        assert_eq!(complete("while ! ec"), ["echo"]);
+        assert_eq!(
+            Cursor::scan("echo hi | spawn c").position(),
+            Position::Argument {
+                command: "spawn".into(),
+                index: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn arguments_are_completed_by_each_command() {
+        assert_eq!(complete("echo hi | spawn c"), ["cube", "cylinder"]);
+        assert_eq!(
+            complete("history "),
+            ["clear", "dedup", "list", "search", "size"]
+        );
+        assert_eq!(complete("history dedup o"), ["off", "on"]);
+        assert!(complete("history dedup on o").is_empty());
+        // Without a `complete` of its own, a command completes by its
+        // parameters' kinds
+        assert_eq!(complete("camera f"), ["fly"]);
+        assert!(complete("no_such_command a").is_empty());
+    }
+
+    #[test]
+    fn alias_arguments_follow_the_expansion() {
+        assert_eq!(complete("hist cl"), ["clear"]);
+        assert_eq!(complete("quiet o"), ["off", "on"]);
+    }
+
+    #[test]
+    fn substitutions_start_a_command_of_their_own() {
+        let cursor = Cursor::scan("echo $(sp");
+        assert_eq!(cursor.position(), Position::Command);
+        assert_eq!((cursor.start, cursor.partial.as_str()), (7, "sp"));
+        assert_eq!(complete("echo $(sp"), ["spawn"]);
+        assert_eq!(complete("echo \"$(spawn c"), ["cube", "cylinder"]);
+        assert_eq!(complete("echo $(echo $(hist"), ["hist", "history"]);
+
+        // Once closed, the substitution is part of an argument
+        let cursor = Cursor::scan("echo $(spawn cube | echo)x");
+        assert_eq!(words(&cursor), ["echo"]);
+        assert_eq!(cursor.start, 5);
+        assert_eq!(
+            Cursor::scan("echo $(spawn cube) ").position(),
+            Position::Argument {
+                command: "echo".into(),
+                index: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn quotes_and_escapes_are_removed_from_the_partial_word() {
+        let cursor = Cursor::scan(r#"echo "two \"wo"#);
+        assert_eq!(cursor.quote, Some('"'));
+        assert_eq!((cursor.start, cursor.partial.as_str()), (5, "two \"wo"));
+
+        let cursor = Cursor::scan(r"echo 'a\b c");
+        assert_eq!(cursor.quote, Some('\''));
+        assert_eq!(cursor.partial, r"a\b c");
+
+        let cursor = Cursor::scan(r"echo 'a b'\ c");
+        assert_eq!(cursor.quote, None);
+        assert_eq!(cursor.partial, "a b c");
+
+        // Separators inside quotes don't end the command
+        let cursor = Cursor::scan("echo 'a | b' c");
+        assert_eq!(words(&cursor), ["echo", "a | b"]);
+    }
+
+    #[test]
+    fn replacements_read_back_as_the_candidate() {
+        let unquoted = Cursor::scan("echo ");
+        assert_eq!(unquoted.replacement("two words"), r"two\ words");
+        assert_eq!(unquoted.replacement("$a;b|c"), r"\$a\;b\|c");
+
+        let double = Cursor::scan("echo \"t");
+        assert_eq!(double.replacement("say \"$hi\""), r#""say \"\$hi\"""#);
+        assert_eq!(double.replacement("my dir/"), "\"my dir/");
+
+        let single = Cursor::scan("echo 't");
+        assert_eq!(single.replacement("it's $a"), "'it's $a'");
+    }
+
+    #[test]
+    fn redirect_targets_are_paths() {
+        assert_eq!(
+            Cursor::scan("echo hi > ou").position(),
+            Position::RedirectTarget
+        );
+        assert_eq!(
+            Cursor::scan("echo hi 2>>").position(),
+            Position::RedirectTarget
+        );
+        assert_eq!(
+            Cursor::scan("echo hi > out.txt mo").position(),
+            Position::Argument {
+                command: "echo".into(),
+                index: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn paths_with_spaces() {
+        let dir = std::env::temp_dir().join(format!("zenyx-complete-{}", std::process::id()));
+        fs::create_dir_all(dir.join("my scenes")).unwrap();
+        fs::write(dir.join("two words.zensh"), "").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        let dir_name = dir.display().to_string();
+
+        // `exec` only offers scripts and directories
+        let listed = complete(&format!("exec {dir_name}/"));
+        let typed = format!("exec {dir_name}/two\\ w");
+        let escaped = complete(&typed);
+        let quoted = Cursor::scan(&format!("exec \"{dir_name}/my"));
+        let quoted_candidates = CommandCompleter::candidates(&manager(), &quoted);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            listed,
+            [
+                format!("{dir_name}/my scenes/"),
+                format!("{dir_name}/two words.zensh"),
+            ]
+        );
+        assert_eq!(escaped, [format!("{dir_name}/two words.zensh")]);
+        assert_eq!(
+            Cursor::scan(&typed).replacement(&escaped[0]),
+            format!("{dir_name}/two\\ words.zensh")
+        );
+        // The quote stays open so the directory can be completed further
+        assert_eq!(quoted_candidates, [format!("{dir_name}/my scenes/")]);
+        assert_eq!(
+            quoted.replacement(&quoted_candidates[0]),
+            format!("\"{dir_name}/my scenes/")
+        );
+    }
+}
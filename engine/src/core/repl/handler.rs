@@ -1,8 +1,15 @@
-use std::collections::HashMap;
+use std::{
+    any::Any,
+    collections::{HashMap, VecDeque},
+    fmt,
+};
 
-use colored::Colorize;
+use anyhow::anyhow;
 use lazy_static::lazy_static;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use thiserror::Error;
+
+use super::args::{Args, Params};
 lazy_static! {
     pub static ref COMMAND_MANAGER: RwLock<CommandManager> = RwLock::new(CommandManager::init());
 }
@@ -23,7 +30,7 @@ macro_rules! alias {
     ($($alias:expr => $command:expr),*) => {
         $(
             {
-                let mut manager = $crate::COMMAND_MANAGER.write();
+                let manager = $crate::core::repl::handler::COMMAND_MANAGER.read();
                 manager.add_alias($alias, $command);
             }
         )*
@@ -71,7 +78,8 @@ fn check_similarity(target: &str) -> Option<String> {
     let mut best_match: Option<String> = None;
     let mut best_distance = usize::MAX;
 
-    for (cmd_name, _) in COMMAND_MANAGER.read().get_commands() {
+    // Called from `execute_command`, under the caller's read lock
+    for (cmd_name, _) in COMMAND_MANAGER.read_recursive().get_commands() {
         if let Some(hamming_dist) = hamming_distance(target, cmd_name) {
             if hamming_dist <= max_hamming_distance && hamming_dist < best_distance {
                 best_distance = hamming_dist;
@@ -89,16 +97,133 @@ fn check_similarity(target: &str) -> Option<String> {
     best_match
 }
 
+const HISTORY_LIMIT: usize = 100;
+
+/// Returned by `exit`. It stops the script or REPL running it like any other
+/// error, and whatever started them exits the process with the code once
+/// it's cleaned up.
+#[derive(Debug, Error)]
+#[error("exit {0}")]
+pub struct Exit(pub u8);
+
+impl Exit {
+    /// The code `error` asks to exit with, if it came from `exit`.
+    pub fn code(error: &anyhow::Error) -> Option<u8> {
+        error.downcast_ref::<Exit>().map(|exit| exit.0)
+    }
+}
+
+/// Returned by [`Command::undo`] for commands that can never be undone, as
+/// opposed to ones that failed to undo this time. The history drops their
+/// entries so that the commands before them can still be undone.
+#[derive(Debug, Error)]
+#[error("Command '{0}' cannot be undone")]
+pub struct CannotUndo(pub String);
+
+/// What a command hands back instead of printing. Piped commands receive the
+/// previous command's output as their input.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Output {
+    #[default]
+    None,
+    Text(String),
+    Lines(Vec<String>),
+}
+
+impl Output {
+    pub fn is_none(&self) -> bool {
+        match self {
+            Output::None => true,
+            Output::Text(text) => text.is_empty(),
+            Output::Lines(lines) => lines.is_empty(),
+        }
+    }
+
+    pub fn lines(&self) -> Vec<&str> {
+        match self {
+            Output::None => Vec::new(),
+            Output::Text(text) => text.lines().collect(),
+            Output::Lines(lines) => lines.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Output::None => Ok(()),
+            Output::Text(text) => write!(f, "{}", text.trim_end_matches('\n')),
+            Output::Lines(lines) => write!(f, "{}", lines.join("\n")),
+        }
+    }
+}
+
+/// State a command captures before it runs so that it can later be undone.
+pub type CommandState = Box<dyn Any + Send + Sync>;
+
+pub struct HistoryEntry {
+    pub command: String,
+    pub args: Args,
+    pub input: Output,
+    pub state: Option<CommandState>,
+}
+
+/// Bounded undo/redo stacks of the undoable commands that have been executed.
+pub struct CommandHistory {
+    undo_stack: VecDeque<HistoryEntry>,
+    redo_stack: Vec<HistoryEntry>,
+    limit: usize,
+}
+
+impl CommandHistory {
+    pub fn new(limit: usize) -> CommandHistory {
+        CommandHistory {
+            undo_stack: VecDeque::with_capacity(limit),
+            redo_stack: Vec::new(),
+            limit,
+        }
+    }
+
+    /// Records a freshly executed command. This invalidates anything that
+    /// could previously have been redone.
+    pub fn record(&mut self, entry: HistoryEntry) {
+        self.redo_stack.clear();
+        self.push_undo(entry);
+    }
+
+    fn push_undo(&mut self, entry: HistoryEntry) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.undo_stack.len() == self.limit {
+            self.undo_stack.pop_front();
+        }
+        self.undo_stack.push_back(entry);
+    }
+
+    pub fn undo_len(&self) -> usize {
+        self.undo_stack.len()
+    }
+
+    pub fn redo_len(&self) -> usize {
+        self.redo_stack.len()
+    }
+}
+
 pub struct CommandManager {
     pub commands: HashMap<String, Box<dyn Command>>,
-    pub aliases: HashMap<String, String>,
+    /// Behind their own lock so commands can define aliases while the
+    /// manager is borrowed to run them.
+    pub aliases: RwLock<HashMap<String, String>>,
+    pub history: Mutex<CommandHistory>,
 }
 
 impl CommandManager {
     pub fn init() -> CommandManager {
         CommandManager {
             commands: HashMap::new(),
-            aliases: HashMap::new(),
+            aliases: RwLock::new(HashMap::new()),
+            history: Mutex::new(CommandHistory::new(HISTORY_LIMIT)),
         }
     }
 
@@ -106,54 +231,314 @@ impl CommandManager {
         self.commands.iter()
     }
 
+    /// Runs a registered command. Aliases are expanded by the shell before
+    /// commands get here, since they can stand for a whole pipeline.
     pub fn execute_command(
         &self,
         command: &str,
         args: Option<Vec<String>>,
-    ) -> Result<(), anyhow::Error> {
-        if let Some(command) = self.commands.get(command) {
-            command.execute(args)?;
-            Ok(())
+        input: Output,
+    ) -> Result<Output, anyhow::Error> {
+        if let Some(cmd) = self.commands.get(command) {
+            let params = cmd.get_params();
+            let args = params
+                .validate(args)
+                .map_err(|e| anyhow!("{}\nUsage: {}", e, params.usage(command)))?;
+            if cmd.is_undoable() {
+                let state = cmd.capture();
+                let output = cmd.execute(&args, input.clone())?;
+                self.history.lock().record(HistoryEntry {
+                    command: command.to_string(),
+                    args,
+                    input,
+                    state,
+                });
+                Ok(output)
+            } else {
+                cmd.execute(&args, input)
+            }
         } else {
-            let corrected_cmd = check_similarity(command);
-            if corrected_cmd.is_some() {
-                println!("Command: {} was not found. Did you mean {}?",command.red().bold(),corrected_cmd
-                    .expect("A command was editied during execution, something has gone seriously wrong").green().bold().italic());
+            match check_similarity(command) {
+                Some(corrected_cmd) => Err(anyhow!(
+                    "Command '{}' not found. Did you mean '{}'?",
+                    command,
+                    corrected_cmd
+                )),
+                None => Err(anyhow!("Command '{}' not found.", command)),
             }
-            Err(anyhow::anyhow!("Command '{}' not found.", command))
         }
     }
 
-    pub fn execute(&self, command: &str, args: Option<Vec<String>>) -> Result<(), anyhow::Error> {
-        match self.aliases.get(command) {
-            Some(command) => self.execute(command, args),
-            // check to see if we are using an alias or the command just doesnt exist
-            None => {
-                self.execute_command(command, args)?;
-                Ok(())
+    /// Undoes the most recent undoable command, returning its name and
+    /// whatever the command reported while undoing. Entries above it whose
+    /// commands can't be undone are dropped, and mentioned in the output.
+    pub fn undo(&self) -> Result<(String, Output), anyhow::Error> {
+        let mut skipped = Vec::new();
+        loop {
+            let Some(entry) = self.history.lock().undo_stack.pop_back() else {
+                skipped.push(String::from("Nothing to undo"));
+                return Err(anyhow!(skipped.join("\n")));
+            };
+            let Some(command) = self.commands.get(&entry.command) else {
+                return Err(anyhow!(
+                    "Command '{}' is no longer registered",
+                    entry.command
+                ));
+            };
+            let output = match command.undo(&entry) {
+                Ok(output) => output,
+                Err(e) if e.is::<CannotUndo>() => {
+                    skipped.push(format!("Skipped '{}': {e}", entry.command));
+                    continue;
+                }
+                Err(e) => {
+                    self.history.lock().undo_stack.push_back(entry);
+                    return Err(e);
+                }
+            };
+            let name = entry.command.clone();
+            self.history.lock().redo_stack.push(entry);
+            if !skipped.is_empty() {
+                skipped.extend(output.lines().into_iter().map(String::from));
+                return Ok((name, Output::Lines(skipped)));
             }
+            return Ok((name, output));
         }
     }
 
+    /// Re-applies the most recently undone command, returning its name and
+    /// output.
+    pub fn redo(&self) -> Result<(String, Output), anyhow::Error> {
+        let mut entry = self
+            .history
+            .lock()
+            .redo_stack
+            .pop()
+            .ok_or(anyhow!("Nothing to redo"))?;
+        let Some(command) = self.commands.get(&entry.command) else {
+            return Err(anyhow!(
+                "Command '{}' is no longer registered",
+                entry.command
+            ));
+        };
+        let state = command.capture();
+        let output = match command.redo(&entry) {
+            Ok(output) => output,
+            Err(e) => {
+                self.history.lock().redo_stack.push(entry);
+                return Err(e);
+            }
+        };
+        entry.state = state;
+        let name = entry.command.clone();
+        self.history.lock().push_undo(entry);
+        Ok((name, output))
+    }
+
     pub fn add_command(&mut self, command: Box<dyn Command>) {
         self.commands
             .insert(command.get_name().to_lowercase(), command);
     }
 
-    pub fn add_alias(&mut self, alias: &str, command: &str) {
-        self.aliases.insert(
-            alias.to_string().to_lowercase(),
-            command.to_string().to_lowercase(),
-        );
+    pub fn add_alias(&self, alias: &str, expansion: &str) {
+        self.aliases
+            .write()
+            .insert(alias.to_lowercase(), expansion.trim().to_string());
+    }
+
+    pub fn remove_alias(&self, alias: &str) -> Option<String> {
+        self.aliases.write().remove(&alias.to_lowercase())
+    }
+
+    pub fn get_alias(&self, alias: &str) -> Option<String> {
+        self.aliases.read().get(&alias.to_lowercase()).cloned()
     }
 }
 
 pub trait Command: Send + Sync {
-    fn execute(&self, args: Option<Vec<String>>) -> Result<(), anyhow::Error>;
-    fn undo(&self);
-    fn redo(&self);
+    fn execute(&self, args: &Args, input: Output) -> Result<Output, anyhow::Error>;
+
+    /// Whether executions of this command are recorded in the undo history.
+    fn is_undoable(&self) -> bool {
+        false
+    }
+
+    /// Called right before an undoable command executes. The returned state
+    /// is stored in the history entry and handed back to `undo`.
+    fn capture(&self) -> Option<CommandState> {
+        None
+    }
+
+    fn undo(&self, _entry: &HistoryEntry) -> Result<Output, anyhow::Error> {
+        Err(CannotUndo(self.get_name()).into())
+    }
+
+    fn redo(&self, entry: &HistoryEntry) -> Result<Output, anyhow::Error> {
+        self.execute(&entry.args, entry.input.clone())
+    }
+
+    /// Completions for the argument at `index`, given the part of it typed
+    /// so far. Defaults to completing by the parameter's kind.
+    fn complete(&self, index: usize, partial: &str) -> Vec<String> {
+        self.get_params()
+            .get(index)
+            .map(|param| param.kind.complete(partial))
+            .unwrap_or_default()
+    }
+
     fn get_description(&self) -> String;
     fn get_name(&self) -> String;
     fn get_help(&self) -> String;
-    fn get_params(&self) -> String;
+    fn get_params(&self) -> Params;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::repl::commands::{CounterCommand, EchoCommand};
+
+    /// Undoable, but with the default `undo` that refuses.
+    #[derive(Default)]
+    struct StubbornCommand;
+
+    impl Command for StubbornCommand {
+        fn execute(&self, _args: &Args, _input: Output) -> Result<Output, anyhow::Error> {
+            Ok(Output::None)
+        }
+
+        fn is_undoable(&self) -> bool {
+            true
+        }
+
+        fn get_description(&self) -> String {
+            String::new()
+        }
+
+        fn get_name(&self) -> String {
+            String::from("stubborn")
+        }
+
+        fn get_help(&self) -> String {
+            String::new()
+        }
+
+        fn get_params(&self) -> Params {
+            Params::none()
+        }
+    }
+
+    fn manager() -> CommandManager {
+        let mut manager = CommandManager::init();
+        manager.add_command(Box::new(CounterCommand::default()));
+        manager.add_command(Box::new(EchoCommand));
+        manager.add_command(Box::new(StubbornCommand));
+        manager
+    }
+
+    fn count(manager: &CommandManager) -> String {
+        manager
+            .execute_command("count", None, Output::None)
+            .unwrap()
+            .to_string()
+    }
+
+    fn entry(command: &str) -> HistoryEntry {
+        HistoryEntry {
+            command: command.to_string(),
+            args: Args::default(),
+            input: Output::None,
+            state: None,
+        }
+    }
+
+    #[test]
+    fn undo_restores_state_and_redo_reapplies() {
+        let manager = manager();
+        count(&manager);
+        assert!(count(&manager).ends_with("Current count: 2"));
+
+        let (name, output) = manager.undo().unwrap();
+        assert_eq!(name, "count");
+        assert!(output.to_string().ends_with("Current count: 1"));
+        assert_eq!(manager.history.lock().undo_len(), 1);
+        assert_eq!(manager.history.lock().redo_len(), 1);
+
+        let (name, output) = manager.redo().unwrap();
+        assert_eq!(name, "count");
+        assert!(output.to_string().ends_with("Current count: 2"));
+        assert_eq!(manager.history.lock().undo_len(), 2);
+        assert_eq!(manager.history.lock().redo_len(), 0);
+    }
+
+    #[test]
+    fn new_commands_clear_redo() {
+        let manager = manager();
+        count(&manager);
+        manager.undo().unwrap();
+        count(&manager);
+        assert_eq!(manager.redo().unwrap_err().to_string(), "Nothing to redo");
+    }
+
+    #[test]
+    fn commands_that_are_not_undoable_are_not_recorded() {
+        let manager = manager();
+        manager
+            .execute_command("echo", Some(vec!["hi".into()]), Output::None)
+            .unwrap();
+        assert_eq!(manager.undo().unwrap_err().to_string(), "Nothing to undo");
+    }
+
+    #[test]
+    fn refused_undo_is_dropped_from_history() {
+        let manager = manager();
+        manager
+            .execute_command("stubborn", None, Output::None)
+            .unwrap();
+        let error = manager.undo().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Skipped 'stubborn': Command 'stubborn' cannot be undone\nNothing to undo"
+        );
+        assert_eq!(manager.history.lock().undo_len(), 0);
+        assert_eq!(manager.history.lock().redo_len(), 0);
+    }
+
+    #[test]
+    fn refused_undo_does_not_hide_earlier_entries() {
+        let manager = manager();
+        count(&manager);
+        count(&manager);
+        manager
+            .execute_command("stubborn", None, Output::None)
+            .unwrap();
+
+        let (name, output) = manager.undo().unwrap();
+        assert_eq!(name, "count");
+        let lines = output.lines();
+        assert_eq!(
+            lines[0],
+            "Skipped 'stubborn': Command 'stubborn' cannot be undone"
+        );
+        assert!(lines.last().unwrap().ends_with("Current count: 1"));
+        assert_eq!(manager.history.lock().undo_len(), 1);
+
+        // Redo only has the command that was undone
+        manager.redo().unwrap();
+        assert_eq!(manager.redo().unwrap_err().to_string(), "Nothing to redo");
+        assert_eq!(manager.history.lock().undo_len(), 2);
+    }
+
+    #[test]
+    fn history_drops_oldest_entries_past_its_limit() {
+        let mut history = CommandHistory::new(2);
+        for command in ["a", "b", "c"] {
+            history.record(entry(command));
+        }
+        assert_eq!(history.undo_len(), 2);
+        assert_eq!(history.undo_stack.front().unwrap().command, "b");
+
+        let mut history = CommandHistory::new(0);
+        history.record(entry("a"));
+        assert_eq!(history.undo_len(), 0);
+    }
 }
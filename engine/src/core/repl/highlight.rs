@@ -0,0 +1,369 @@
+use colored::{Color, Colorize};
+
+const KEYWORDS: &[&str] = &[
+    "if", "else", "for", "in", "while", "fn", "set", "unset", "break", "continue", "true", "false",
+    "!",
+];
+
+/// Keywords that are followed by another command rather than by arguments.
+const COMMAND_KEYWORDS: &[&str] = &["if", "else", "while", "!"];
+
+/// Colours a line of zensh input for display in the line editor. Only colour
+/// codes are added, the visible text is unchanged, and incomplete input is
+/// highlighted as far as it goes instead of failing.
+///
+/// `is_command` tells whether a name in command position can be run.
+pub fn highlight(line: &str, is_command: &dyn Fn(&str) -> bool) -> String {
+    LineHighlighter {
+        chars: line.chars().collect(),
+        pos: 0,
+        out: String::with_capacity(line.len()),
+        is_command,
+    }
+    .run()
+}
+
+struct LineHighlighter<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    out: String,
+    is_command: &'a dyn Fn(&str) -> bool,
+}
+
+impl LineHighlighter<'_> {
+    fn run(mut self) -> String {
+        let mut command_position = true;
+        while let Some(&c) = self.chars.get(self.pos) {
+            if matches!(c, '\n' | ';' | '|' | '{' | '}') {
+                self.push(self.pos + 1, Color::BrightBlue);
+                command_position = true;
+            } else if c.is_whitespace() {
+                self.out.push(c);
+                self.pos += 1;
+            } else if c == '#' {
+                let end = self.find_from(self.pos, |c| c == '\n');
+                self.push(end, Color::BrightBlack);
+            } else if let Some(len) = self.operator_len() {
+                self.push(self.pos + len, Color::BrightBlue);
+            } else {
+                command_position = self.word(command_position);
+            }
+        }
+        self.out
+    }
+
+    fn text(&self, start: usize, end: usize) -> String {
+        self.chars[start..end].iter().collect()
+    }
+
+    /// Writes the text up to `end` in one style and moves past it.
+    fn push(&mut self, end: usize, color: Color) {
+        if end <= self.pos {
+            return;
+        }
+        let text = self.text(self.pos, end);
+        self.out.push_str(&text.color(color).to_string());
+        self.pos = end;
+    }
+
+    fn find_from(&self, start: usize, pred: impl Fn(char) -> bool) -> usize {
+        (start..self.chars.len())
+            .find(|&i| pred(self.chars[i]))
+            .unwrap_or(self.chars.len())
+    }
+
+    fn operator_len(&self) -> Option<usize> {
+        let at = |offset| self.chars.get(self.pos + offset).copied();
+        match (at(0)?, at(1), at(2)) {
+            ('2', Some('>'), Some('>')) => Some(3),
+            ('2', Some('>'), _) => Some(2),
+            ('>' | '<' | '=' | '!', Some('='), _) | ('>', Some('>'), _) => Some(2),
+            ('>' | '<', _, _) => Some(1),
+            _ => None,
+        }
+    }
+
+    /// Returns the index just past the `)` closing a `$(` whose body starts
+    /// at `start`.
+    fn substitution_end(&self, start: usize) -> usize {
+        let mut depth = 0;
+        let mut quote = None;
+        let mut i = start;
+        while let Some(&c) = self.chars.get(i) {
+            match (quote, c) {
+                (Some('\''), '\'') => quote = None,
+                (Some('\''), _) => {}
+                (_, '\\') => i += 1,
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '\'' | '"') => quote = Some(c),
+                (None, '(') => depth += 1,
+                (None, ')') if depth == 0 => return i + 1,
+                (None, ')') => depth -= 1,
+                _ => {}
+            }
+            i += 1;
+        }
+        self.chars.len()
+    }
+
+    fn quote_end(&self, start: usize) -> usize {
+        let quote = self.chars[start];
+        let mut i = start + 1;
+        while let Some(&c) = self.chars.get(i) {
+            if c == quote {
+                return i + 1;
+            }
+            if c == '\\' && quote == '"' {
+                i += 1;
+            }
+            i += 1;
+        }
+        self.chars.len()
+    }
+
+    fn word_end(&self) -> usize {
+        let mut i = self.pos;
+        while let Some(&c) = self.chars.get(i) {
+            match c {
+                '\'' | '"' => i = self.quote_end(i),
+                '\\' => i += 2,
+                '$' if self.chars.get(i + 1) == Some(&'(') => i = self.substitution_end(i + 2),
+                '$' if self.chars.get(i + 1) == Some(&'{') => {
+                    i = self.find_from(i, |c| c == '}') + 1;
+                }
+                c if c.is_whitespace() || matches!(c, ';' | '|' | '}' | '<' | '>') => {
+                    break;
+                }
+                _ => i += 1,
+            }
+        }
+        i.min(self.chars.len())
+    }
+
+    /// Highlights the word at the cursor and returns whether the next word is
+    /// in command position.
+    fn word(&mut self, command_position: bool) -> bool {
+        let end = self.word_end();
+        let word = self.text(self.pos, end);
+        let is_bare = !word.contains(['\'', '"', '\\', '$']);
+
+        if is_bare && command_position {
+            if KEYWORDS.contains(&word.as_str()) {
+                self.push(end, Color::Magenta);
+                return COMMAND_KEYWORDS.contains(&word.as_str());
+            }
+            if (self.is_command)(&word) {
+                self.out.push_str(&word.green().bold().to_string());
+                self.pos = end;
+            } else {
+                self.push(end, Color::Red);
+            }
+        } else if is_bare && word.parse::<f64>().is_ok() {
+            self.push(end, Color::BrightMagenta);
+        } else {
+            self.word_parts(end);
+        }
+        false
+    }
+
+    fn word_parts(&mut self, end: usize) {
+        while self.pos < end {
+            let c = self.chars[self.pos];
+            match (c, self.chars.get(self.pos + 1).copied()) {
+                ('\'' | '"', _) => {
+                    let quote_end = self.quote_end(self.pos).min(end);
+                    self.push(quote_end, Color::Yellow);
+                }
+                ('\\', _) => {
+                    self.out
+                        .extend(&self.chars[self.pos..(self.pos + 2).min(end)]);
+                    self.pos += 2;
+                }
+                ('$', Some('(')) => {
+                    self.push(self.pos + 2, Color::Cyan);
+                    let close = self.substitution_end(self.pos);
+                    let inner_end = if self.chars.get(close - 1) == Some(&')') {
+                        close - 1
+                    } else {
+                        close
+                    };
+                    let inner = self.text(self.pos, inner_end);
+                    self.out.push_str(&highlight(&inner, self.is_command));
+                    self.pos = inner_end;
+                    self.push(close, Color::Cyan);
+                }
+                ('$', Some('{')) => {
+                    let close = self.find_from(self.pos, |c| c == '}');
+                    self.push((close + 1).min(end), Color::Cyan);
+                }
+                ('$', Some('?' | '#' | '@')) => self.push(self.pos + 2, Color::Cyan),
+                ('$', Some(next)) if next.is_alphanumeric() || next == '_' => {
+                    let name_end =
+                        self.find_from(self.pos + 1, |c| !(c.is_alphanumeric() || c == '_'));
+                    self.push(name_end.min(end), Color::Cyan);
+                }
+                _ => {
+                    self.out.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+        self.pos = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    fn is_command(name: &str) -> bool {
+        matches!(name, "echo" | "spawn")
+    }
+
+    fn highlighted(line: &str) -> String {
+        colored::control::set_override(true);
+        highlight(line, &is_command)
+    }
+
+    fn command(name: &str) -> String {
+        name.green().bold().to_string()
+    }
+
+    fn colored(text: &str, color: Color) -> String {
+        text.color(color).to_string()
+    }
+
+    /// Removes the colour codes again.
+    fn plain(highlighted: &str) -> String {
+        let mut plain = String::new();
+        let mut chars = highlighted.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|&c| c == 'm');
+            } else {
+                plain.push(c);
+            }
+        }
+        plain
+    }
+
+    #[test]
+    fn only_colours_are_added() {
+        for line in [
+            "echo hi | spawn cube; nope",
+            "if true { echo $a } else { echo ${b}c }",
+            "echo \"unterminated $(echo 'x",
+            "echo $(echo $(echo (a)) b",
+            "echo a\\",
+            "spawn 2>> err.txt >= > < # comment",
+            "fn f { echo $@ $# $? }\nf 1 2.5",
+        ] {
+            assert_eq!(plain(&highlighted(line)), line);
+        }
+    }
+
+    #[test]
+    fn unterminated_strings_run_to_the_end() {
+        assert_eq!(
+            highlighted("echo \"a | b"),
+            format!("{} {}", command("echo"), colored("\"a | b", Color::Yellow))
+        );
+        assert_eq!(
+            highlighted("echo 'a\\' b"),
+            format!(
+                "{} {}",
+                command("echo"),
+                colored("'a\\'", Color::Yellow) + " b"
+            )
+        );
+        // An escaped quote doesn't end a double-quoted string
+        assert_eq!(
+            highlighted("echo \"a\\\" b"),
+            format!(
+                "{} {}",
+                command("echo"),
+                colored("\"a\\\" b", Color::Yellow)
+            )
+        );
+    }
+
+    #[test]
+    fn substitutions_are_highlighted_as_commands_of_their_own() {
+        let cyan = |text| colored(text, Color::Cyan);
+        assert_eq!(
+            highlighted("echo $(echo $(nope)) x"),
+            format!(
+                "{} {}{} {}{}{}{} x",
+                command("echo"),
+                cyan("$("),
+                command("echo"),
+                cyan("$("),
+                colored("nope", Color::Red),
+                cyan(")"),
+                cyan(")"),
+            )
+        );
+        // Parentheses inside don't close it, and an unclosed one runs to
+        // the end of the line
+        assert_eq!(
+            highlighted("echo $(spawn (a) b"),
+            format!(
+                "{} {}{} (a) b",
+                command("echo"),
+                cyan("$("),
+                command("spawn")
+            )
+        );
+    }
+
+    #[test]
+    fn keywords_only_in_command_position() {
+        let keyword = |text| colored(text, Color::Magenta);
+        let punctuation = |text| colored(text, Color::BrightBlue);
+        assert_eq!(
+            highlighted("if ! echo if { set x 1 }"),
+            format!(
+                "{} {} {} if {} {} x {} {}",
+                keyword("if"),
+                keyword("!"),
+                command("echo"),
+                punctuation("{"),
+                keyword("set"),
+                colored("1", Color::BrightMagenta),
+                punctuation("}"),
+            )
+        );
+        // Quoting a keyword makes it a command name
+        assert_eq!(
+            highlighted("'if' a"),
+            format!("{} a", colored("'if'", Color::Yellow))
+        );
+    }
+
+    #[test]
+    fn command_names_are_checked_with_the_callback() {
+        colored::control::set_override(true);
+        let asked = RefCell::new(Vec::new());
+        let known = |name: &str| {
+            asked.borrow_mut().push(name.to_string());
+            matches!(name, "my_alias" | "my_fn")
+        };
+        let line = highlight("my_alias a | my_fn b; nope", &known);
+        assert_eq!(
+            line,
+            format!(
+                "{} a {} {} b{} {}",
+                command("my_alias"),
+                colored("|", Color::BrightBlue),
+                command("my_fn"),
+                colored(";", Color::BrightBlue),
+                colored("nope", Color::Red),
+            )
+        );
+        // Only words in command position are looked up
+        assert_eq!(*asked.borrow(), ["my_alias", "my_fn", "nope"]);
+    }
+}
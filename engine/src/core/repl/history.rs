@@ -0,0 +1,356 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, anyhow};
+use lazy_static::lazy_static;
+use log::debug;
+use parking_lot::Mutex;
+
+use crate::core::workspace;
+
+pub const DEFAULT_LIMIT: usize = 1000;
+/// How long new entries may stay unsaved while the REPL is running.
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    pub static ref HISTORY: Mutex<History> = Mutex::new(History::new(DEFAULT_LIMIT));
+}
+
+pub fn history_path() -> anyhow::Result<PathBuf> {
+    Ok(workspace::get_data_dir()?.join("history.txt"))
+}
+
+/// Lines entered into the REPL, oldest first. Entries are numbered from 1
+/// for display and for `!n` references.
+pub struct History {
+    entries: VecDeque<String>,
+    limit: usize,
+    dedup: bool,
+    path: Option<PathBuf>,
+    dirty: bool,
+    last_save: Instant,
+}
+
+impl History {
+    pub fn new(limit: usize) -> History {
+        History {
+            entries: VecDeque::new(),
+            limit,
+            dedup: true,
+            path: None,
+            dirty: false,
+            last_save: Instant::now(),
+        }
+    }
+
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = (usize, &str)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (i + 1, entry.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.truncate();
+    }
+
+    pub fn dedup(&self) -> bool {
+        self.dedup
+    }
+
+    /// When enabled, adding an entry removes any earlier copies of it.
+    pub fn set_dedup(&mut self, dedup: bool) {
+        self.dedup = dedup;
+        if dedup {
+            let mut seen = HashSet::new();
+            let mut kept: Vec<String> = Vec::new();
+            for entry in self.entries.drain(..).rev() {
+                if seen.insert(entry.clone()) {
+                    kept.push(entry);
+                }
+            }
+            self.entries = kept.into_iter().rev().collect();
+            self.dirty = true;
+        }
+    }
+
+    pub fn add(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        if self.dedup {
+            self.entries.retain(|entry| entry != line);
+        }
+        self.entries.push_back(line.to_string());
+        self.truncate();
+        self.dirty = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.dirty = true;
+    }
+
+    fn truncate(&mut self) {
+        while self.entries.len() > self.limit {
+            self.entries.pop_front();
+            self.dirty = true;
+        }
+    }
+
+    /// Entries containing `text`, with their numbers.
+    pub fn search<'a>(&'a self, text: &'a str) -> impl Iterator<Item = (usize, &'a str)> {
+        self.entries()
+            .filter(move |(_, entry)| entry.contains(text))
+    }
+
+    /// Expands a `!!`, `!n`, `!-n` or `!prefix` reference to the entry it
+    /// names. Returns `None` if the line isn't a history reference.
+    pub fn expand(&self, line: &str) -> anyhow::Result<Option<String>> {
+        let line = line.trim();
+        let Some(reference) = line.strip_prefix('!') else {
+            return Ok(None);
+        };
+        // `!` followed by a space negates a condition
+        if reference.is_empty() || reference.starts_with(char::is_whitespace) {
+            return Ok(None);
+        }
+
+        let found = if reference == "!" {
+            self.entries.back()
+        } else if let Ok(n) = reference.parse::<isize>() {
+            let index = if n < 0 {
+                self.entries.len().checked_sub(n.unsigned_abs())
+            } else {
+                n.unsigned_abs().checked_sub(1)
+            };
+            index.and_then(|i| self.entries.get(i))
+        } else {
+            self.entries
+                .iter()
+                .rev()
+                .find(|entry| entry.starts_with(reference))
+        };
+        found
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| anyhow!("{}: event not found", line))
+    }
+
+    /// Loads entries saved at `path` and saves back to it from then on.
+    pub fn load(&mut self, path: PathBuf) -> anyhow::Result<()> {
+        let loaded = match fs::read_to_string(&path) {
+            Ok(contents) => contents.lines().map(unescape).collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                debug!("No previous history.");
+                Vec::new()
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+            }
+        };
+        self.path = Some(path);
+        // Entries added before loading are newer than the saved ones
+        let added: Vec<String> = self.entries.drain(..).collect();
+        for line in loaded.iter().chain(&added) {
+            self.add(line);
+        }
+        self.dirty = !added.is_empty();
+        Ok(())
+    }
+
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let mut contents = String::new();
+        for entry in &self.entries {
+            contents.push_str(&escape(entry));
+            contents.push('\n');
+        }
+        fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+        self.dirty = false;
+        self.last_save = Instant::now();
+        Ok(())
+    }
+
+    /// Saves if there are changes that have gone unsaved for a while.
+    pub fn save_periodically(&mut self) -> anyhow::Result<()> {
+        if self.last_save.elapsed() >= SAVE_INTERVAL {
+            self.save()?;
+        }
+        Ok(())
+    }
+}
+
+/// Entries are stored one per line, so newlines in multi-line entries are
+/// escaped.
+fn escape(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut entry = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                chars.next();
+                entry.push('\n');
+            }
+            ('\\', Some('\\')) => {
+                chars.next();
+                entry.push('\\');
+            }
+            _ => entry.push(c),
+        }
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(lines: &[&str]) -> History {
+        let mut history = History::new(DEFAULT_LIMIT);
+        for line in lines {
+            history.add(line);
+        }
+        history
+    }
+
+    fn expand(history: &History, line: &str) -> Option<String> {
+        history.expand(line).unwrap()
+    }
+
+    fn entries(history: &History) -> Vec<&str> {
+        history.entries().map(|(_, entry)| entry).collect()
+    }
+
+    #[test]
+    fn bang_bang_is_the_last_entry() {
+        let history = history(&["first", "second"]);
+        assert_eq!(expand(&history, "!!").as_deref(), Some("second"));
+        assert!(History::new(DEFAULT_LIMIT).expand("!!").is_err());
+    }
+
+    #[test]
+    fn numbers_count_from_the_start_or_back_from_the_end() {
+        let history = history(&["one", "two", "three"]);
+        assert_eq!(expand(&history, "!1").as_deref(), Some("one"));
+        assert_eq!(expand(&history, "!3").as_deref(), Some("three"));
+        assert_eq!(expand(&history, "!-1").as_deref(), Some("three"));
+        assert_eq!(expand(&history, "!-3").as_deref(), Some("one"));
+        for missing in ["!0", "!4", "!-4"] {
+            let e = history.expand(missing).unwrap_err();
+            assert_eq!(e.to_string(), format!("{missing}: event not found"));
+        }
+    }
+
+    #[test]
+    fn prefix_finds_the_most_recent_match() {
+        let history = history(&["echo a", "help", "echo b"]);
+        assert_eq!(expand(&history, "!ec").as_deref(), Some("echo b"));
+        assert_eq!(expand(&history, "!h").as_deref(), Some("help"));
+        assert!(history.expand("!nothing").is_err());
+    }
+
+    #[test]
+    fn lines_that_are_not_references_are_left_alone() {
+        let history = history(&["echo a"]);
+        assert_eq!(expand(&history, "echo !!"), None);
+        assert_eq!(expand(&history, "!"), None);
+        assert_eq!(expand(&history, "! echo a"), None);
+    }
+
+    #[test]
+    fn dedup_keeps_the_latest_copy() {
+        let mut history = history(&["a", "b", "a", "  b  ", ""]);
+        assert_eq!(entries(&history), ["a", "b"]);
+
+        history.set_dedup(false);
+        history.add("a");
+        history.add("a");
+        assert_eq!(entries(&history), ["a", "b", "a", "a"]);
+
+        // Turning it back on removes the copies already there
+        history.set_dedup(true);
+        assert_eq!(entries(&history), ["b", "a"]);
+    }
+
+    #[test]
+    fn oldest_entries_are_dropped_past_the_limit() {
+        let mut history = History::new(DEFAULT_LIMIT);
+        for i in 0..DEFAULT_LIMIT + 5 {
+            history.add(&format!("command {i}"));
+        }
+        assert_eq!(history.len(), DEFAULT_LIMIT);
+        assert_eq!(expand(&history, "!1").as_deref(), Some("command 5"));
+
+        history.set_limit(2);
+        assert_eq!(
+            entries(&history),
+            [
+                format!("command {}", DEFAULT_LIMIT + 3),
+                format!("command {}", DEFAULT_LIMIT + 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn escaping_round_trips() {
+        for entry in ["plain", "two\nlines", r"back\slash", r"literal \n", "\\\n"] {
+            let escaped = escape(entry);
+            assert!(!escaped.contains('\n'), "{escaped:?}");
+            assert_eq!(unescape(&escaped), entry);
+        }
+    }
+
+    #[test]
+    fn multi_line_entries_survive_save_and_load() {
+        let path = std::env::temp_dir().join(format!("zenyx-history-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut saved = History::new(DEFAULT_LIMIT);
+        saved.load(path.clone()).unwrap();
+        saved.add("fn f {\n  echo \\n\n}");
+        saved.add("echo done");
+        saved.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        // Entries added before loading stay newest
+        let mut loaded = history(&["fresh"]);
+        loaded.load(path.clone()).unwrap();
+        assert_eq!(
+            entries(&loaded),
+            ["fn f {\n  echo \\n\n}", "echo done", "fresh"]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,62 +1,57 @@
 use std::{
     borrow::Cow::{self, Borrowed, Owned},
+    collections::HashSet,
     sync::Arc,
 };
 
 use chrono::Local;
 use colored::Colorize;
-use log::debug;
+use log::error;
 use parking_lot::Mutex;
-use regex::Regex;
 use rustyline::{
     Cmd, Completer, ConditionalEventHandler, Editor, Event, EventContext, EventHandler, Helper,
-    Hinter, KeyEvent, RepeatCount, Validator, completion::Completer, error::ReadlineError,
-    highlight::Highlighter, hint::HistoryHinter, history::DefaultHistory,
+    Hinter, KeyEvent, RepeatCount,
+    error::ReadlineError,
+    highlight::{CmdKind, Highlighter},
+    hint::HistoryHinter,
+    history::{DefaultHistory, History as _},
+    validate::{ValidationContext, ValidationResult, Validator},
 };
 
-use super::handler::COMMAND_MANAGER;
-use crate::core::logger::LOGGER;
-
-struct CommandCompleter;
-impl CommandCompleter {
-    fn new() -> Self {
-        CommandCompleter {}
-    }
-}
+use super::{
+    completion::CommandCompleter,
+    handler::{COMMAND_MANAGER, Exit},
+    highlight::highlight,
+    history::{HISTORY, history_path},
+    zensh::{Interpreter, parser::parse},
+};
+use crate::core::{logger::LOGGER, render::bus};
 
-impl Completer for CommandCompleter {
-    type Candidate = String;
-
-    fn complete(
-        &self,
-        line: &str,
-        pos: usize,
-        _ctx: &rustyline::Context<'_>,
-    ) -> rustyline::Result<(usize, Vec<Self::Candidate>)> {
-        let binding = COMMAND_MANAGER.read();
-        let binding = binding.get_commands();
-        let filtered_commands: Vec<_> = binding
-            .filter(|(command, _)| command.starts_with(line))
-            .collect();
-
-        let completions: Vec<String> = filtered_commands
-            .iter()
-            .filter(|(command, _)| command.starts_with(&line[..pos]))
-            .map(|(command, _)| command[pos..].to_string())
-            .collect();
-        println!("{:#?}", completions);
-        Ok((pos, completions))
-    }
-}
-#[derive(Completer, Helper, Hinter, Validator)]
+#[derive(Completer, Helper, Hinter)]
 struct MyHelper {
     #[rustyline(Hinter)]
     hinter: HistoryHinter,
     #[rustyline(Completer)]
     completer: CommandCompleter,
+    /// Functions defined in the shell so far, highlighted like commands.
+    functions: HashSet<String>,
 }
 
 impl Highlighter for MyHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        let manager = COMMAND_MANAGER.read();
+        let is_command = |name: &str| {
+            manager.commands.contains_key(name)
+                || manager.aliases.read().contains_key(name)
+                || self.functions.contains(name)
+        };
+        Owned(highlight(line, &is_command))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, kind: CmdKind) -> bool {
+        kind != CmdKind::MoveCursor
+    }
+
     fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
         &'s self,
         prompt: &'p str,
@@ -74,6 +69,17 @@ impl Highlighter for MyHelper {
     }
 }
 
+impl Validator for MyHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        // Keep reading lines while the input is merely unfinished. Any other
+        // syntax error is reported when the input runs.
+        match parse(ctx.input()) {
+            Err(e) if e.is_incomplete() => Ok(ValidationResult::Incomplete),
+            _ => Ok(ValidationResult::Valid(None)),
+        }
+    }
+}
+
 #[derive(Clone)]
 struct BacktickEventHandler {
     toggle_state: Arc<Mutex<bool>>, // Tracks whether logging is enabled or disabled
@@ -104,76 +110,35 @@ impl ConditionalEventHandler for BacktickEventHandler {
     }
 }
 
-pub fn tokenize(command: &str) -> Vec<String> {
-    let mut tokens = Vec::new();
-    let mut current_token = String::new();
-    let mut inside_string = false;
-
-    for char in command.chars() {
-        if char == '"' || char == '\'' {
-            inside_string = !inside_string;
-        } else if char.is_whitespace() && !inside_string {
-            if !current_token.is_empty() {
-                tokens.push(current_token);
-                current_token = String::new();
-            }
-        } else {
-            current_token.push(char);
-        }
-    }
-
-    // ignore the last token if it's empty. Who are we. Mojang? - Caz
-    if !current_token.is_empty() {
-        tokens.push(current_token);
+/// Mirrors the shared history into the editor, where it's used for
+/// navigating with the arrow keys and for hints.
+fn sync_history(rl: &mut Editor<MyHelper, DefaultHistory>) -> rustyline::Result<()> {
+    let history = HISTORY.lock();
+    let editor = rl.history_mut();
+    editor.clear()?;
+    editor.set_max_len(history.limit())?;
+    for (_, entry) in history.entries() {
+        editor.add(entry)?;
     }
-
-    tokens
-}
-
-pub fn parse_command(input: &str) -> anyhow::Result<Vec<String>> {
-    let pattern = Regex::new(r"[;|\n]").unwrap();
-    let commands: Vec<String> = pattern.split(input).map(String::from).collect();
-    Ok(commands)
+    Ok(())
 }
-pub fn evaluate_command(input: &str) -> anyhow::Result<()> {
-    if input.trim().is_empty() {
-        return Ok(());
-    }
-
-    let pattern = Regex::new(r"[;|\n]").unwrap();
-    let commands: Vec<&str> = pattern.split(input).collect();
-
-    for command in commands {
-        let command = command.trim();
-        if command.is_empty() {
-            println!("Empty command, skipping.");
-            continue;
-        }
 
-        let tokens = tokenize(command);
-        if tokens.is_empty() {
-            println!("Empty command, skipping.");
-            continue;
-        }
-        let cmd_name = &tokens[0];
-        let args: Option<Vec<String>> = if tokens.len() > 1 {
-            Some(tokens[1..].iter().map(|s| s.to_string()).collect())
-        } else {
-            None
-        };
-        match COMMAND_MANAGER.read().execute(cmd_name, args) {
-            Ok(_) => continue,
-            Err(e) => return Err(e),
-        }
+/// Saves what was typed, for when the REPL can't return on its own, such as
+/// after its window is closed.
+pub fn save_history() {
+    if let Err(e) = HISTORY.lock().save() {
+        error!("Failed to save history: {e:#}");
     }
-    Ok(())
 }
 
-pub async fn handle_repl() -> anyhow::Result<()> {
+/// Reads and runs lines until `exit`, CTRL+C or CTRL+D, and returns the code
+/// to exit with.
+pub async fn handle_repl(mut shell: Interpreter) -> anyhow::Result<u8> {
     let mut rl = Editor::<MyHelper, DefaultHistory>::new()?;
     rl.set_helper(Some(MyHelper {
         hinter: HistoryHinter::new(),
         completer: CommandCompleter::new(),
+        functions: HashSet::new(),
     }));
 
     rl.bind_sequence(
@@ -183,30 +148,62 @@ pub async fn handle_repl() -> anyhow::Result<()> {
         })),
     );
 
-    if rl.load_history("history.txt").is_err() {
-        debug!("No previous history.");
+    let loaded = history_path().and_then(|path| HISTORY.lock().load(path));
+    if let Err(e) = loaded {
+        error!("Failed to load history: {e:#}");
     }
+    sync_history(&mut rl)?;
 
     loop {
+        // Shown between lines, rather than over the one being typed
+        for notice in bus::take_notices() {
+            println!("{}", notice.to_string().yellow());
+        }
         let time = Local::now().format("%H:%M:%S.%3f").to_string();
         let prompt = format!("[{}/{}] {}", time, "SHELL", ">>\t");
         let sig = rl.readline(&prompt.bright_white());
 
         match sig {
             Ok(line) => {
-                rl.add_history_entry(line.as_str())?;
-                match evaluate_command(line.as_str()) {
-                    Ok(_) => continue,
-                    Err(e) => println!("{e}"),
+                let expanded = HISTORY.lock().expand(&line);
+                let line = match expanded {
+                    Ok(Some(expanded)) => {
+                        println!("{expanded}");
+                        expanded
+                    }
+                    Ok(None) => line,
+                    Err(e) => {
+                        println!("{e}");
+                        continue;
+                    }
+                };
+                HISTORY.lock().add(&line);
+
+                let result = shell.run(line.as_str());
+                sync_history(&mut rl)?;
+                if let Err(e) = HISTORY.lock().save_periodically() {
+                    error!("Failed to save history: {e:#}");
+                }
+                if let Some(helper) = rl.helper_mut() {
+                    helper.functions = shell.function_names().map(str::to_string).collect();
+                }
+                if let Err(e) = result {
+                    if let Some(code) = Exit::code(&e) {
+                        save_history();
+                        return Ok(code);
+                    }
+                    println!("{e:#}");
                 }
             }
             Err(ReadlineError::Interrupted) => {
                 println!("CTRL+C received, exiting...");
-                std::process::exit(0);
+                save_history();
+                return Ok(0);
             }
             Err(ReadlineError::Eof) => {
                 println!("Error: CTRL+D pressed. Exiting...");
-                std::process::exit(0);
+                save_history();
+                return Ok(0);
             }
             Err(err) => {
                 println!("Error: {}", err);
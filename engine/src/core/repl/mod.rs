@@ -1,18 +1,41 @@
-use commands::{ClearCommand, CounterCommand, ExecFile, ExitCommand, HelpCommand, PanicCommmand};
+use commands::{
+    AliasCommand, BenchmarkCommand, CameraCommand, ClearColorCommand, ClearCommand, CounterCommand,
+    EchoCommand, ExecFile, ExitCommand, GrepCommand, HelpCommand, HistoryCommand, PanicCommmand,
+    RedoCommand, ResizeCommand, ScreenshotCommand, SpawnCommand, UnaliasCommand, UndoCommand,
+};
 
 use crate::commands;
 
+pub mod aliases;
+pub mod args;
 pub mod commands;
+pub mod completion;
 pub mod handler;
+pub mod highlight;
+pub mod history;
 pub mod input;
+pub mod zensh;
 
 pub fn setup() {
     commands!(
         HelpCommand,
         ExecFile,
+        EchoCommand,
+        GrepCommand,
         ClearCommand,
         ExitCommand,
         CounterCommand,
-        PanicCommmand
+        PanicCommmand,
+        UndoCommand,
+        RedoCommand,
+        HistoryCommand,
+        AliasCommand,
+        UnaliasCommand,
+        ScreenshotCommand,
+        ClearColorCommand,
+        ResizeCommand,
+        SpawnCommand,
+        CameraCommand,
+        BenchmarkCommand
     );
 }
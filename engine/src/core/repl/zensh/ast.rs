@@ -0,0 +1,182 @@
+use std::{fmt, rc::Rc};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WordPart {
+    Literal(String),
+    Var(String),
+    /// `$(...)`, replaced by the output of the enclosed script.
+    CommandSubst(Script),
+}
+
+/// A single shell word, e.g. `foo`, `"hello $name"` or `$1`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+    /// Set when any part of the word was quoted. Quoted words are never
+    /// treated as keywords and always expand to exactly one argument.
+    pub quoted: bool,
+}
+
+impl Word {
+    /// A word that stands for exactly `text`, as if it had been quoted.
+    pub fn literal(text: String) -> Word {
+        Word {
+            parts: vec![WordPart::Literal(text)],
+            quoted: true,
+        }
+    }
+
+    /// Returns the word's text if it is a plain, unquoted literal.
+    pub fn as_bare(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [WordPart::Literal(text)] if !self.quoted => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        self.as_bare() == Some(keyword)
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                WordPart::Literal(text) => write!(f, "{text}")?,
+                WordPart::Var(name) => write!(f, "${{{name}}}")?,
+                WordPart::CommandSubst(_) => write!(f, "$(...)")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// There's no `>`, since that always redirects. `a > b` is written `b < a`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Ge,
+}
+
+impl fmt::Display for CompareOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Ge => ">=",
+        };
+        write!(f, "{op}")
+    }
+}
+
+pub type Block = Vec<Statement>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedirectKind {
+    /// `>`
+    Stdout,
+    /// `>>`
+    StdoutAppend,
+    /// `2>`
+    Stderr,
+    /// `2>>`
+    StderrAppend,
+}
+
+impl RedirectKind {
+    pub fn is_append(&self) -> bool {
+        matches!(
+            self,
+            RedirectKind::StdoutAppend | RedirectKind::StderrAppend
+        )
+    }
+
+    pub fn is_stderr(&self) -> bool {
+        matches!(self, RedirectKind::Stderr | RedirectKind::StderrAppend)
+    }
+}
+
+impl fmt::Display for RedirectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redirect = match self {
+            RedirectKind::Stdout => ">",
+            RedirectKind::StdoutAppend => ">>",
+            RedirectKind::Stderr => "2>",
+            RedirectKind::StderrAppend => "2>>",
+        };
+        write!(f, "{redirect}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    pub kind: RedirectKind,
+    pub target: Word,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandCall {
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+    pub line: usize,
+}
+
+/// One or more commands joined by `|`, each receiving the previous one's
+/// output as its input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    pub commands: Vec<CommandCall>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Literal(bool),
+    Pipeline(Pipeline),
+    Compare {
+        left: Word,
+        op: CompareOp,
+        right: Word,
+    },
+    Not(Box<Condition>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Pipeline(Pipeline),
+    Set {
+        name: String,
+        value: Vec<Word>,
+    },
+    Unset(String),
+    If {
+        branches: Vec<(Condition, Block)>,
+        otherwise: Option<Block>,
+    },
+    For {
+        var: String,
+        items: Vec<Word>,
+        body: Block,
+    },
+    While {
+        condition: Condition,
+        body: Block,
+    },
+    Function {
+        name: String,
+        body: Rc<Block>,
+    },
+    Break,
+    Continue,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Script {
+    pub statements: Block,
+}
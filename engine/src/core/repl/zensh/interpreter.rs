@@ -0,0 +1,693 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+    rc::Rc,
+};
+
+use anyhow::{Context, anyhow};
+use thiserror::Error;
+
+use super::{
+    ast::{
+        Block, CommandCall, CompareOp, Condition, Pipeline, Redirect, Script, Statement, Word,
+        WordPart,
+    },
+    parser::parse,
+};
+use crate::core::repl::handler::{COMMAND_MANAGER, Exit, Output};
+
+const MAX_CALL_DEPTH: usize = 64;
+
+/// Returned in place of an error whose message was already written to a
+/// `2>` target. The command still counts as failed, but doesn't abort the
+/// script.
+#[derive(Debug, Error)]
+#[error("error output was redirected")]
+struct Redirected;
+
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+}
+
+/// Runs zensh scripts. Variables and functions persist between calls to
+/// [`Interpreter::run`], so a single interpreter backs a whole REPL session.
+///
+/// Output that isn't piped into another command is printed, unless it is
+/// being captured, e.g. for a function used as a pipeline stage.
+pub struct Interpreter {
+    scopes: Vec<HashMap<String, String>>,
+    functions: HashMap<String, Rc<Block>>,
+    source_name: Option<String>,
+    depth: usize,
+    captures: Vec<Vec<String>>,
+    /// Aliases currently being expanded, which aren't expanded again so an
+    /// alias can wrap the command it is named after.
+    expanding: Vec<String>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        let mut interpreter = Interpreter {
+            scopes: vec![HashMap::new()],
+            functions: HashMap::new(),
+            source_name: None,
+            depth: 0,
+            captures: Vec::new(),
+            expanding: Vec::new(),
+        };
+        interpreter.set_status(true);
+        interpreter
+    }
+
+    /// Creates an interpreter for a script file. `args` become the
+    /// positional parameters `$1`, `$2`, ... and errors are reported with the
+    /// file name and line.
+    pub fn for_script(source_name: &str, args: Vec<String>) -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        interpreter.source_name = Some(source_name.to_string());
+        interpreter.set_args(&args);
+        interpreter
+    }
+
+    /// Sets the positional parameters `$1`, `$2`, ..., `$#` and `$@`.
+    pub fn set_args(&mut self, args: &[String]) {
+        self.scopes[0].extend(positional_vars(args));
+    }
+
+    pub fn run(&mut self, source: &str) -> Result<(), anyhow::Error> {
+        let script = parse(source).map_err(|e| match &self.source_name {
+            Some(name) => anyhow!("{}:{}", name, e.render(source)),
+            None => anyhow!("Syntax error at {}", e.render(source)),
+        })?;
+        self.exec_script(&script)
+    }
+
+    /// Runs a script file in this interpreter, so whatever it defines stays
+    /// available afterwards. Errors point into the file.
+    pub fn run_file(&mut self, path: &Path) -> Result<(), anyhow::Error> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let outer = self.source_name.replace(path.display().to_string());
+        let result = self.run(&source);
+        self.source_name = outer;
+        result
+    }
+
+    /// Like [`Interpreter::run`], but collects everything the script would
+    /// have printed. The output gathered so far is returned even on failure.
+    pub fn run_captured(&mut self, source: &str) -> (Output, Result<(), anyhow::Error>) {
+        self.captures.push(Vec::new());
+        let result = self.run(source);
+        let lines = self.captures.pop().unwrap_or_default();
+        (Output::Lines(lines), result)
+    }
+
+    pub fn exec_script(&mut self, script: &Script) -> Result<(), anyhow::Error> {
+        match self.exec_block(&script.statements)? {
+            Flow::Normal => Ok(()),
+            // The parser rejects `break` and `continue` outside of loops
+            Flow::Break | Flow::Continue => unreachable!(),
+        }
+    }
+
+    pub fn function_names(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().map(String::as_str)
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<&String> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Assigns to the innermost scope that already defines `name`, or
+    /// creates it in the current scope.
+    pub fn set_var(&mut self, name: &str, value: String) {
+        let scope = match self.scopes.iter().rposition(|s| s.contains_key(name)) {
+            Some(index) => &mut self.scopes[index],
+            None => self
+                .scopes
+                .last_mut()
+                .expect("there is always a global scope"),
+        };
+        scope.insert(name.to_string(), value);
+    }
+
+    fn unset_var(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.iter_mut().rev().find(|s| s.contains_key(name)) {
+            scope.remove(name);
+        }
+    }
+
+    fn emit(&mut self, output: Output) {
+        if output.is_none() {
+            return;
+        }
+        match self.captures.last_mut() {
+            Some(buffer) => buffer.extend(output.lines().into_iter().map(String::from)),
+            None => println!("{}", output),
+        }
+    }
+
+    fn set_status(&mut self, success: bool) {
+        self.scopes[0].insert(
+            String::from("?"),
+            String::from(if success { "0" } else { "1" }),
+        );
+    }
+
+    fn exec_block(&mut self, block: &Block) -> Result<Flow, anyhow::Error> {
+        for statement in block {
+            match self.exec_statement(statement)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn exec_statement(&mut self, statement: &Statement) -> Result<Flow, anyhow::Error> {
+        match statement {
+            Statement::Pipeline(pipeline) => {
+                let result = self.run_pipeline(pipeline, Output::None);
+                self.set_status(result.is_ok());
+                match result {
+                    Ok(output) => self.emit(output),
+                    Err(e) if e.downcast_ref::<Redirected>().is_some() => {}
+                    Err(e) => return Err(self.locate(e, pipeline.line)),
+                }
+            }
+            Statement::Set { name, value } => {
+                let value = self.expand_words(value)?.join(" ");
+                self.set_var(name, value);
+            }
+            Statement::Unset(name) => self.unset_var(name),
+            Statement::If {
+                branches,
+                otherwise,
+            } => {
+                for (condition, body) in branches {
+                    if self.eval_condition(condition)? {
+                        return self.exec_block(body);
+                    }
+                }
+                if let Some(body) = otherwise {
+                    return self.exec_block(body);
+                }
+            }
+            Statement::For { var, items, body } => {
+                for item in self.expand_words(items)? {
+                    self.set_var(var, item);
+                    if let Flow::Break = self.exec_block(body)? {
+                        break;
+                    }
+                }
+            }
+            Statement::While { condition, body } => {
+                while self.eval_condition(condition)? {
+                    if let Flow::Break = self.exec_block(body)? {
+                        break;
+                    }
+                }
+            }
+            Statement::Function { name, body } => {
+                self.functions.insert(name.to_lowercase(), Rc::clone(body));
+            }
+            Statement::Break => return Ok(Flow::Break),
+            Statement::Continue => return Ok(Flow::Continue),
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn locate(&self, error: anyhow::Error, line: usize) -> anyhow::Error {
+        match &self.source_name {
+            Some(name) => error.context(format!("{}:{}", name, line)),
+            None => error,
+        }
+    }
+
+    fn eval_condition(&mut self, condition: &Condition) -> Result<bool, anyhow::Error> {
+        match condition {
+            Condition::Literal(value) => Ok(*value),
+            Condition::Not(inner) => Ok(!self.eval_condition(inner)?),
+            Condition::Pipeline(pipeline) => {
+                let result = self.run_pipeline(pipeline, Output::None);
+                self.set_status(result.is_ok());
+                match result {
+                    Ok(output) => {
+                        self.emit(output);
+                        Ok(true)
+                    }
+                    Err(e) if e.is::<Exit>() => Err(e),
+                    Err(_) => Ok(false),
+                }
+            }
+            Condition::Compare { left, op, right } => {
+                let left = self.expand_word(left)?.join(" ");
+                let right = self.expand_word(right)?.join(" ");
+                Ok(compare(&left, *op, &right))
+            }
+        }
+    }
+
+    fn run_pipeline(
+        &mut self,
+        pipeline: &Pipeline,
+        input: Output,
+    ) -> Result<Output, anyhow::Error> {
+        let mut output = input;
+        for call in &pipeline.commands {
+            output = self.call(call, output)?;
+        }
+        Ok(output)
+    }
+
+    fn call(&mut self, call: &CommandCall, input: Output) -> Result<Output, anyhow::Error> {
+        if call.redirects.is_empty() {
+            return self.invoke(call, input);
+        }
+
+        // Like a shell, open every target before running the command so
+        // that they are created or truncated even if it produces nothing.
+        let mut stdout = None;
+        let mut stderr = None;
+        for redirect in &call.redirects {
+            let file = self.open_redirect(redirect)?;
+            if redirect.kind.is_stderr() {
+                stderr = Some(file);
+            } else {
+                stdout = Some(file);
+            }
+        }
+
+        match self.invoke(call, input) {
+            Ok(output) => match stdout {
+                Some(mut file) => {
+                    if !output.is_none() {
+                        writeln!(file, "{}", output)?;
+                    }
+                    Ok(Output::None)
+                }
+                None => Ok(output),
+            },
+            Err(e) if e.is::<Exit>() => Err(e),
+            Err(e) => match stderr {
+                Some(mut file) => {
+                    writeln!(file, "{:#}", e)?;
+                    Err(Redirected.into())
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    fn open_redirect(&mut self, redirect: &Redirect) -> Result<std::fs::File, anyhow::Error> {
+        let path = self.expand_word(&redirect.target)?.join(" ");
+        let append = redirect.kind.is_append();
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&path)
+            .with_context(|| format!("Failed to open '{}' for '{}'", path, redirect.kind))
+    }
+
+    fn invoke(&mut self, call: &CommandCall, input: Output) -> Result<Output, anyhow::Error> {
+        let mut words = self.expand_words(&call.words)?.into_iter();
+        let Some(name) = words.next() else {
+            return Ok(Output::None);
+        };
+        let args: Vec<String> = words.collect();
+
+        let key = name.to_lowercase();
+        if !self.expanding.contains(&key) {
+            // Scripts run by `exec` get here under the shell's read lock
+            let expansion = COMMAND_MANAGER.read_recursive().get_alias(&key);
+            if let Some(expansion) = expansion {
+                return self.call_alias(key, &expansion, args, input);
+            }
+        }
+
+        if let Some(body) = self.functions.get(&name.to_lowercase()).cloned() {
+            return self.call_function(&name, &body, args, input);
+        }
+
+        let args = if args.is_empty() { None } else { Some(args) };
+        COMMAND_MANAGER
+            .read_recursive()
+            .execute_command(&name, args, input)
+    }
+
+    /// Runs the pipeline an alias expands to, with the call's arguments
+    /// appended to its last command and piped input going to its first.
+    fn call_alias(
+        &mut self,
+        name: String,
+        expansion: &str,
+        args: Vec<String>,
+        input: Output,
+    ) -> Result<Output, anyhow::Error> {
+        let mut pipeline =
+            parse_alias(expansion).with_context(|| format!("In alias '{}'", name))?;
+        if let Some(last) = pipeline.commands.last_mut() {
+            last.words.extend(args.into_iter().map(Word::literal));
+        }
+        self.expanding.push(name);
+        let result = self.run_pipeline(&pipeline, input);
+        self.expanding.pop();
+        result
+    }
+
+    /// Runs a user-defined function and returns what its body printed. Piped
+    /// input is available to the body as `$in`.
+    fn call_function(
+        &mut self,
+        name: &str,
+        body: &Block,
+        args: Vec<String>,
+        input: Output,
+    ) -> Result<Output, anyhow::Error> {
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(anyhow!(
+                "Maximum call depth of {} exceeded in '{}'",
+                MAX_CALL_DEPTH,
+                name
+            ));
+        }
+        let mut scope = positional_vars(&args);
+        if !input.is_none() {
+            scope.insert(String::from("in"), input.to_string());
+        }
+        self.depth += 1;
+        self.scopes.push(scope);
+        self.captures.push(Vec::new());
+        let result = self.exec_block(body);
+        let lines = self.captures.pop().unwrap_or_default();
+        self.scopes.pop();
+        self.depth -= 1;
+        result
+            .map(|_| Output::Lines(lines))
+            .with_context(|| format!("in function '{}'", name))
+    }
+
+    fn expand_words(&mut self, words: &[Word]) -> Result<Vec<String>, anyhow::Error> {
+        let mut expanded = Vec::new();
+        for word in words {
+            expanded.extend(self.expand_word(word)?);
+        }
+        Ok(expanded)
+    }
+
+    /// Expands a word into zero or more arguments. Only an unquoted `$@` or
+    /// `$(...)` on its own splits into several arguments; everything else
+    /// yields exactly one.
+    fn expand_word(&mut self, word: &Word) -> Result<Vec<String>, anyhow::Error> {
+        if let [WordPart::Var(name)] = word.parts.as_slice()
+            && name == "@"
+            && !word.quoted
+        {
+            let count: usize = self.get_var("#").map_or(Ok(0), |n| n.parse())?;
+            return (1..=count)
+                .map(|i| self.lookup(&i.to_string()).cloned())
+                .collect();
+        }
+
+        if let [WordPart::CommandSubst(script)] = word.parts.as_slice()
+            && !word.quoted
+        {
+            let output = self.substitute(script)?;
+            return Ok(output.split_whitespace().map(String::from).collect());
+        }
+
+        let mut value = String::new();
+        for part in &word.parts {
+            match part {
+                WordPart::Literal(text) => value.push_str(text),
+                WordPart::Var(name) => value.push_str(self.lookup(name)?),
+                WordPart::CommandSubst(script) => value.push_str(&self.substitute(script)?),
+            }
+        }
+        Ok(vec![value])
+    }
+
+    /// Runs the script of a `$(...)` and returns what it printed.
+    fn substitute(&mut self, script: &Script) -> Result<String, anyhow::Error> {
+        self.captures.push(Vec::new());
+        let result = self.exec_script(script);
+        let lines = self.captures.pop().unwrap_or_default();
+        result?;
+        Ok(lines.join("\n"))
+    }
+
+    fn lookup(&self, name: &str) -> Result<&String, anyhow::Error> {
+        self.get_var(name)
+            .ok_or_else(|| anyhow!("Variable '{}' is not set", name))
+    }
+}
+
+/// Parses an alias expansion, which must be a single command or pipeline.
+pub fn parse_alias(expansion: &str) -> Result<Pipeline, anyhow::Error> {
+    let script =
+        parse(expansion).map_err(|e| anyhow!("Syntax error at {}", e.render(expansion)))?;
+    match script.statements.as_slice() {
+        [Statement::Pipeline(pipeline)] => Ok(pipeline.clone()),
+        _ => Err(anyhow!(
+            "An alias must expand to a single command or pipeline"
+        )),
+    }
+}
+
+fn positional_vars(args: &[String]) -> HashMap<String, String> {
+    let mut vars: HashMap<String, String> = args
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| ((i + 1).to_string(), arg.clone()))
+        .collect();
+    vars.insert(String::from("#"), args.len().to_string());
+    vars.insert(String::from("@"), args.join(" "));
+    vars
+}
+
+/// Compares numerically when both sides are numbers, otherwise as strings.
+fn compare(left: &str, op: CompareOp, right: &str) -> bool {
+    let ordering = match (left.parse::<f64>(), right.parse::<f64>()) {
+        (Ok(l), Ok(r)) => l.partial_cmp(&r),
+        _ => Some(left.cmp(right)),
+    };
+    let Some(ordering) = ordering else {
+        return op == CompareOp::Ne;
+    };
+    match op {
+        CompareOp::Eq => ordering.is_eq(),
+        CompareOp::Ne => ordering.is_ne(),
+        CompareOp::Lt => ordering.is_lt(),
+        CompareOp::Le => ordering.is_le(),
+        CompareOp::Ge => ordering.is_ge(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Once;
+
+    use super::*;
+    use crate::{
+        commands,
+        core::repl::commands::{EchoCommand, ExitCommand, GrepCommand},
+    };
+
+    /// Runs `source` in a fresh interpreter and returns what it printed.
+    fn run(source: &str) -> (String, Result<(), anyhow::Error>) {
+        static SETUP: Once = Once::new();
+        SETUP.call_once(|| {
+            commands!(EchoCommand, ExitCommand, GrepCommand);
+        });
+        let (output, result) = Interpreter::new().run_captured(source);
+        (output.to_string(), result)
+    }
+
+    fn output(source: &str) -> String {
+        let (output, result) = run(source);
+        result.unwrap();
+        output
+    }
+
+    fn error(source: &str) -> String {
+        format!("{:#}", run(source).1.unwrap_err())
+    }
+
+    #[test]
+    fn variables_and_quoting() {
+        assert_eq!(output("set x \"a  b\"; echo $x \"[$x]\""), "a  b [a  b]");
+        assert_eq!(output("set x 1; unset x; set x 2; echo $x"), "2");
+        assert!(error("echo $missing").contains("Variable 'missing' is not set"));
+    }
+
+    #[test]
+    fn functions_get_their_own_scope() {
+        let source = "
+            set total 0
+            fn f {
+                set local inner
+                set total 1
+                echo $local $1 $#
+            }
+            f bob
+            echo $total
+            echo $local
+        ";
+        let (output, result) = run(source);
+        assert_eq!(output, "inner bob 1\n1");
+        assert!(format!("{:#}", result.unwrap_err()).contains("'local' is not set"));
+    }
+
+    #[test]
+    fn function_output_can_be_piped() {
+        assert_eq!(output("fn f { echo a; echo b }; f | grep b"), "b");
+        assert_eq!(output("fn up { echo got $in }; echo x | up"), "got x");
+        // Names are case-insensitive like commands
+        assert_eq!(output("fn Hello { echo hi }; HELLO"), "hi");
+    }
+
+    #[test]
+    fn command_substitution() {
+        assert_eq!(output("set n $(echo 3); echo $n"), "3");
+        assert_eq!(output("echo $(echo $(echo deep))"), "deep");
+        assert_eq!(output("echo \"<$(echo a b)>\""), "<a b>");
+        // A substitution that isn't quoted splits into arguments
+        assert_eq!(output("for w in $(echo a b) { echo $w }"), "a\nb");
+    }
+
+    #[test]
+    fn recursion_is_bounded() {
+        let e = error("fn f { f }; f");
+        assert!(
+            e.contains(&format!("Maximum call depth of {MAX_CALL_DEPTH} exceeded")),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn break_and_continue() {
+        let source = "
+            for i in 1 2 3 4 5 {
+                if $i == 2 { continue }
+                if $i == 4 { break }
+                echo $i
+            }
+        ";
+        assert_eq!(output(source), "1\n3");
+        assert_eq!(
+            output("set go yes; while $go == yes { echo once; set go no }"),
+            "once"
+        );
+    }
+
+    #[test]
+    fn status_of_the_last_command() {
+        assert_eq!(output("echo $?"), "0");
+        assert_eq!(output("if no_such_command { }; echo $?; echo $?"), "1\n0");
+        assert_eq!(output("if ! no_such_command { echo failed }"), "failed");
+    }
+
+    #[test]
+    fn errors_stop_the_script() {
+        let (output, result) = run("echo before\nno_such_command\necho after");
+        assert_eq!(output, "before");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn exit_stops_the_script_with_its_code() {
+        for source in [
+            "echo a; exit 3; echo b",
+            "fn f { exit 3 }; echo a; f",
+            "echo a; if exit 3 { echo b }",
+            "echo a; exit 3 2> /dev/null",
+        ] {
+            let (output, result) = run(source);
+            assert_eq!(output, "a", "{source}");
+            assert_eq!(Exit::code(&result.unwrap_err()), Some(3), "{source}");
+        }
+        assert!(error("exit 256").contains("out of range"));
+    }
+
+    #[test]
+    fn comparisons_are_numeric_when_both_sides_are_numbers() {
+        assert_eq!(output("if 9 < 10 { echo yes }"), "yes");
+        assert_eq!(output("if 9x < 10 { echo yes } else { echo no }"), "no");
+        assert_eq!(output("if 10 >= 10.0 { echo yes }"), "yes");
+        assert_eq!(output("if 1.0 == 1 { echo equal }"), "equal");
+    }
+
+    /// Registers aliases with the shared command manager. Names are unique
+    /// to each test, since tests run in parallel.
+    fn alias(definitions: &[(&str, &str)]) {
+        let manager = COMMAND_MANAGER.read();
+        for (name, expansion) in definitions {
+            manager.add_alias(name, expansion);
+        }
+    }
+
+    #[test]
+    fn aliases_take_arguments_and_input() {
+        alias(&[("t_greet", "echo hello"), ("t_find", "grep b")]);
+        assert_eq!(output("t_greet bob"), "hello bob");
+        assert_eq!(output("T_GREET"), "hello");
+        assert_eq!(output("echo abc | t_find"), "abc");
+    }
+
+    #[test]
+    fn aliases_may_use_other_aliases() {
+        alias(&[("t_hi", "t_say there"), ("t_say", "echo hi")]);
+        assert_eq!(output("t_hi you"), "hi there you");
+    }
+
+    #[test]
+    fn aliases_are_not_expanded_inside_themselves() {
+        // Wraps the function of the same name instead of recursing
+        alias(&[("t_self", "t_self wrapped")]);
+        assert_eq!(output("fn t_self { echo $@ }; t_self call"), "wrapped call");
+
+        // Each alias falls through to a command, which doesn't exist
+        alias(&[("t_ping", "t_pong"), ("t_pong", "t_ping")]);
+        assert!(
+            error("t_ping").contains("Command 't_ping' not found"),
+            "{}",
+            error("t_ping")
+        );
+    }
+
+    #[test]
+    fn aliases_expand_to_one_pipeline() {
+        alias(&[("t_two", "echo a; echo b")]);
+        assert_eq!(
+            error("t_two"),
+            "In alias 't_two': An alias must expand to a single command or pipeline"
+        );
+    }
+
+    #[test]
+    fn redirects_write_to_files() {
+        let path = std::env::temp_dir().join(format!("zensh-redirect-{}", std::process::id()));
+        let path = path.display();
+        let printed = output(&format!(
+            "echo one > {path}; echo two >> {path}; no_such_command 2> {path}.err; echo $?"
+        ));
+        assert_eq!(printed, "1");
+        assert_eq!(fs::read_to_string(path.to_string()).unwrap(), "one\ntwo\n");
+        let errors = fs::read_to_string(format!("{path}.err")).unwrap();
+        assert!(errors.contains("no_such_command"), "{errors}");
+        fs::remove_file(path.to_string()).unwrap();
+        fs::remove_file(format!("{path}.err")).unwrap();
+    }
+}
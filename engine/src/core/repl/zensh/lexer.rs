@@ -0,0 +1,599 @@
+use std::fmt;
+
+use super::{
+    SyntaxError,
+    ast::{CompareOp, RedirectKind, Script, Word, WordPart},
+    parser::parse_at,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Word(Word),
+    Op(CompareOp),
+    Redirect(RedirectKind),
+    Semi,
+    Newline,
+    Pipe,
+    LBrace,
+    RBrace,
+    Eof,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Word(word) => write!(f, "'{word}'"),
+            TokenKind::Op(op) => write!(f, "'{op}'"),
+            TokenKind::Redirect(kind) => write!(f, "'{kind}'"),
+            TokenKind::Semi => write!(f, "';'"),
+            TokenKind::Newline => write!(f, "newline"),
+            TokenKind::Pipe => write!(f, "'|'"),
+            TokenKind::LBrace => write!(f, "'{{'"),
+            TokenKind::RBrace => write!(f, "'}}'"),
+            TokenKind::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub line: usize,
+    pub column: usize,
+}
+
+pub struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Lexer {
+    pub fn new(source: &str) -> Lexer {
+        Lexer::at(source, 1, 1)
+    }
+
+    /// Creates a lexer for source that starts at the given position of some
+    /// enclosing source, so nested errors point at the right place.
+    pub fn at(source: &str, line: usize, column: usize) -> Lexer {
+        Lexer {
+            chars: source.chars().collect(),
+            pos: 0,
+            line,
+            column,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_next(&self) -> Option<char> {
+        self.chars.get(self.pos + 1).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    pub fn tokenize(mut self) -> Result<Vec<Token>, SyntaxError> {
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next_token()?;
+            let eof = token.kind == TokenKind::Eof;
+            tokens.push(token);
+            if eof {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Token, SyntaxError> {
+        while let Some(c) = self.peek() {
+            if c == '#' {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.bump();
+                }
+            } else if c == '\\' && self.peek_next() == Some('\n') {
+                // Line continuation
+                self.bump();
+                self.bump();
+            } else if c.is_whitespace() && c != '\n' {
+                self.bump();
+            } else {
+                break;
+            }
+        }
+
+        let (line, column) = (self.line, self.column);
+        let token = |kind| Token { kind, line, column };
+
+        let Some(c) = self.peek() else {
+            return Ok(token(TokenKind::Eof));
+        };
+
+        let simple = match c {
+            '\n' => Some(TokenKind::Newline),
+            ';' => Some(TokenKind::Semi),
+            '|' => Some(TokenKind::Pipe),
+            '{' => Some(TokenKind::LBrace),
+            '}' => Some(TokenKind::RBrace),
+            _ => None,
+        };
+        if let Some(kind) = simple {
+            self.bump();
+            return Ok(token(kind));
+        }
+
+        if let Some(kind) = self.redirect() {
+            return Ok(token(TokenKind::Redirect(kind)));
+        }
+
+        if let Some(op) = self.operator() {
+            return Ok(token(TokenKind::Op(op)));
+        }
+
+        Ok(token(TokenKind::Word(self.word()?)))
+    }
+
+    /// Lexes `>`, `>>`, `2>` and `2>>`, leaving `>=` to be lexed as an
+    /// operator.
+    fn redirect(&mut self) -> Option<RedirectKind> {
+        let rest = &self.chars[self.pos..];
+        let (kind, len) = if rest.starts_with(&['2', '>', '>']) {
+            (RedirectKind::StderrAppend, 3)
+        } else if rest.starts_with(&['2', '>']) {
+            (RedirectKind::Stderr, 2)
+        } else if rest.starts_with(&['>', '>']) {
+            (RedirectKind::StdoutAppend, 2)
+        } else if rest.starts_with(&['>']) && rest.get(1) != Some(&'=') {
+            (RedirectKind::Stdout, 1)
+        } else {
+            return None;
+        };
+        for _ in 0..len {
+            self.bump();
+        }
+        Some(kind)
+    }
+
+    fn operator(&mut self) -> Option<CompareOp> {
+        let (op, len) = match (self.peek()?, self.peek_next()) {
+            ('=', Some('=')) => (CompareOp::Eq, 2),
+            ('!', Some('=')) => (CompareOp::Ne, 2),
+            ('<', Some('=')) => (CompareOp::Le, 2),
+            ('>', Some('=')) => (CompareOp::Ge, 2),
+            ('<', _) => (CompareOp::Lt, 1),
+            _ => return None,
+        };
+        for _ in 0..len {
+            self.bump();
+        }
+        Some(op)
+    }
+
+    fn is_word_end(c: char) -> bool {
+        c.is_whitespace() || matches!(c, ';' | '|' | '}' | '<' | '>')
+    }
+
+    fn word(&mut self) -> Result<Word, SyntaxError> {
+        let mut word = Word::default();
+        let mut literal = String::new();
+
+        while let Some(c) = self.peek() {
+            if Self::is_word_end(c) {
+                break;
+            }
+            match c {
+                '\'' => {
+                    word.quoted = true;
+                    let (line, column) = (self.line, self.column);
+                    self.bump();
+                    loop {
+                        match self.bump() {
+                            Some('\'') => break,
+                            Some(c) => literal.push(c),
+                            None => {
+                                return Err(SyntaxError::UnterminatedQuote {
+                                    quote: '\'',
+                                    line,
+                                    column,
+                                });
+                            }
+                        }
+                    }
+                }
+                '"' => {
+                    word.quoted = true;
+                    let (line, column) = (self.line, self.column);
+                    self.bump();
+                    loop {
+                        match self.peek() {
+                            Some('"') => {
+                                self.bump();
+                                break;
+                            }
+                            Some('\\') => {
+                                self.bump();
+                                match self.bump() {
+                                    Some('n') => literal.push('\n'),
+                                    Some('t') => literal.push('\t'),
+                                    Some('r') => literal.push('\r'),
+                                    Some('0') => literal.push('\0'),
+                                    Some('\n') => {}
+                                    Some(c @ ('"' | '\\' | '$')) => literal.push(c),
+                                    // Unknown escapes are kept as written
+                                    Some(c) => {
+                                        literal.push('\\');
+                                        literal.push(c);
+                                    }
+                                    None => {
+                                        return Err(SyntaxError::UnterminatedQuote {
+                                            quote: '"',
+                                            line,
+                                            column,
+                                        });
+                                    }
+                                }
+                            }
+                            Some('$') => self.dollar(&mut word, &mut literal)?,
+                            Some(c) => {
+                                self.bump();
+                                literal.push(c);
+                            }
+                            None => {
+                                return Err(SyntaxError::UnterminatedQuote {
+                                    quote: '"',
+                                    line,
+                                    column,
+                                });
+                            }
+                        }
+                    }
+                }
+                '\\' => {
+                    let (line, column) = (self.line, self.column);
+                    self.bump();
+                    match self.bump() {
+                        Some('\n') => {}
+                        Some(c) => {
+                            // An escaped character never forms a keyword
+                            word.quoted = true;
+                            literal.push(c);
+                        }
+                        None => return Err(SyntaxError::UnterminatedEscape { line, column }),
+                    }
+                }
+                '$' => self.dollar(&mut word, &mut literal)?,
+                _ => {
+                    self.bump();
+                    literal.push(c);
+                }
+            }
+        }
+
+        if !literal.is_empty() {
+            word.parts.push(WordPart::Literal(literal));
+        }
+        Ok(word)
+    }
+
+    /// Lexes a `$name`, `${name}`, `$1`, `$?` or `$(command)` reference. A
+    /// `$` that isn't followed by a valid name is kept as a literal character.
+    fn dollar(&mut self, word: &mut Word, literal: &mut String) -> Result<(), SyntaxError> {
+        let (line, column) = (self.line, self.column);
+        self.bump();
+
+        let name = match self.peek() {
+            Some('(') => {
+                self.bump();
+                let script = self.substitution(line, column)?;
+                if !literal.is_empty() {
+                    word.parts.push(WordPart::Literal(std::mem::take(literal)));
+                }
+                word.parts.push(WordPart::CommandSubst(script));
+                return Ok(());
+            }
+            Some('{') => {
+                self.bump();
+                let mut name = String::new();
+                loop {
+                    match self.bump() {
+                        Some('}') => break,
+                        Some(c) if is_name_char(c) => name.push(c),
+                        _ => return Err(SyntaxError::UnterminatedVariable { line, column }),
+                    }
+                }
+                if name.is_empty() {
+                    return Err(SyntaxError::UnterminatedVariable { line, column });
+                }
+                name
+            }
+            Some(c @ ('?' | '#' | '@')) => {
+                self.bump();
+                c.to_string()
+            }
+            Some(c) if c.is_ascii_digit() => self.take_while(|c| c.is_ascii_digit()),
+            Some(c) if c.is_alphabetic() || c == '_' => self.take_while(is_name_char),
+            _ => {
+                literal.push('$');
+                return Ok(());
+            }
+        };
+
+        if !literal.is_empty() {
+            word.parts.push(WordPart::Literal(std::mem::take(literal)));
+        }
+        word.parts.push(WordPart::Var(name));
+        Ok(())
+    }
+
+    /// Reads up to the `)` that closes a `$(` and parses what's in between.
+    fn substitution(&mut self, line: usize, column: usize) -> Result<Script, SyntaxError> {
+        let (inner_line, inner_column) = (self.line, self.column);
+        let mut source = String::new();
+        let mut depth = 0;
+        let mut quote = None;
+
+        loop {
+            let Some(c) = self.bump() else {
+                return Err(SyntaxError::UnterminatedSubstitution { line, column });
+            };
+            match (quote, c) {
+                (Some('\''), '\'') => quote = None,
+                (Some('\''), _) => {}
+                (_, '\\') => {
+                    source.push(c);
+                    if let Some(escaped) = self.bump() {
+                        source.push(escaped);
+                    }
+                    continue;
+                }
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '\'' | '"') => quote = Some(c),
+                (None, '(') => depth += 1,
+                (None, ')') if depth == 0 => break,
+                (None, ')') => depth -= 1,
+                _ => {}
+            }
+            source.push(c);
+        }
+
+        parse_at(&source, inner_line, inner_column)
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+        let mut taken = String::new();
+        while let Some(c) = self.peek().filter(|c| pred(*c)) {
+            self.bump();
+            taken.push(c);
+        }
+        taken
+    }
+}
+
+pub fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') && chars.all(is_name_char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::repl::zensh::ast::Statement;
+
+    fn tokens(source: &str) -> Vec<Token> {
+        Lexer::new(source).tokenize().unwrap()
+    }
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        tokens(source).into_iter().map(|token| token.kind).collect()
+    }
+
+    fn word(source: &str) -> Word {
+        match kinds(source).as_slice() {
+            [TokenKind::Word(word), TokenKind::Eof] => word.clone(),
+            other => panic!("expected a single word, got {other:?}"),
+        }
+    }
+
+    fn literal(text: &str) -> WordPart {
+        WordPart::Literal(text.to_string())
+    }
+
+    fn error(source: &str) -> SyntaxError {
+        Lexer::new(source).tokenize().unwrap_err()
+    }
+
+    #[test]
+    fn punctuation_and_positions() {
+        let tokens = tokens("echo a | grep b; x\n  }");
+        let summary: Vec<_> = (tokens.iter())
+            .map(|token| (token.kind.to_string(), token.line, token.column))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("'echo'".to_string(), 1, 1),
+                ("'a'".to_string(), 1, 6),
+                ("'|'".to_string(), 1, 8),
+                ("'grep'".to_string(), 1, 10),
+                ("'b'".to_string(), 1, 15),
+                ("';'".to_string(), 1, 16),
+                ("'x'".to_string(), 1, 18),
+                ("newline".to_string(), 1, 19),
+                ("'}'".to_string(), 2, 3),
+                ("end of input".to_string(), 2, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn redirects_and_operators() {
+        assert_eq!(
+            kinds(">> 2> 2>> == != <= >= < >"),
+            [
+                TokenKind::Redirect(RedirectKind::StdoutAppend),
+                TokenKind::Redirect(RedirectKind::Stderr),
+                TokenKind::Redirect(RedirectKind::StderrAppend),
+                TokenKind::Op(CompareOp::Eq),
+                TokenKind::Op(CompareOp::Ne),
+                TokenKind::Op(CompareOp::Le),
+                TokenKind::Op(CompareOp::Ge),
+                TokenKind::Op(CompareOp::Lt),
+                TokenKind::Redirect(RedirectKind::Stdout),
+                TokenKind::Eof,
+            ]
+        );
+        // Operators end a word even without surrounding spaces
+        assert_eq!(kinds("a>b").len(), 4);
+    }
+
+    #[test]
+    fn comments_and_line_continuations_are_skipped() {
+        let tokens = tokens("echo a \\\n  b # not a word\nc");
+        let words: Vec<_> = (tokens.iter())
+            .filter_map(|token| match &token.kind {
+                TokenKind::Word(word) => Some((word.to_string(), token.line)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            words,
+            [
+                ("echo".to_string(), 1),
+                ("a".to_string(), 1),
+                ("b".to_string(), 2),
+                ("c".to_string(), 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn double_quote_escapes() {
+        let word = word(r#""a\nb\t\"\\\$x\q""#);
+        assert!(word.quoted);
+        assert_eq!(word.parts, [literal("a\nb\t\"\\$x\\q")]);
+    }
+
+    #[test]
+    fn single_quotes_are_taken_literally() {
+        assert_eq!(word(r"'$x \n'").parts, [literal(r"$x \n")]);
+    }
+
+    #[test]
+    fn variables_split_a_word_into_parts() {
+        assert_eq!(
+            word("pre${name}post$1$?").parts,
+            [
+                literal("pre"),
+                WordPart::Var("name".into()),
+                literal("post"),
+                WordPart::Var("1".into()),
+                WordPart::Var("?".into()),
+            ]
+        );
+        assert_eq!(word("cost$").parts, [literal("cost$")]);
+        assert_eq!(
+            word(r#""hi $name""#).parts,
+            [literal("hi "), WordPart::Var("name".into())]
+        );
+    }
+
+    #[test]
+    fn quoting_or_escaping_stops_a_word_being_a_keyword() {
+        assert!(word("if").is_keyword("if"));
+        assert!(!word("'if'").is_keyword("if"));
+        assert!(!word("\"if\"").is_keyword("if"));
+        assert!(!word(r"\if").is_keyword("if"));
+    }
+
+    #[test]
+    fn command_substitutions_nest() {
+        let parts = word(r#"$(echo $(echo hi) ")")"#).parts;
+        let [WordPart::CommandSubst(outer)] = parts.as_slice() else {
+            panic!("expected a substitution, got {parts:?}");
+        };
+        let [Statement::Pipeline(pipeline)] = outer.statements.as_slice() else {
+            panic!("expected a pipeline, got {outer:?}");
+        };
+        let words = &pipeline.commands[0].words;
+        assert_eq!(words.len(), 3);
+        assert!(matches!(
+            words[1].parts.as_slice(),
+            [WordPart::CommandSubst(inner)] if inner.statements.len() == 1
+        ));
+        assert_eq!(words[2].parts, [literal(")")]);
+    }
+
+    #[test]
+    fn unterminated_quotes_point_at_the_opening_quote() {
+        let e = error("echo \"abc");
+        assert_eq!(
+            e,
+            SyntaxError::UnterminatedQuote {
+                quote: '"',
+                line: 1,
+                column: 6,
+            }
+        );
+        assert!(e.is_incomplete());
+
+        let e = error("echo\n  'abc");
+        assert_eq!(e.position(), (2, 3));
+        assert!(e.is_incomplete());
+    }
+
+    #[test]
+    fn unterminated_escape_and_substitution_are_incomplete() {
+        let e = error(r"echo a\");
+        assert_eq!(e, SyntaxError::UnterminatedEscape { line: 1, column: 7 });
+        assert!(e.is_incomplete());
+
+        let e = error("echo $(echo (hi)");
+        assert_eq!(
+            e,
+            SyntaxError::UnterminatedSubstitution { line: 1, column: 6 }
+        );
+        assert!(e.is_incomplete());
+    }
+
+    #[test]
+    fn bad_variable_reference_is_not_incomplete() {
+        let e = error("echo ${na-me}");
+        assert_eq!(e, SyntaxError::UnterminatedVariable { line: 1, column: 6 });
+        assert!(!e.is_incomplete());
+        assert!(matches!(
+            error("echo ${}"),
+            SyntaxError::UnterminatedVariable { .. }
+        ));
+    }
+
+    #[test]
+    fn errors_inside_substitutions_point_into_the_outer_source() {
+        let e = error("echo\n  $(})");
+        assert_eq!(
+            e,
+            SyntaxError::Unexpected {
+                found: "'}'".into(),
+                expected: "a command",
+                line: 2,
+                column: 5,
+            }
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use thiserror::Error;
+
+pub mod ast;
+pub mod interpreter;
+pub mod lexer;
+pub mod parser;
+
+pub use interpreter::Interpreter;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum SyntaxError {
+    #[error("{line}:{column}: unterminated {quote} quote")]
+    UnterminatedQuote {
+        quote: char,
+        line: usize,
+        column: usize,
+    },
+    #[error("{line}:{column}: unterminated escape sequence")]
+    UnterminatedEscape { line: usize, column: usize },
+    #[error("{line}:{column}: unterminated variable reference")]
+    UnterminatedVariable { line: usize, column: usize },
+    #[error("{line}:{column}: unterminated command substitution")]
+    UnterminatedSubstitution { line: usize, column: usize },
+    #[error("{line}:{column}: unexpected {found}, expected {expected}")]
+    Unexpected {
+        found: String,
+        expected: &'static str,
+        line: usize,
+        column: usize,
+    },
+    #[error("{line}:{column}: unexpected end of input, expected {expected}")]
+    UnexpectedEof {
+        expected: &'static str,
+        line: usize,
+        column: usize,
+    },
+    #[error("{line}:{column}: '{keyword}' used outside of a loop")]
+    OutsideLoop {
+        keyword: &'static str,
+        line: usize,
+        column: usize,
+    },
+}
+
+impl SyntaxError {
+    /// Whether the source only failed to parse because it ended early, such
+    /// as inside a quote or an open block, so more input could complete it.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(
+            self,
+            SyntaxError::UnterminatedQuote { .. }
+                | SyntaxError::UnterminatedEscape { .. }
+                | SyntaxError::UnterminatedSubstitution { .. }
+                | SyntaxError::UnexpectedEof { .. }
+        )
+    }
+
+    pub fn position(&self) -> (usize, usize) {
+        match *self {
+            SyntaxError::UnterminatedQuote { line, column, .. }
+            | SyntaxError::UnterminatedEscape { line, column }
+            | SyntaxError::UnterminatedVariable { line, column }
+            | SyntaxError::UnterminatedSubstitution { line, column }
+            | SyntaxError::Unexpected { line, column, .. }
+            | SyntaxError::UnexpectedEof { line, column, .. }
+            | SyntaxError::OutsideLoop { line, column, .. } => (line, column),
+        }
+    }
+
+    /// Formats the error followed by the offending line of `source` with a
+    /// caret under the column it points at.
+    pub fn render(&self, source: &str) -> String {
+        let (line, column) = self.position();
+        let Some(text) = source.lines().nth(line - 1) else {
+            return self.to_string();
+        };
+        // Keep tabs so the caret lines up with the source as displayed
+        let padding: String = text
+            .chars()
+            .take(column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        format!("{}\n    {}\n    {}^", self, text, padding)
+    }
+}
@@ -0,0 +1,572 @@
+use std::rc::Rc;
+
+use super::{
+    SyntaxError,
+    ast::{Block, CommandCall, Condition, Pipeline, Redirect, Script, Statement, Word},
+    lexer::{Lexer, Token, TokenKind, is_valid_name},
+};
+
+pub fn parse(source: &str) -> Result<Script, SyntaxError> {
+    parse_at(source, 1, 1)
+}
+
+/// Parses source nested inside another script, such as the body of `$(...)`.
+pub fn parse_at(source: &str, line: usize, column: usize) -> Result<Script, SyntaxError> {
+    let tokens = Lexer::at(source, line, column).tokenize()?;
+    Parser::new(tokens).parse_script()
+}
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    loop_depth: usize,
+}
+
+impl Parser {
+    pub fn new(tokens: Vec<Token>) -> Parser {
+        Parser {
+            tokens,
+            pos: 0,
+            loop_depth: 0,
+        }
+    }
+
+    fn peek(&self) -> &Token {
+        // The lexer always terminates the stream with an Eof token
+        &self.tokens[self.pos.min(self.tokens.len() - 1)]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.peek().clone();
+        if token.kind != TokenKind::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn unexpected(token: &Token, expected: &'static str) -> SyntaxError {
+        if token.kind == TokenKind::Eof {
+            SyntaxError::UnexpectedEof {
+                expected,
+                line: token.line,
+                column: token.column,
+            }
+        } else {
+            SyntaxError::Unexpected {
+                found: token.kind.to_string(),
+                expected,
+                line: token.line,
+                column: token.column,
+            }
+        }
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.peek().kind, TokenKind::Word(word) if word.is_keyword(keyword))
+    }
+
+    fn skip_separators(&mut self) {
+        while matches!(self.peek().kind, TokenKind::Semi | TokenKind::Newline) {
+            self.next();
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek().kind == TokenKind::Newline {
+            self.next();
+        }
+    }
+
+    pub fn parse_script(&mut self) -> Result<Script, SyntaxError> {
+        let statements = self.statements()?;
+        let token = self.peek();
+        if token.kind != TokenKind::Eof {
+            return Err(Self::unexpected(token, "a command"));
+        }
+        Ok(Script { statements })
+    }
+
+    /// Parses statements up to the end of input or a closing brace, which is
+    /// left for the caller to consume.
+    fn statements(&mut self) -> Result<Block, SyntaxError> {
+        let mut statements = Vec::new();
+        loop {
+            self.skip_separators();
+            if matches!(self.peek().kind, TokenKind::Eof | TokenKind::RBrace) {
+                return Ok(statements);
+            }
+            statements.push(self.statement()?);
+            match self.peek().kind {
+                TokenKind::Semi | TokenKind::Newline | TokenKind::RBrace | TokenKind::Eof => {}
+                _ => return Err(Self::unexpected(self.peek(), "end of statement")),
+            }
+        }
+    }
+
+    fn block(&mut self) -> Result<Block, SyntaxError> {
+        self.skip_newlines();
+        let open = self.next();
+        if open.kind != TokenKind::LBrace {
+            return Err(Self::unexpected(&open, "'{'"));
+        }
+        let body = self.statements()?;
+        let close = self.next();
+        if close.kind != TokenKind::RBrace {
+            return Err(Self::unexpected(&close, "'}'"));
+        }
+        Ok(body)
+    }
+
+    fn loop_body(&mut self) -> Result<Block, SyntaxError> {
+        self.loop_depth += 1;
+        let body = self.block();
+        self.loop_depth -= 1;
+        body
+    }
+
+    fn name(&mut self, expected: &'static str) -> Result<String, SyntaxError> {
+        let token = self.next();
+        match &token.kind {
+            TokenKind::Word(word) => match word.as_bare() {
+                Some(name) if is_valid_name(name) => Ok(name.to_string()),
+                _ => Err(Self::unexpected(&token, expected)),
+            },
+            _ => Err(Self::unexpected(&token, expected)),
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement, SyntaxError> {
+        let token = self.peek().clone();
+        let TokenKind::Word(word) = &token.kind else {
+            return Err(Self::unexpected(&token, "a command"));
+        };
+
+        match word.as_bare() {
+            Some("if") => {
+                self.next();
+                self.if_statement()
+            }
+            Some("for") => {
+                self.next();
+                let var = self.name("a loop variable name")?;
+                let in_token = self.next();
+                if !matches!(&in_token.kind, TokenKind::Word(w) if w.is_keyword("in")) {
+                    return Err(Self::unexpected(&in_token, "'in'"));
+                }
+                let items = self.words()?;
+                let body = self.loop_body()?;
+                Ok(Statement::For { var, items, body })
+            }
+            Some("while") => {
+                self.next();
+                let condition = self.condition()?;
+                let body = self.loop_body()?;
+                Ok(Statement::While { condition, body })
+            }
+            Some("fn") => {
+                self.next();
+                let name = self.name("a function name")?;
+                let outer_depth = std::mem::take(&mut self.loop_depth);
+                let body = self.block();
+                self.loop_depth = outer_depth;
+                Ok(Statement::Function {
+                    name,
+                    body: Rc::new(body?),
+                })
+            }
+            Some("set") => {
+                self.next();
+                let name = self.name("a variable name")?;
+                let value = self.words()?;
+                Ok(Statement::Set { name, value })
+            }
+            Some("unset") => {
+                self.next();
+                Ok(Statement::Unset(self.name("a variable name")?))
+            }
+            Some("break") => {
+                self.next();
+                self.check_in_loop("break", &token)?;
+                Ok(Statement::Break)
+            }
+            Some("continue") => {
+                self.next();
+                self.check_in_loop("continue", &token)?;
+                Ok(Statement::Continue)
+            }
+            _ => Ok(Statement::Pipeline(self.pipeline()?)),
+        }
+    }
+
+    fn check_in_loop(&self, keyword: &'static str, token: &Token) -> Result<(), SyntaxError> {
+        if self.loop_depth == 0 {
+            return Err(SyntaxError::OutsideLoop {
+                keyword,
+                line: token.line,
+                column: token.column,
+            });
+        }
+        Ok(())
+    }
+
+    fn if_statement(&mut self) -> Result<Statement, SyntaxError> {
+        let mut branches = vec![(self.condition()?, self.block()?)];
+        let mut otherwise = None;
+
+        loop {
+            let checkpoint = self.pos;
+            self.skip_newlines();
+            if !self.peek_keyword("else") {
+                self.pos = checkpoint;
+                break;
+            }
+            self.next();
+            if self.peek_keyword("if") {
+                self.next();
+                branches.push((self.condition()?, self.block()?));
+            } else {
+                otherwise = Some(self.block()?);
+                break;
+            }
+        }
+
+        Ok(Statement::If {
+            branches,
+            otherwise,
+        })
+    }
+
+    /// Collects plain words up to the next separator or brace.
+    fn words(&mut self) -> Result<Vec<Word>, SyntaxError> {
+        let mut words = Vec::new();
+        loop {
+            match &self.peek().kind {
+                TokenKind::Word(word) => {
+                    words.push(word.clone());
+                    self.next();
+                }
+                TokenKind::Op(_) => return Err(Self::unexpected(self.peek(), "a word")),
+                _ => return Ok(words),
+            }
+        }
+    }
+
+    fn command(&mut self) -> Result<CommandCall, SyntaxError> {
+        let line = self.peek().line;
+        let mut words = Vec::new();
+        let mut redirects = Vec::new();
+        loop {
+            let kind = match &self.peek().kind {
+                TokenKind::Word(word) => {
+                    words.push(word.clone());
+                    self.next();
+                    continue;
+                }
+                TokenKind::Redirect(kind) => *kind,
+                TokenKind::Op(_) => return Err(Self::unexpected(self.peek(), "a word")),
+                _ => break,
+            };
+            self.next();
+            let target = self.operand()?;
+            redirects.push(Redirect { kind, target });
+        }
+        if words.is_empty() {
+            return Err(Self::unexpected(self.peek(), "a command"));
+        }
+        Ok(CommandCall {
+            words,
+            redirects,
+            line,
+        })
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, SyntaxError> {
+        let line = self.peek().line;
+        let mut commands = vec![self.command()?];
+        while self.peek().kind == TokenKind::Pipe {
+            self.next();
+            self.skip_newlines();
+            commands.push(self.command()?);
+        }
+        Ok(Pipeline { commands, line })
+    }
+
+    fn condition(&mut self) -> Result<Condition, SyntaxError> {
+        if self.peek_keyword("!") {
+            self.next();
+            return Ok(Condition::Not(Box::new(self.condition()?)));
+        }
+
+        if self.at_comparison() {
+            return self.comparison();
+        }
+
+        let pipeline = self.pipeline()?;
+        if let [command] = pipeline.commands.as_slice()
+            && let [word] = command.words.as_slice()
+        {
+            if word.is_keyword("true") {
+                return Ok(Condition::Literal(true));
+            }
+            if word.is_keyword("false") {
+                return Ok(Condition::Literal(false));
+            }
+        }
+        Ok(Condition::Pipeline(pipeline))
+    }
+
+    /// Whether the condition is `left OP right`. None of the comparison
+    /// operators can follow the first word of a command, so a condition
+    /// such as `if help > out.txt {` is always a redirected pipeline.
+    fn at_comparison(&self) -> bool {
+        let mut kinds = self.tokens[self.pos..].iter().map(|token| &token.kind);
+        matches!(
+            (kinds.next(), kinds.next()),
+            (Some(TokenKind::Word(_)), Some(TokenKind::Op(_)))
+        )
+    }
+
+    fn comparison(&mut self) -> Result<Condition, SyntaxError> {
+        let left = self.operand()?;
+        let token = self.next();
+        let TokenKind::Op(op) = token.kind else {
+            return Err(Self::unexpected(&token, "a comparison operator"));
+        };
+        let right = self.operand()?;
+        Ok(Condition::Compare { left, op, right })
+    }
+
+    fn operand(&mut self) -> Result<Word, SyntaxError> {
+        let token = self.next();
+        match token.kind {
+            TokenKind::Word(word) => Ok(word),
+            _ => Err(Self::unexpected(&token, "a word")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::repl::zensh::ast::{CompareOp, RedirectKind, WordPart};
+
+    fn condition(source: &str) -> Condition {
+        let script = parse(source).unwrap();
+        match script.statements.as_slice() {
+            [Statement::If { branches, .. }] => branches[0].0.clone(),
+            other => panic!("expected an if statement, got {other:?}"),
+        }
+    }
+
+    fn error(source: &str) -> SyntaxError {
+        parse(source).unwrap_err()
+    }
+
+    fn command_names(block: &Block) -> Vec<String> {
+        (block.iter())
+            .map(|statement| match statement {
+                Statement::Pipeline(pipeline) => pipeline.commands[0].words[0].to_string(),
+                other => panic!("expected a pipeline, got {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn statements_are_split_by_newlines_and_semicolons() {
+        let script = parse(
+            "a | b 2>> log
+\nc; d\n",
+        )
+        .unwrap();
+        let [Statement::Pipeline(first), ..] = script.statements.as_slice() else {
+            panic!("expected a pipeline");
+        };
+        assert_eq!(first.commands.len(), 2);
+        assert_eq!(
+            first.commands[1].redirects[0].kind,
+            RedirectKind::StderrAppend
+        );
+        assert_eq!(command_names(&script.statements), ["a", "c", "d"]);
+    }
+
+    #[test]
+    fn if_else_chain() {
+        let script = parse("if true { a } else if ! false { b }\nelse { c }").unwrap();
+        let [
+            Statement::If {
+                branches,
+                otherwise,
+            },
+        ] = script.statements.as_slice()
+        else {
+            panic!("expected an if statement");
+        };
+        assert_eq!(branches.len(), 2);
+        assert_eq!(branches[0].0, Condition::Literal(true));
+        assert_eq!(
+            branches[1].0,
+            Condition::Not(Box::new(Condition::Literal(false)))
+        );
+        assert_eq!(command_names(&branches[1].1), ["b"]);
+        assert_eq!(command_names(otherwise.as_ref().unwrap()), ["c"]);
+    }
+
+    #[test]
+    fn loops_functions_and_variables() {
+        let script = parse(
+            "for x in a \"b c\" { continue }\nwhile true { break }\nfn Greet { echo hi }\nset v 1 2\nunset v",
+        )
+        .unwrap();
+        let [
+            Statement::For { var, items, body },
+            Statement::While { .. },
+            Statement::Function { name, .. },
+            Statement::Set { value, .. },
+            Statement::Unset(unset),
+        ] = script.statements.as_slice()
+        else {
+            panic!("unexpected statements: {:?}", script.statements);
+        };
+        assert_eq!(var, "x");
+        assert_eq!(items.len(), 2);
+        assert_eq!(body, &vec![Statement::Continue]);
+        assert_eq!(name, "Greet");
+        assert_eq!(value.len(), 2);
+        assert_eq!(unset, "v");
+    }
+
+    #[test]
+    fn quoted_keyword_is_a_command() {
+        let script = parse("'if' x").unwrap();
+        assert_eq!(command_names(&script.statements), ["if"]);
+    }
+
+    #[test]
+    fn break_outside_a_loop_is_an_error() {
+        assert_eq!(
+            error("echo a\n  break"),
+            SyntaxError::OutsideLoop {
+                keyword: "break",
+                line: 2,
+                column: 3,
+            }
+        );
+        // A function body doesn't see the loop it's defined in
+        let e = error("while true { fn f { continue } }");
+        assert_eq!(e.position(), (1, 21));
+        assert!(!e.is_incomplete());
+    }
+
+    #[test]
+    fn unexpected_tokens_are_located() {
+        assert_eq!(
+            error("echo a }"),
+            SyntaxError::Unexpected {
+                found: "'}'".into(),
+                expected: "a command",
+                line: 1,
+                column: 8,
+            }
+        );
+        let e = error("set 1x y");
+        assert_eq!(e.position(), (1, 5));
+        assert!(!e.is_incomplete());
+        // The right side of the comparison is missing
+        assert_eq!(error("if a == { }").position(), (1, 9));
+    }
+
+    #[test]
+    fn input_that_ends_early_is_incomplete() {
+        for source in [
+            "if true {",
+            "if true { a }\nelse",
+            "for x in a b",
+            "echo a |",
+            "fn f {\n  echo",
+        ] {
+            let e = error(source);
+            assert!(
+                matches!(e, SyntaxError::UnexpectedEof { .. }),
+                "{source:?}: {e:?}"
+            );
+            assert!(e.is_incomplete(), "{source:?}");
+        }
+    }
+
+    #[test]
+    fn word_op_word_is_a_comparison() {
+        let Condition::Compare { left, op, right } = condition("if 3 < $a { echo big }") else {
+            panic!("expected a comparison");
+        };
+        assert_eq!(left.as_bare(), Some("3"));
+        assert_eq!(op, CompareOp::Lt);
+        assert_eq!(right.parts, vec![WordPart::Var("a".into())]);
+    }
+
+    #[test]
+    fn gt_after_a_command_name_is_a_redirect() {
+        let Condition::Pipeline(pipeline) = condition("if help > out.txt { echo ok }") else {
+            panic!("expected a pipeline");
+        };
+        let command = &pipeline.commands[0];
+        assert_eq!(command.words.len(), 1);
+        assert_eq!(command.words[0].as_bare(), Some("help"));
+        assert_eq!(command.redirects[0].kind, RedirectKind::Stdout);
+        assert_eq!(command.redirects[0].target.as_bare(), Some("out.txt"));
+        // `>=` still compares
+        assert!(matches!(
+            condition("if $a >= 3 { }"),
+            Condition::Compare {
+                op: CompareOp::Ge,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn comparisons_take_one_word_on_each_side() {
+        assert_eq!(
+            error("if a == b c { }").to_string(),
+            "1:11: unexpected 'c', expected '{'"
+        );
+        assert_eq!(
+            error("if a == > { }").to_string(),
+            "1:9: unexpected '>', expected a word"
+        );
+    }
+
+    #[test]
+    fn comparison_may_have_its_block_on_the_next_line() {
+        assert!(matches!(
+            condition("if a == b\n{ echo same }"),
+            Condition::Compare {
+                op: CompareOp::Eq,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn longer_condition_with_gt_is_a_redirected_pipeline() {
+        let Condition::Pipeline(pipeline) = condition("if echo hi > out.txt { echo ok }") else {
+            panic!("expected a pipeline");
+        };
+        let [command] = pipeline.commands.as_slice() else {
+            panic!("expected one command");
+        };
+        let words: Vec<_> = command.words.iter().map(|w| w.as_bare()).collect();
+        assert_eq!(words, [Some("echo"), Some("hi")]);
+        assert_eq!(command.redirects.len(), 1);
+        assert_eq!(command.redirects[0].kind, RedirectKind::Stdout);
+        assert_eq!(command.redirects[0].target.as_bare(), Some("out.txt"));
+    }
+
+    #[test]
+    fn piped_condition_with_gt_is_a_pipeline() {
+        let Condition::Pipeline(pipeline) = condition("if ls | grep x > found.txt { }") else {
+            panic!("expected a pipeline");
+        };
+        assert_eq!(pipeline.commands.len(), 2);
+        assert_eq!(pipeline.commands[1].redirects[0].kind, RedirectKind::Stdout);
+    }
+}
@@ -1,35 +1,139 @@
-use core::{panic::set_panic_hook, repl::setup, splash, workspace};
+use core::{
+    cli::Cli,
+    headless::Simulation,
+    logger::LOGGER,
+    panic::set_panic_hook,
+    render::bus::{self, RenderRequest},
+    repl::{
+        aliases::load_aliases,
+        handler::Exit,
+        input::{handle_repl, save_history},
+        setup,
+        zensh::Interpreter,
+    },
+    splash,
+};
+use std::process::ExitCode;
 
+use clap::Parser;
 use colored::Colorize;
-use log::info;
+use log::{debug, error, info, warn};
 use tokio::runtime;
 use winit::event_loop::EventLoop;
 
 pub mod core;
 
+/// Size of the offscreen target drawn to when a window can't be opened.
+const FALLBACK_SIZE: (u32, u32) = (800, 600);
+
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> anyhow::Result<ExitCode> {
+    let cli = Cli::parse();
+    if log::set_logger(&*LOGGER).is_ok() {
+        log::set_max_level(cli.log_level.into());
+    }
+
     if !cfg!(debug_assertions) {
         println!("{}", "Debug mode disabled".bright_blue());
         set_panic_hook();
     }
     setup();
-    splash::print_splash();
-    info!("Type 'help' for a list of commands.");
+    if let Err(e) = load_aliases() {
+        error!("Failed to load aliases: {e:#}");
+    }
+
+    if cli.is_interactive() {
+        if !cli.no_splash {
+            splash::print_splash();
+        }
+        info!("Type 'help' for a list of commands.");
+    }
+
+    let event_loop = if cli.headless {
+        None
+    } else {
+        EventLoop::with_user_event()
+            .build()
+            .inspect_err(|e| warn!("Can't open a window ({e}), rendering offscreen instead"))
+            .ok()
+    };
+    let Some(event_loop) = event_loop else {
+        // Without an event loop the simulation gets its own thread
+        let offscreen = if cli.headless {
+            cli.offscreen
+        } else {
+            Some(FALLBACK_SIZE)
+        };
+        let simulation = Simulation::start(cli.tick_rate, offscreen);
+        let code = run_shell(&cli).await;
+        let ticks = simulation.stop();
+        debug!("Ran {ticks} simulation ticks");
+        return Ok(ExitCode::from(code));
+    };
 
-    let repl_thread = std::thread::spawn(|| {
+    let interactive = cli.is_interactive();
+    // Connected before the shell starts, so render commands in scripts, rc
+    // files and typed early wait for the window instead of failing
+    let _connection = bus::connect_event_loop(event_loop.create_proxy());
+    let shell_thread = std::thread::spawn(move || {
         let rt = runtime::Builder::new_current_thread()
             .enable_all()
             .build()
             .unwrap();
-        rt.block_on(core::repl::input::handle_repl())
+        let code = rt.block_on(run_shell(&cli));
+        // The event loop doesn't return while the window is open
+        if let Err(e) = bus::request(RenderRequest::Close) {
+            debug!("Failed to close the window: {e}");
+        }
+        code
     });
 
-    let event_loop = EventLoop::new().unwrap();
-    core::render::init_renderer(event_loop);
+    let rendered = core::render::init_renderer(event_loop);
 
-    if let Err(_) = repl_thread.join() {
-        eprintln!("REPL thread panicked");
+    // A REPL still waiting for a line when the window closes can't be told
+    // to stop, so the process ends without it once its history is saved.
+    // Scripts go on without the window, and their code is the one exited
+    // with
+    if interactive && !shell_thread.is_finished() {
+        save_history();
+        return Ok(match rendered {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(_) => ExitCode::FAILURE,
+        });
     }
-    Ok(())
+    match shell_thread.join() {
+        Ok(code) => Ok(ExitCode::from(code)),
+        Err(_) => {
+            eprintln!("Shell thread panicked");
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+/// Runs the script or commands given on the command line, or the REPL when
+/// interactive, and returns the code to exit with.
+async fn run_shell(cli: &Cli) -> u8 {
+    let result = if cli.is_interactive() {
+        run_repl(cli).await
+    } else {
+        cli.run_startup(&mut Interpreter::new()).map(|()| 0)
+    };
+    exit_code(result)
+}
+
+/// Starts the shell, running the rc files and startup commands, and reads
+/// lines into it until the REPL ends.
+async fn run_repl(cli: &Cli) -> anyhow::Result<u8> {
+    handle_repl(cli.start_shell()?).await
+}
+
+/// The code to exit the process with: the one given to `exit`, or 1 after
+/// reporting any other error.
+fn exit_code(result: anyhow::Result<u8>) -> u8 {
+    result.unwrap_or_else(|e| {
+        Exit::code(&e).unwrap_or_else(|| {
+            eprintln!("{e:#}");
+            1
+        })
+    })
 }
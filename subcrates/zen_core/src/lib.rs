@@ -1,6 +1,7 @@
 use thiserror::Error;
 
 #[derive(Debug, Error)]
+#[allow(dead_code)]
 enum ZError {
     #[error(transparent)]
     Unknown(#[from] anyhow::Error),